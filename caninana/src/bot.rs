@@ -1,42 +1,68 @@
 use rust_sc2::bot::Bot;
 use rust_sc2::prelude::*;
 
-use caninana_core::managers::army_manager::ArmyManager;
-use caninana_core::managers::cache_manager::CacheManager;
-use caninana_core::managers::defense_manager::DefenseManager;
-use caninana_core::managers::production_manager::ProductionManager;
-use caninana_core::managers::resource_manager::ResourceManager;
-use caninana_core::managers::squad_manager::SquadManager;
-use caninana_core::managers::worker_manager::WorkerManager;
-use caninana_core::units::overlord_manager::OverlordManager;
-use caninana_core::units::queen_manager::QueenManager;
-use caninana_core::units::ravager_manager::RavagerManager;
+use caninana_core::params::ParamsConfig;
+use caninana_core::pipeline::{ComponentRegistry, Pipeline, PipelineConfig};
+use caninana_core::random::GameRng;
+use caninana_core::trace::Trace;
 use caninana_core::*;
-use caninana_openings::zerg::hatch17::Hatch17;
+use caninana_openings::build_order::BuildOrder;
+use caninana_openings::selector::OpeningSelector;
+use caninana_openings::{find_opening, opening_names};
+use log::{debug, error, info};
+
+const DATA_DIR: &str = "data";
+const BUILDS_DIR: &str = "openings/builds";
+const HISTORY_DIR: &str = "data/history";
 
 #[bot]
 pub struct Caninana {
-    components: Vec<ProcessLimiter>,
+    pub opponent: String,
+    pub params_config: ParamsConfig,
+    pub pipeline: Pipeline,
     bot_state: BotState,
+    opening_name: String,
+    opening: Option<ProcessLimiter>,
+    opening_selector: Option<OpeningSelector>,
+    builds_dir: String,
 }
 
 impl Default for Caninana {
     fn default() -> Self {
         Self {
             _bot: Bot::default(),
-            components: vec![
-                ProcessLimiter::new(0, Box::new(CacheManager::default())),
-                ProcessLimiter::new(0, Box::new(SquadManager::default())),
-                ProcessLimiter::new(5, Box::new(ArmyManager::default())),
-                ProcessLimiter::new(15, Box::new(DefenseManager::default())),
-                ProcessLimiter::new(15, Box::new(ResourceManager::default())),
-                ProcessLimiter::new(5, Box::new(WorkerManager::default())),
-                ProcessLimiter::new(15, Box::new(OverlordManager::default())),
-                ProcessLimiter::new(15, Box::new(QueenManager::default())),
-                ProcessLimiter::new(15, Box::new(RavagerManager::default())),
-                ProcessLimiter::new(10, Box::new(ProductionManager::default())),
-            ],
+            opponent: "unknown".to_string(),
+            params_config: Default::default(),
+            pipeline: PipelineConfig::default()
+                .build(&ComponentRegistry::default())
+                .expect("Default pipeline uses registered components"),
             bot_state: Default::default(),
+            opening_name: String::new(),
+            opening: None,
+            opening_selector: None,
+            builds_dir: BUILDS_DIR.to_string(),
+        }
+    }
+}
+
+impl Caninana {
+    pub fn set_seed(&mut self, seed: u64) {
+        self.bot_state.rng = GameRng::new(seed);
+    }
+
+    pub fn set_trace(&mut self, trace: Trace) {
+        self.bot_state.trace = trace;
+    }
+
+    pub fn set_builds_dir(&mut self, dir: &str) {
+        self.builds_dir = dir.to_string();
+    }
+
+    fn update_params(&mut self, enemy_race: Race) {
+        // Params were validated on load, this can't fail
+        if let Ok(params) = self.params_config.params(enemy_race) {
+            debug!("Params vs {:?}: {:?}", enemy_race, params);
+            self.bot_state.params = params;
         }
     }
 }
@@ -47,30 +73,58 @@ impl Player for Caninana {
     }
 
     fn on_start(&mut self) -> SC2Result<()> {
-        let mut opening = Hatch17::default();
-        opening.opening(&self._bot, &mut self.bot_state);
+        info!("Random seed {}", self.bot_state.rng.seed());
+        self.update_params(self._bot.enemy_race);
+        let build_orders = BuildOrder::load_dir(&self.builds_dir);
+        let selector = OpeningSelector::load(DATA_DIR, &self.opponent);
+        self.opening_name = selector.select(&opening_names(&build_orders));
+        info!(
+            "Using opening {} against {}",
+            self.opening_name, self.opponent
+        );
+        if let Some(opening) = find_opening(&self.opening_name, &build_orders) {
+            let opening = OpeningComponent::new(opening);
+            self.opening = Some(ProcessLimiter::new("Opening", 0, Box::new(opening)));
+        }
+        self.opening_selector = Some(selector);
         self._bot
             .chat_ally(format!("Tag:{}v{}", crate_name!(), crate_version!()).as_str());
         Ok(())
     }
 
     fn on_step(&mut self, _iteration: usize) -> SC2Result<()> {
-        for component in self.components.iter_mut() {
-            component.process(&mut self._bot, &mut self.bot_state);
+        if let Some(opening) = self.opening.as_mut() {
+            opening.process(&mut self._bot, &mut self.bot_state);
         }
+        self.pipeline.process(&mut self._bot, &mut self.bot_state);
         Ok(())
     }
 
     /// Called once on last step with a result for your bot.
     fn on_end(&self, _result: GameResult) -> SC2Result<()> {
         println!("Result {:?}", _result);
+        info!("Component step times:\n{}", self.pipeline.report());
+        self.bot_state.trace.flush();
+        if let Some(selector) = &self.opening_selector {
+            if let Err(e) = selector.record(&self.opening_name, _result) {
+                error!("Could not save opening result: {}", e);
+            }
+        }
+        let mut summary = self.bot_state.match_summary.clone();
+        summary.finish(&self._bot, &self.opponent, &self.opening_name, _result);
+        summary.seed = self.bot_state.rng.seed();
+        match summary.save(HISTORY_DIR) {
+            Ok(path) => info!("Match summary saved to {:?}", path),
+            Err(e) => error!("Could not save match summary: {}", e),
+        }
         Ok(())
     }
 
     fn on_event(&mut self, event: Event) -> SC2Result<()> {
-        for component in self.components.iter_mut() {
-            component.on_event(&event, &mut self.bot_state);
+        if let Event::RandomRaceDetected(race) = event {
+            self.update_params(race);
         }
+        self.pipeline.on_event(&event, &mut self.bot_state);
         Ok(())
     }
 }
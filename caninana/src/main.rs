@@ -6,14 +6,20 @@
 extern crate clap;
 
 use std::ops::RangeInclusive;
+use std::path::Path;
 use std::str::FromStr;
 
 mod bot;
+mod stats;
 
 use crate::bot::Caninana;
+use caninana_core::params::ParamsConfig;
+use caninana_core::pipeline::{ComponentRegistry, Pipeline, PipelineConfig, PipelineError};
+use caninana_core::trace::{read_trace, Trace, TraceFilter};
 use clap::ArgEnum;
 use rand::prelude::SliceRandom;
-use rand::thread_rng;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 use rust_sc2::prelude::*;
 
 use crate::clap::Parser;
@@ -44,6 +50,34 @@ struct Cli {
     /// Set game step for bot
     #[clap(short = 's', long = "step", default_value_t = 1)]
     game_step: u32,
+
+    /// Params file (TOML), with optional [terran]/[zerg]/[protoss]/[random] tables
+    #[clap(long = "params")]
+    params: Option<String>,
+
+    /// Override a param (key=value or race.key=value), can be repeated
+    #[clap(long = "param", multiple_occurrences = true)]
+    param: Vec<String>,
+
+    /// Pipeline file (TOML) with the components to run, their order and delay
+    #[clap(long = "pipeline")]
+    pipeline: Option<String>,
+
+    /// Disable a component of the pipeline, can be repeated
+    #[clap(long = "disable", multiple_occurrences = true)]
+    disable: Vec<String>,
+
+    /// Seed for the bot randomness (random when not set)
+    #[clap(long = "seed")]
+    seed: Option<u64>,
+
+    /// Write army, production, resource and worker manager decisions to this file as JSON lines
+    #[clap(long = "trace")]
+    trace: Option<String>,
+
+    /// Directory with the build order files (TOML or JSON)
+    #[clap(long = "builds", default_value = "openings/builds")]
+    builds: String,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
@@ -79,6 +113,17 @@ fn port_in_range(s: &str) -> Result<(), String> {
         })
 }
 
+fn build_pipeline(path: Option<&str>, disabled: &[String]) -> Result<Pipeline, PipelineError> {
+    let mut config = match path {
+        Some(path) => PipelineConfig::load(path)?,
+        None => PipelineConfig::default(),
+    };
+    for name in disabled {
+        config.set_enabled(name, false)?;
+    }
+    config.build(&ComponentRegistry::default())
+}
+
 #[derive(Subcommand, Debug)]
 enum Command {
     /// Bot versus in-game AI
@@ -106,18 +151,103 @@ enum Command {
         /// Replay file
         save_replay: Option<String>,
     },
+    /// Win rates from the local match history
+    Stats {
+        /// Match history directory
+        #[clap(long, default_value = "data/history")]
+        dir: String,
+        /// Only games against this opponent
+        #[clap(long)]
+        opponent: Option<String>,
+    },
+    /// Decisions from a trace file written with --trace
+    Trace {
+        file: String,
+        /// Only decisions involving this unit tag
+        #[clap(long)]
+        unit: Option<u64>,
+        /// Only decisions of this component
+        #[clap(long)]
+        component: Option<String>,
+        /// Start of the time window (game seconds)
+        #[clap(long)]
+        from: Option<f32>,
+        /// End of the time window (game seconds)
+        #[clap(long)]
+        to: Option<f32>,
+    },
 }
 
 fn main() -> SC2Result<()> {
     env_logger::init();
     let app = Cli::parse();
+    if let Some(Command::Stats { dir, opponent }) = &app.command {
+        stats::print_stats(dir, opponent.as_deref());
+        return Ok(());
+    }
+    if let Some(Command::Trace {
+        file,
+        unit,
+        component,
+        from,
+        to,
+    }) = &app.command
+    {
+        let filter = TraceFilter {
+            unit: *unit,
+            component: component.clone(),
+            from: *from,
+            to: *to,
+        };
+        match read_trace(Path::new(file), &filter) {
+            Ok(entries) => entries.iter().for_each(|entry| println!("{}", entry)),
+            Err(e) => {
+                eprintln!("Could not read trace {}: {}", file, e);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
     let game_step = app.game_step;
 
+    let seed = app.seed.unwrap_or_else(rand::random);
+
     let mut bot = Caninana::default();
     bot.set_game_step(game_step);
+    bot.set_seed(seed);
+    bot.set_builds_dir(&app.builds);
+    bot.params_config = match ParamsConfig::load(app.params.as_deref(), &app.param) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    match build_pipeline(app.pipeline.as_deref(), &app.disable) {
+        Ok(pipeline) => bot.pipeline = pipeline,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+    if let Some(path) = &app.trace {
+        match Trace::open(path) {
+            Ok(trace) => bot.set_trace(trace),
+            Err(e) => {
+                eprintln!("Could not open trace {}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    }
     if let Some(race) = app.race {
         bot.race = race.into();
     }
+    bot.opponent = match (&app.opponent, &app.command) {
+        (Some(opponent), _) => opponent.clone(),
+        (None, Some(Command::Local { .. })) => "computer".to_string(),
+        (None, Some(Command::Human { .. })) => "human".to_string(),
+        (None, _) => "unknown".to_string(),
+    };
 
     const LADDER_MAPS: &[&str] = &[
         "2000AtmospheresAIE",
@@ -127,7 +257,7 @@ fn main() -> SC2Result<()> {
         "GlitteringAshesAIE",
         "HardwireAIE",
     ];
-    let mut rng = thread_rng();
+    let mut rng = StdRng::seed_from_u64(seed);
 
     match &app.command {
         Some(Command::Local {
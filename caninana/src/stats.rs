@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::fs;
+
+use caninana_core::managers::summary_manager::MatchSummary;
+use log::warn;
+use rust_sc2::prelude::*;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Record {
+    wins: usize,
+    losses: usize,
+    ties: usize,
+}
+
+impl Record {
+    fn add(&mut self, result: &Option<GameResult>) {
+        match result {
+            Some(GameResult::Victory) => self.wins += 1,
+            Some(GameResult::Defeat) => self.losses += 1,
+            Some(GameResult::Tie) => self.ties += 1,
+            _ => {}
+        }
+    }
+
+    fn games(&self) -> usize {
+        self.wins + self.losses + self.ties
+    }
+
+    fn win_rate(&self) -> f32 {
+        if self.games() == 0 {
+            0f32
+        } else {
+            self.wins as f32 * 100f32 / self.games() as f32
+        }
+    }
+}
+
+pub fn load_history(dir: &str) -> Vec<MatchSummary> {
+    let mut result = vec![];
+    if let Ok(entries) = fs::read_dir(dir) {
+        for path in entries.flatten().map(|e| e.path()) {
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            match MatchSummary::load(&path) {
+                Ok(summary) => result.push(summary),
+                Err(e) => warn!("Skipping match summary {:?}: {}", path, e),
+            }
+        }
+    }
+    result.sort_by_key(|s| s.timestamp);
+    result
+}
+
+// Worst results first, same as arena_history.md
+fn table<F>(title: &str, games: &[MatchSummary], key: F) -> String
+where
+    F: Fn(&MatchSummary) -> String,
+{
+    let mut records: HashMap<String, Record> = HashMap::new();
+    for game in games {
+        records.entry(key(game)).or_default().add(&game.result);
+    }
+    let mut records = records.into_iter().collect::<Vec<_>>();
+    records.sort_by(|(a_name, a), (b_name, b)| {
+        a.win_rate()
+            .partial_cmp(&b.win_rate())
+            .unwrap()
+            .then(b.games().cmp(&a.games()))
+            .then(a_name.cmp(b_name))
+    });
+    let mut lines = vec![
+        format!(
+            "| {:<18} | {:<5} | {:<4} | {:<6} | {:<4} | {:<8} |",
+            title, "Games", "Wins", "Losses", "Ties", "Win rate"
+        ),
+        "|--------------------|-------|------|--------|------|----------|".to_string(),
+    ];
+    for (name, record) in records {
+        lines.push(format!(
+            "| {:<18} | {:<5} | {:<4} | {:<6} | {:<4} | {:>7.2}% |",
+            name,
+            record.games(),
+            record.wins,
+            record.losses,
+            record.ties,
+            record.win_rate()
+        ));
+    }
+    lines.join("\n")
+}
+
+pub fn report(games: &[MatchSummary]) -> String {
+    let mut total = Record::default();
+    for game in games {
+        total.add(&game.result);
+    }
+    [
+        format!(
+            "{:.2}% win rate after {} games",
+            total.win_rate(),
+            total.games()
+        ),
+        table("Opponent", games, |g| g.opponent.clone()),
+        table("Map", games, |g| g.map.clone()),
+        table("Enemy race", games, |g| {
+            g.enemy_race.map(|r| format!("{:?}", r)).unwrap_or_default()
+        }),
+        table("Opening", games, |g| g.opening.clone()),
+    ]
+    .join("\n\n")
+}
+
+pub fn print_stats(dir: &str, opponent: Option<&str>) {
+    let games = load_history(dir)
+        .into_iter()
+        .filter(|g| opponent.map_or(true, |o| g.opponent == o))
+        .collect::<Vec<_>>();
+    if games.is_empty() {
+        println!("No games found in {}", dir);
+    } else {
+        println!("{}", report(&games));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use caninana_core::managers::summary_manager::MatchSummary;
+    use rust_sc2::prelude::*;
+
+    use crate::stats::report;
+
+    fn game(opponent: &str, result: GameResult) -> MatchSummary {
+        MatchSummary {
+            opponent: opponent.to_string(),
+            map: "BlackburnAIE".to_string(),
+            enemy_race: Some(Race::Protoss),
+            opening: "Hatch17".to_string(),
+            result: Some(result),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn worst_opponent_first() {
+        let report = report(&[
+            game("SharpenedEdge", GameResult::Defeat),
+            game("MechaShark", GameResult::Victory),
+            game("MechaShark", GameResult::Defeat),
+        ]);
+        assert!(report.starts_with("33.33% win rate after 3 games"));
+        let sharpened = report.find("| SharpenedEdge").unwrap();
+        let mecha = report.find("| MechaShark").unwrap();
+        assert!(sharpened < mecha);
+        assert!(report.contains("| Protoss            | 3     | 1    | 2      | 0    |   33.33% |"));
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::VecDeque;
+
+use rust_sc2::prelude::*;
+use serde::{Deserialize, Serialize};
+
+// What managers tell each other, instead of sharing flags in BotState.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Message {
+    WorkerRushDetected,
+    // Townhall tag, sent every army step while enemies are close
+    BaseUnderAttack(u64),
+    ExpansionStarted(Point2),
+    // The active plan was dropped, unit unlocks should be decided again
+    TechSwitchRequested,
+    // A drone walks to a future building spot and shouldn't be sent mining
+    BuilderSent(u64),
+    BuilderReleased(u64),
+    // The game rejected a command of one of our units, reason is the action result
+    ActionFailed {
+        unit: u64,
+        ability: AbilityId,
+        reason: String,
+    },
+    // One of our units changed type keeping its tag
+    UnitMorphed {
+        tag: u64,
+        from: UnitTypeId,
+        to: UnitTypeId,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Envelope {
+    id: u64,
+    game_loop: u32,
+    message: Message,
+}
+
+// Read position of a component on the bus, messages published after the last
+// read are received once.
+#[derive(Debug, Default, Clone)]
+pub struct Subscription {
+    next_id: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct MessageBus {
+    messages: VecDeque<Envelope>,
+    next_id: u64,
+    game_loop: u32,
+}
+
+impl MessageBus {
+    // Components run every few loops, messages are kept long enough for all of them.
+    const RETENTION_LOOPS: u32 = 224;
+
+    pub fn start_step(&mut self, game_loop: u32) {
+        self.game_loop = game_loop;
+        while self
+            .messages
+            .front()
+            .map_or(false, |e| e.game_loop + Self::RETENTION_LOOPS < game_loop)
+        {
+            self.messages.pop_front();
+        }
+    }
+
+    pub fn publish(&mut self, message: Message) {
+        self.messages.push_back(Envelope {
+            id: self.next_id,
+            game_loop: self.game_loop,
+            message,
+        });
+        self.next_id += 1;
+    }
+
+    pub fn receive(&self, subscription: &mut Subscription) -> Vec<Message> {
+        let result = self
+            .messages
+            .iter()
+            .filter(|e| e.id >= subscription.next_id)
+            .map(|e| e.message.clone())
+            .collect();
+        subscription.next_id = self.next_id;
+        result
+    }
+
+    // Everything still on the bus, oldest first
+    pub fn messages(&self) -> impl Iterator<Item = &Message> {
+        self.messages.iter().map(|e| &e.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::{Message, MessageBus, Subscription};
+
+    #[test]
+    fn messages_received_once() {
+        let mut bus = MessageBus::default();
+        let mut subscription = Subscription::default();
+        bus.start_step(10);
+        bus.publish(Message::WorkerRushDetected);
+        bus.publish(Message::BaseUnderAttack(7));
+        assert_eq!(
+            bus.receive(&mut subscription),
+            vec![Message::WorkerRushDetected, Message::BaseUnderAttack(7)]
+        );
+        assert!(bus.receive(&mut subscription).is_empty());
+
+        let mut late = Subscription::default();
+        bus.start_step(15);
+        bus.publish(Message::TechSwitchRequested);
+        assert_eq!(
+            bus.receive(&mut subscription),
+            vec![Message::TechSwitchRequested]
+        );
+        assert_eq!(bus.receive(&mut late).len(), 3);
+    }
+
+    #[test]
+    fn old_messages_dropped() {
+        let mut bus = MessageBus::default();
+        let mut subscription = Subscription::default();
+        bus.start_step(10);
+        bus.publish(Message::WorkerRushDetected);
+        bus.start_step(1_000);
+        bus.publish(Message::TechSwitchRequested);
+        assert_eq!(
+            bus.receive(&mut subscription),
+            vec![Message::TechSwitchRequested]
+        );
+    }
+}
@@ -1,6 +1,11 @@
 use rust_sc2::bot::Bot;
 use rust_sc2::prelude::*;
 
+use crate::economy::Economy;
+use crate::game_view::{GameView, ResourceCost};
+use crate::reservation::Funds;
+use crate::utils::ProducedOn;
+
 #[derive(Debug, Clone)]
 struct BlockedElement {
     pub element: PriorityElement,
@@ -33,6 +38,11 @@ pub enum Command {
         upgrade: UpgradeId,
         save_resources: bool,
     },
+    // In-base townhalls for larva, never placed at an expansion
+    MacroHatchCommand {
+        wanted_amount: usize,
+        save_resources: bool,
+    },
 }
 
 impl Command {
@@ -50,6 +60,31 @@ impl Command {
             save_resources,
         }
     }
+
+    pub fn new_macro_hatch(wanted_amount: usize, save_resources: bool) -> Self {
+        Command::MacroHatchCommand {
+            wanted_amount,
+            save_resources,
+        }
+    }
+
+    pub fn is_complete<G: GameView>(&self, game: &G) -> bool {
+        match self {
+            Command::UnitCommand {
+                unit_type,
+                wanted_amount,
+                save_resources: _,
+            } => game.count_alias(*unit_type) >= *wanted_amount,
+            Command::UpgradeCommand {
+                upgrade,
+                save_resources: _,
+            } => game.has_upgrade(*upgrade),
+            Command::MacroHatchCommand {
+                wanted_amount,
+                save_resources: _,
+            } => game.macro_townhalls().len() >= *wanted_amount,
+        }
+    }
 }
 
 pub struct CommandQueueIter {
@@ -70,18 +105,53 @@ impl CommandQueue {
         }
     }
 
-    pub fn check_completion(&mut self, bot: &Bot) {
-        self.queue.retain(|x| match x.element.command {
-            Command::UnitCommand {
-                unit_type,
-                wanted_amount,
-                save_resources: _,
-            } => bot.counter().alias().count(unit_type) < wanted_amount,
-            Command::UpgradeCommand {
-                upgrade,
-                save_resources: _,
-            } => !bot.has_upgrade(upgrade),
-        });
+    pub fn check_completion<G: GameView>(&mut self, game: &G) {
+        self.queue.retain(|x| !x.element.command.is_complete(game));
+    }
+
+    // Seconds until each pending entry could start at the current income, paying
+    // for the entries ahead of it first. None when it won't be affordable.
+    pub fn time_to_afford<G: GameView>(
+        &self,
+        game: &G,
+        economy: &Economy,
+        available: Funds,
+    ) -> Vec<(PriorityElement, Option<f32>)> {
+        let mut needed = ResourceCost::default();
+        let mut larva = 0;
+        let mut result = vec![];
+        for item in self
+            .queue
+            .iter()
+            .filter(|x| !x.element.command.is_complete(game))
+        {
+            let cost = match item.element.command {
+                Command::UnitCommand { unit_type, .. } => {
+                    if unit_type.produced_on().contains(&UnitTypeId::Larva) {
+                        larva += 1;
+                    }
+                    game.unit_cost(unit_type)
+                }
+                Command::UpgradeCommand { upgrade, .. } => Some(game.upgrade_cost(upgrade)),
+                Command::MacroHatchCommand { .. } => game.unit_cost(game.townhall_type()),
+            };
+            let estimate = cost.and_then(|cost| {
+                needed.minerals += cost.minerals;
+                needed.vespene += cost.vespene;
+                economy.time_to_afford(available, &needed, larva)
+            });
+            result.push((item.element.clone(), estimate));
+        }
+        result
+    }
+
+    pub fn has_blocked(&self) -> bool {
+        self.queue.iter().any(|x| x.blocked)
+    }
+
+    pub fn cancel(&mut self, command: &Command, priority: usize) {
+        self.queue
+            .retain(|x| x.element.command != *command || x.element.priority != priority);
     }
 
     pub fn push(&mut self, command: Command, blocked: bool, priority: usize) {
@@ -108,6 +178,9 @@ impl CommandQueue {
                 } => *upgrade == new_upgrade,
                 _ => false,
             },
+            Command::MacroHatchCommand { .. } => {
+                matches!(command, Command::MacroHatchCommand { .. })
+            }
         });
         if let Some(previous_command_index) = replace_previous_command {
             self.queue.remove(previous_command_index);
@@ -155,6 +228,10 @@ mod tests {
     use rust_sc2::prelude::*;
 
     use crate::command_queue::Command;
+    use crate::economy::Economy;
+    use crate::fake_game::FakeGame;
+    use crate::game_view::UnitSnapshot;
+    use crate::reservation::Funds;
     use crate::CommandQueue;
 
     #[test]
@@ -219,6 +296,19 @@ mod tests {
         assert_eq!(next.clone().unwrap().command, expected);
     }
 
+    #[test]
+    fn cancel_only_matching_priority() {
+        let mut queue = CommandQueue::default();
+        let command1 = Command::new_unit(UnitTypeId::Zergling, 10, false);
+        let command2 = Command::new_unit(UnitTypeId::Zergling, 10, false);
+        queue.push(command1.clone(), true, 5);
+        queue.push(command2, true, 3);
+        queue.cancel(&command1, 5);
+        let mut iter = queue.into_iter();
+        assert_eq!(iter.next().unwrap().priority, 3);
+        assert_eq!(iter.next().is_some(), false);
+    }
+
     #[test]
     fn iterator_blocked_check() {
         let mut queue = CommandQueue::default();
@@ -232,4 +322,40 @@ mod tests {
         assert_eq!(next.is_some(), true);
         assert_eq!(next2.is_some(), true);
     }
+
+    #[test]
+    fn estimates_include_entries_ahead() {
+        let mut game = FakeGame::default();
+        let mut economy = Economy::default();
+        economy.sample(&game);
+        game.time = 10f32;
+        game.collected_minerals = 100;
+        game.units
+            .push(UnitSnapshot::new(1, UnitTypeId::Larva, game.start_location));
+        economy.sample(&game);
+        let mut queue = CommandQueue::default();
+        queue.push(Command::new_unit(UnitTypeId::Overlord, 1, true), false, 20);
+        queue.push(Command::new_unit(UnitTypeId::Drone, 1, false), false, 10);
+        queue.push(Command::new_unit(UnitTypeId::Roach, 1, false), false, 5);
+        let available = Funds {
+            minerals: 50,
+            larva: 1,
+            ..Default::default()
+        };
+        let estimates = queue
+            .time_to_afford(&game, &economy, available)
+            .into_iter()
+            .map(|(_, estimate)| estimate)
+            .collect::<Vec<_>>();
+        assert_eq!(estimates, vec![Some(5f32), Some(10f32), None]);
+    }
+
+    #[test]
+    fn blocked_commands_tracked() {
+        let mut queue = CommandQueue::default();
+        queue.push(Command::new_unit(UnitTypeId::Drone, 13, true), false, 10);
+        assert!(!queue.has_blocked());
+        queue.push(Command::new_unit(UnitTypeId::Overlord, 2, true), true, 20);
+        assert!(queue.has_blocked());
+    }
 }
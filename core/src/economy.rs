@@ -0,0 +1,167 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::game_view::{GameView, ResourceCost};
+use crate::reservation::Funds;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Sample {
+    time: f32,
+    minerals: u32,
+    vespene: u32,
+    larva: u32,
+}
+
+// Mineral and gas income and larva generation, measured over sliding windows of
+// game time.
+#[derive(Debug, Default)]
+pub struct Economy {
+    samples: VecDeque<Sample>,
+    larva_seen: HashSet<u64>,
+    larva_created: u32,
+}
+
+impl Economy {
+    const INCOME_WINDOW: f32 = 20f32;
+    const LARVA_WINDOW: f32 = 60f32;
+
+    pub fn sample<G: GameView>(&mut self, game: &G) {
+        let time = game.time();
+        if self.samples.back().map_or(false, |s| s.time >= time) {
+            return;
+        }
+        let larvas = game
+            .my_larvas()
+            .iter()
+            .map(|l| l.tag)
+            .collect::<HashSet<u64>>();
+        // Larva already there on the first sample wasn't generated in the window
+        if !self.samples.is_empty() {
+            self.larva_created += larvas.difference(&self.larva_seen).count() as u32;
+        }
+        self.larva_seen = larvas;
+        self.samples.push_back(Sample {
+            time,
+            minerals: game.collected_minerals(),
+            vespene: game.collected_vespene(),
+            larva: self.larva_created,
+        });
+        let window_start = time - Self::INCOME_WINDOW.max(Self::LARVA_WINDOW);
+        while self.samples.len() > 1 && self.samples[1].time <= window_start {
+            self.samples.pop_front();
+        }
+    }
+
+    // Per second
+    fn rate(&self, window: f32, value: impl Fn(&Sample) -> u32) -> f32 {
+        if let Some(last) = self.samples.back() {
+            if let Some(first) = self
+                .samples
+                .iter()
+                .find(|s| s.time >= last.time - window && s.time < last.time)
+            {
+                return value(last).saturating_sub(value(first)) as f32 / (last.time - first.time);
+            }
+        }
+        0f32
+    }
+
+    pub fn mineral_income(&self) -> f32 {
+        self.rate(Self::INCOME_WINDOW, |s| s.minerals)
+    }
+
+    pub fn vespene_income(&self) -> f32 {
+        self.rate(Self::INCOME_WINDOW, |s| s.vespene)
+    }
+
+    pub fn larva_rate(&self) -> f32 {
+        self.rate(Self::LARVA_WINDOW, |s| s.larva)
+    }
+
+    // Larva per second needed to spend the mineral income
+    pub fn larva_demand(&self, minerals_per_larva: f32) -> f32 {
+        self.mineral_income() / minerals_per_larva
+    }
+
+    // Seconds until the funds cover the cost at the current rates, None when
+    // something missing isn't coming in. Supply is left to the supply logic.
+    pub fn time_to_afford(&self, available: Funds, cost: &ResourceCost, larva: u32) -> Option<f32> {
+        let wait = |needed: u32, have: u32, rate: f32| {
+            let missing = needed.saturating_sub(have);
+            if missing == 0 {
+                Some(0f32)
+            } else if rate > 0f32 {
+                Some(missing as f32 / rate)
+            } else {
+                None
+            }
+        };
+        let minerals = wait(cost.minerals, available.minerals, self.mineral_income())?;
+        let vespene = wait(cost.vespene, available.vespene, self.vespene_income())?;
+        let larva = wait(larva, available.larva, self.larva_rate())?;
+        Some(minerals.max(vespene).max(larva))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_sc2::prelude::*;
+
+    use crate::economy::Economy;
+    use crate::fake_game::FakeGame;
+    use crate::game_view::{ResourceCost, UnitSnapshot};
+    use crate::reservation::Funds;
+
+    fn step(economy: &mut Economy, game: &mut FakeGame, seconds: f32, larva: u64) {
+        game.time += seconds;
+        game.collected_minerals += (seconds * 10f32) as u32;
+        game.collected_vespene += (seconds * 2f32) as u32;
+        game.units.push(UnitSnapshot::new(
+            larva,
+            UnitTypeId::Larva,
+            game.start_location,
+        ));
+        economy.sample(game);
+    }
+
+    #[test]
+    fn income_over_window() {
+        let mut game = FakeGame::default();
+        let mut economy = Economy::default();
+        economy.sample(&game);
+        assert_eq!(economy.mineral_income(), 0f32);
+        for larva in 1..=10 {
+            step(&mut economy, &mut game, 10f32, larva);
+        }
+        assert_eq!(economy.mineral_income(), 10f32);
+        assert_eq!(economy.vespene_income(), 2f32);
+        assert_eq!(economy.larva_rate(), 0.1f32);
+        assert_eq!(economy.larva_demand(50f32), 0.2f32);
+        // Only the last window counts
+        game.time += 10f32;
+        economy.sample(&game);
+        assert_eq!(economy.mineral_income(), 5f32);
+    }
+
+    #[test]
+    fn time_to_afford_waits_for_slowest_resource() {
+        let mut game = FakeGame::default();
+        let mut economy = Economy::default();
+        economy.sample(&game);
+        step(&mut economy, &mut game, 10f32, 1);
+        let available = Funds {
+            minerals: 25,
+            vespene: 5,
+            ..Default::default()
+        };
+        let roach = ResourceCost::new(75, 25, 2f32);
+        assert_eq!(economy.time_to_afford(available, &roach, 0), Some(10f32));
+        assert_eq!(economy.time_to_afford(available, &roach, 1), Some(10f32));
+        assert_eq!(economy.time_to_afford(available, &roach, 2), Some(20f32));
+        let idle = Economy::default();
+        assert_eq!(idle.time_to_afford(available, &roach, 0), None);
+        assert_eq!(
+            idle.time_to_afford(available, &ResourceCost::new(25, 0, 0f32), 0),
+            Some(0f32)
+        );
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+
+use rust_sc2::prelude::*;
+
+use crate::game_view::{GameView, UnitSnapshot};
+use crate::params::Params;
+use crate::utils::SegmentDistance;
+
+// Strongest enemy force seen around each expansion, fading out over time, and
+// the enemy army strength on the last record.
+#[derive(Debug, Default)]
+pub struct ThreatHistory {
+    threats: HashMap<Point2, (f32, f32)>,
+    army_strength: f32,
+}
+
+impl ThreatHistory {
+    const MEMORY: f32 = 120f32;
+    const RANGE: f32 = 15f32;
+
+    pub fn record<G: GameView>(&mut self, game: &G, enemies: &[UnitSnapshot]) {
+        let time = game.time();
+        self.army_strength = game.strength(
+            enemies
+                .iter()
+                .filter(|u| !u.is_worker() && !u.is_structure()),
+        );
+        for expansion in game.expansions().iter() {
+            let strength = game.strength(enemies.iter().filter(|u| {
+                !u.is_worker()
+                    && (!u.is_structure() || u.can_attack_ground)
+                    && u.distance(expansion.loc) < Self::RANGE
+            }));
+            if strength > 0f32 && strength >= self.threat(expansion.loc, time) {
+                self.threats.insert(expansion.loc, (strength, time));
+            }
+        }
+        self.threats
+            .retain(|_, (_, seen)| time - *seen < Self::MEMORY);
+    }
+
+    pub fn threat(&self, location: Point2, time: f32) -> f32 {
+        self.threats
+            .get(&location)
+            .map_or(0f32, |(strength, seen)| {
+                strength * (1f32 - (time - seen) / Self::MEMORY).max(0f32)
+            })
+    }
+
+    // Threat on the expansions around a position
+    pub fn threat_around(&self, position: Point2, time: f32) -> f32 {
+        self.threats
+            .keys()
+            .filter(|loc| loc.distance(position) < Self::RANGE)
+            .map(|loc| self.threat(*loc, time))
+            .fold(0f32, f32::max)
+    }
+
+    pub fn enemy_army_strength(&self) -> f32 {
+        self.army_strength
+    }
+}
+
+// Unscouted resources are assumed untouched
+const MINERAL_FIELD_DEFAULT: u32 = 1500;
+const GEYSER_DEFAULT: u32 = 2250;
+const RESOURCE_RANGE: f32 = 12f32;
+
+fn resources_at<G: GameView>(game: &G, location: Point2) -> u32 {
+    let minerals: u32 = game
+        .mineral_fields()
+        .iter()
+        .filter(|f| f.distance(location) < RESOURCE_RANGE)
+        .map(|f| match f.resources_left() {
+            0 => MINERAL_FIELD_DEFAULT,
+            left => left,
+        })
+        .sum();
+    let vespene: u32 = game
+        .vespene_geysers()
+        .iter()
+        .filter(|g| g.distance(location) < RESOURCE_RANGE)
+        .map(|g| match g.resources_left() {
+            0 => GEYSER_DEFAULT,
+            left => left,
+        })
+        .sum();
+    minerals + vespene
+}
+
+// Ground distances from an origin to every expansion. Expansions don't move,
+// so each origin is queried once in a single batch.
+#[derive(Debug, Default)]
+pub struct ExpansionDistances {
+    distances: HashMap<Point2, HashMap<Point2, Option<f32>>>,
+}
+
+impl ExpansionDistances {
+    pub fn from_origin<G: GameView>(
+        &mut self,
+        game: &G,
+        origin: Point2,
+        locations: &[Point2],
+    ) -> &HashMap<Point2, Option<f32>> {
+        let known = self.distances.entry(origin).or_default();
+        let paths = locations
+            .iter()
+            .filter(|l| !known.contains_key(l))
+            .map(|l| (origin, *l))
+            .collect::<Vec<_>>();
+        if !paths.is_empty() {
+            for ((_, location), distance) in paths.iter().zip(game.ground_distances(&paths)) {
+                known.insert(*location, distance);
+            }
+        }
+        known
+    }
+}
+
+// Free expansions reachable by ground with both geysers, best first. Hidden
+// bases stay away from the enemy and off the path between the mains instead
+// of close to home.
+pub fn rank_expansions<G: GameView>(
+    game: &G,
+    threats: &ThreatHistory,
+    distances: &mut ExpansionDistances,
+    params: &Params,
+    hidden: bool,
+) -> Vec<Point2> {
+    let time = game.time();
+    let main = game.start_location();
+    let locations = game.expansions().iter().map(|e| e.loc).collect::<Vec<_>>();
+    let from_main = distances.from_origin(game, main, &locations).clone();
+    let natural = game
+        .owned_expansions()
+        .iter()
+        .filter(|e| e.loc.distance(main) > RESOURCE_RANGE)
+        .filter_map(|e| from_main.get(&e.loc).copied().flatten().map(|d| (d, e.loc)))
+        .min_by(|(a, _), (b, _)| a.total_cmp(b))
+        .map_or(main, |(_, loc)| loc);
+    let from_natural = distances.from_origin(game, natural, &locations);
+    let enemy_bases = game
+        .enemy_townhalls()
+        .iter()
+        .map(|u| u.position)
+        .chain(game.enemy_expansions().iter().map(|e| e.loc))
+        .chain(std::iter::once(game.enemy_start()))
+        .collect::<Vec<_>>();
+    let mut scored = game
+        .free_expansions()
+        .iter()
+        .filter(|e| e.geysers > 1)
+        .filter_map(|e| {
+            let from_main = from_main.get(&e.loc).copied().flatten()?;
+            let from_natural = from_natural
+                .get(&e.loc)
+                .copied()
+                .flatten()
+                .unwrap_or(from_main);
+            let from_enemy = enemy_bases
+                .iter()
+                .map(|b| b.distance(e.loc))
+                .fold(f32::MAX, f32::min);
+            let threat = threats.threat(e.loc, time) * params.expansion_threat_weight;
+            let score = if hidden {
+                threat - from_enemy - e.loc.segment_distance(main, game.enemy_start())
+            } else {
+                threat + (from_main + from_natural) / 2f32 * params.expansion_distance_weight
+                    - from_enemy * params.expansion_enemy_distance_weight
+                    - resources_at(game, e.loc) as f32 * params.expansion_resources_weight
+            };
+            Some((score, e.loc))
+        })
+        .collect::<Vec<_>>();
+    scored.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+    scored.into_iter().map(|(_, loc)| loc).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_sc2::prelude::*;
+
+    use crate::expansions::{rank_expansions, ExpansionDistances, ThreatHistory};
+    use crate::fake_game::FakeGame;
+    use crate::game_view::{BaseOwner, ExpansionSnapshot, UnitSnapshot};
+    use crate::params::Params;
+
+    fn expansion(x: f32, y: f32) -> ExpansionSnapshot {
+        ExpansionSnapshot {
+            loc: Point2::new(x, y),
+            geysers: 2,
+            owner: BaseOwner::Neutral,
+            base: None,
+        }
+    }
+
+    fn expansions_game() -> FakeGame {
+        FakeGame {
+            expansions: vec![
+                expansion(150f32, 150f32),
+                expansion(30f32, 150f32),
+                expansion(100f32, 40f32),
+                expansion(45f32, 30f32),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn close_and_safe_expansions_first() {
+        let mut game = expansions_game();
+        let params = Params::default();
+        let mut distances = ExpansionDistances::default();
+        let ranking = rank_expansions(
+            &game,
+            &ThreatHistory::default(),
+            &mut distances,
+            &params,
+            false,
+        );
+        assert_eq!(ranking[0], Point2::new(45f32, 30f32));
+        assert_eq!(ranking[3], Point2::new(150f32, 150f32));
+
+        let mut threats = ThreatHistory::default();
+        game.enemies = (0..10)
+            .map(|tag| {
+                UnitSnapshot::new(tag, UnitTypeId::Zealot, Point2::new(45f32, 32f32)).armed()
+            })
+            .collect();
+        threats.record(&game, &game.enemies.clone());
+        let ranking = rank_expansions(&game, &threats, &mut distances, &params, false);
+        assert_ne!(ranking[0], Point2::new(45f32, 30f32));
+
+        // The threat fades out
+        game.time += 120f32;
+        game.enemies.clear();
+        threats.record(&game, &[]);
+        let ranking = rank_expansions(&game, &threats, &mut distances, &params, false);
+        assert_eq!(ranking[0], Point2::new(45f32, 30f32));
+        // Distances were queried once for every ranking
+        assert_eq!(game.pathing_queries.get(), 1);
+    }
+
+    #[test]
+    fn hidden_expansion_away_from_enemy_path() {
+        let game = expansions_game();
+        let ranking = rank_expansions(
+            &game,
+            &ThreatHistory::default(),
+            &mut ExpansionDistances::default(),
+            &Params::default(),
+            true,
+        );
+        assert_eq!(ranking[0], Point2::new(30f32, 150f32));
+    }
+}
@@ -0,0 +1,555 @@
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+
+use rust_sc2::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::game_view::{ExpansionSnapshot, GameView, ResourceCost, UnitSnapshot};
+use crate::trace::LOOPS_PER_SECOND;
+
+// Commands issued to a FakeGame
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Action {
+    Train {
+        unit: u64,
+        unit_type: UnitTypeId,
+    },
+    Research {
+        unit: u64,
+        upgrade: UpgradeId,
+    },
+    Build {
+        unit: u64,
+        unit_type: UnitTypeId,
+        location: Point2,
+    },
+    BuildGas {
+        unit: u64,
+        geyser: u64,
+    },
+    UseAbility {
+        unit: u64,
+        ability: AbilityId,
+    },
+    Gather {
+        unit: u64,
+        target: u64,
+    },
+    ReturnResource {
+        unit: u64,
+    },
+    Attack {
+        unit: u64,
+        target: u64,
+    },
+    AttackMove {
+        unit: u64,
+        location: Point2,
+    },
+    Move {
+        unit: u64,
+        location: Point2,
+    },
+    RallyWorkers {
+        unit: u64,
+        resource: u64,
+    },
+    RallyUnits {
+        unit: u64,
+        location: Point2,
+    },
+    Chat(String),
+}
+
+impl Action {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Train { .. } => "Train",
+            Action::Research { .. } => "Research",
+            Action::Build { .. } => "Build",
+            Action::BuildGas { .. } => "BuildGas",
+            Action::UseAbility { .. } => "UseAbility",
+            Action::Gather { .. } => "Gather",
+            Action::ReturnResource { .. } => "ReturnResource",
+            Action::Attack { .. } => "Attack",
+            Action::AttackMove { .. } => "AttackMove",
+            Action::Move { .. } => "Move",
+            Action::RallyWorkers { .. } => "RallyWorkers",
+            Action::RallyUnits { .. } => "RallyUnits",
+            Action::Chat(_) => "Chat",
+        }
+    }
+
+    pub fn unit_type(&self) -> Option<UnitTypeId> {
+        match self {
+            Action::Train { unit_type, .. } | Action::Build { unit_type, .. } => Some(*unit_type),
+            _ => None,
+        }
+    }
+}
+
+const TOWNHALLS: [UnitTypeId; 9] = [
+    UnitTypeId::Hatchery,
+    UnitTypeId::Lair,
+    UnitTypeId::Hive,
+    UnitTypeId::Nexus,
+    UnitTypeId::CommandCenter,
+    UnitTypeId::CommandCenterFlying,
+    UnitTypeId::OrbitalCommand,
+    UnitTypeId::OrbitalCommandFlying,
+    UnitTypeId::PlanetaryFortress,
+];
+
+const WORKERS: [UnitTypeId; 3] = [UnitTypeId::Drone, UnitTypeId::Probe, UnitTypeId::SCV];
+
+const GAS_BUILDINGS: [UnitTypeId; 2] = [UnitTypeId::Extractor, UnitTypeId::ExtractorRich];
+
+// In-memory Zerg game for tests, commands are recorded instead of sent.
+#[derive(Debug, Clone)]
+pub struct FakeGame {
+    pub time: f32,
+    pub minerals: u32,
+    pub vespene: u32,
+    pub collected_minerals: u32,
+    pub collected_vespene: u32,
+    pub supply_left: u32,
+    pub supply_cap: u32,
+    pub start_location: Point2,
+    pub enemy_start: Point2,
+    pub map_center: Point2,
+    pub ramp: Option<Point2>,
+    pub units: Vec<UnitSnapshot>,
+    pub enemies: Vec<UnitSnapshot>,
+    pub mineral_fields: Vec<UnitSnapshot>,
+    pub vespene_geysers: Vec<UnitSnapshot>,
+    pub expansions: Vec<ExpansionSnapshot>,
+    // Tiles units can't walk on, everything else is
+    pub unpathable: HashSet<(usize, usize)>,
+    // Pathing queries made, each one is a round trip on the real game
+    pub pathing_queries: Cell<u32>,
+    pub upgrades: HashSet<UpgradeId>,
+    pub ordered: Vec<UnitTypeId>,
+    pub ordered_upgrades: HashSet<UpgradeId>,
+    pub costs: HashMap<UnitTypeId, ResourceCost>,
+    pub actions: Vec<Action>,
+    used_larva: HashSet<u64>,
+}
+
+impl Default for FakeGame {
+    fn default() -> Self {
+        let costs = [
+            (UnitTypeId::Drone, ResourceCost::new(50, 0, 1f32)),
+            (UnitTypeId::Overlord, ResourceCost::new(100, 0, 0f32)),
+            (UnitTypeId::Zergling, ResourceCost::new(25, 0, 0.5f32)),
+            (UnitTypeId::Queen, ResourceCost::new(150, 0, 2f32)),
+            (UnitTypeId::Roach, ResourceCost::new(75, 25, 2f32)),
+            (UnitTypeId::Hatchery, ResourceCost::new(300, 0, 0f32)),
+            (UnitTypeId::SpawningPool, ResourceCost::new(200, 0, 0f32)),
+            (UnitTypeId::Extractor, ResourceCost::new(25, 0, 0f32)),
+            (UnitTypeId::RoachWarren, ResourceCost::new(150, 0, 0f32)),
+            (UnitTypeId::SpineCrawler, ResourceCost::new(100, 0, 0f32)),
+            (UnitTypeId::SporeCrawler, ResourceCost::new(75, 0, 0f32)),
+            (UnitTypeId::Probe, ResourceCost::new(50, 0, 1f32)),
+            (UnitTypeId::Zealot, ResourceCost::new(100, 0, 2f32)),
+            (UnitTypeId::Pylon, ResourceCost::new(100, 0, 0f32)),
+            (UnitTypeId::PhotonCannon, ResourceCost::new(150, 0, 0f32)),
+            (UnitTypeId::SCV, ResourceCost::new(50, 0, 1f32)),
+            (UnitTypeId::Marine, ResourceCost::new(50, 0, 1f32)),
+        ];
+        Self {
+            time: 0f32,
+            minerals: 50,
+            vespene: 0,
+            collected_minerals: 0,
+            collected_vespene: 0,
+            supply_left: 2,
+            supply_cap: 14,
+            start_location: Point2::new(30f32, 30f32),
+            enemy_start: Point2::new(170f32, 170f32),
+            map_center: Point2::new(100f32, 100f32),
+            ramp: None,
+            units: vec![],
+            enemies: vec![],
+            mineral_fields: vec![],
+            vespene_geysers: vec![],
+            expansions: vec![],
+            unpathable: HashSet::new(),
+            pathing_queries: Cell::new(0),
+            upgrades: HashSet::new(),
+            ordered: vec![],
+            ordered_upgrades: HashSet::new(),
+            costs: costs.into_iter().collect(),
+            actions: vec![],
+            used_larva: HashSet::new(),
+        }
+    }
+}
+
+impl FakeGame {
+    fn own_of(&self, unit_types: &[UnitTypeId]) -> Vec<UnitSnapshot> {
+        self.units
+            .iter()
+            .filter(|u| unit_types.contains(&u.type_id))
+            .cloned()
+            .collect()
+    }
+}
+
+impl GameView for FakeGame {
+    fn time(&self) -> f32 {
+        self.time
+    }
+
+    fn game_loop(&self) -> u32 {
+        (self.time * LOOPS_PER_SECOND) as u32
+    }
+
+    fn minerals(&self) -> u32 {
+        self.minerals
+    }
+
+    fn vespene(&self) -> u32 {
+        self.vespene
+    }
+
+    fn collected_minerals(&self) -> u32 {
+        self.collected_minerals
+    }
+
+    fn collected_vespene(&self) -> u32 {
+        self.collected_vespene
+    }
+
+    fn supply_left(&self) -> u32 {
+        self.supply_left
+    }
+
+    fn supply_cap(&self) -> u32 {
+        self.supply_cap
+    }
+
+    fn start_location(&self) -> Point2 {
+        self.start_location
+    }
+
+    fn start_center(&self) -> Point2 {
+        self.start_location
+    }
+
+    fn enemy_start(&self) -> Point2 {
+        self.enemy_start
+    }
+
+    fn map_center(&self) -> Point2 {
+        self.map_center
+    }
+
+    fn my_ramp_center(&self) -> Option<Point2> {
+        self.ramp
+    }
+
+    fn worker_type(&self) -> UnitTypeId {
+        UnitTypeId::Drone
+    }
+
+    fn townhall_type(&self) -> UnitTypeId {
+        UnitTypeId::Hatchery
+    }
+
+    fn townhall_types(&self) -> Vec<UnitTypeId> {
+        TOWNHALLS[..3].to_vec()
+    }
+
+    fn gas_types(&self) -> Vec<UnitTypeId> {
+        GAS_BUILDINGS.to_vec()
+    }
+
+    fn my_units(&self) -> Cow<'_, [UnitSnapshot]> {
+        Cow::Borrowed(&self.units)
+    }
+
+    fn my_structures(&self) -> Cow<'_, [UnitSnapshot]> {
+        Cow::Owned(
+            self.units
+                .iter()
+                .filter(|u| u.is_structure())
+                .cloned()
+                .collect(),
+        )
+    }
+
+    fn my_townhalls(&self) -> Cow<'_, [UnitSnapshot]> {
+        Cow::Owned(self.own_of(&TOWNHALLS))
+    }
+
+    fn my_workers(&self) -> Cow<'_, [UnitSnapshot]> {
+        Cow::Owned(self.own_of(&WORKERS))
+    }
+
+    fn my_larvas(&self) -> Cow<'_, [UnitSnapshot]> {
+        Cow::Owned(
+            self.own_of(&[UnitTypeId::Larva])
+                .into_iter()
+                .filter(|u| !self.used_larva.contains(&u.tag))
+                .collect(),
+        )
+    }
+
+    fn my_gas_buildings(&self) -> Cow<'_, [UnitSnapshot]> {
+        Cow::Owned(self.own_of(&GAS_BUILDINGS))
+    }
+
+    fn enemy_units(&self) -> Cow<'_, [UnitSnapshot]> {
+        Cow::Borrowed(&self.enemies)
+    }
+
+    fn enemy_townhalls(&self) -> Cow<'_, [UnitSnapshot]> {
+        Cow::Owned(
+            self.enemies
+                .iter()
+                .filter(|u| TOWNHALLS.contains(&u.type_id))
+                .cloned()
+                .collect(),
+        )
+    }
+
+    fn mineral_fields(&self) -> Cow<'_, [UnitSnapshot]> {
+        Cow::Borrowed(&self.mineral_fields)
+    }
+
+    fn vespene_geysers(&self) -> Cow<'_, [UnitSnapshot]> {
+        Cow::Borrowed(&self.vespene_geysers)
+    }
+
+    fn unit(&self, tag: u64) -> Option<UnitSnapshot> {
+        self.units
+            .iter()
+            .chain(self.enemies.iter())
+            .chain(self.mineral_fields.iter())
+            .chain(self.vespene_geysers.iter())
+            .find(|u| u.tag == tag)
+            .cloned()
+    }
+
+    fn count(&self, unit_type: UnitTypeId) -> usize {
+        self.units
+            .iter()
+            .filter(|u| u.type_id == unit_type && u.is_ready())
+            .count()
+    }
+
+    fn count_ordered(&self, unit_type: UnitTypeId) -> usize {
+        self.units
+            .iter()
+            .filter(|u| u.type_id == unit_type && !u.is_ready())
+            .count()
+            + self.ordered.iter().filter(|t| **t == unit_type).count()
+    }
+
+    fn count_alias(&self, unit_type: UnitTypeId) -> usize {
+        self.count(unit_type)
+    }
+
+    fn has_upgrade(&self, upgrade: UpgradeId) -> bool {
+        self.upgrades.contains(&upgrade)
+    }
+
+    fn is_ordered_upgrade(&self, upgrade: UpgradeId) -> bool {
+        self.ordered_upgrades.contains(&upgrade)
+    }
+
+    fn unit_cost(&self, unit_type: UnitTypeId) -> Option<ResourceCost> {
+        self.costs.get(&unit_type).copied()
+    }
+
+    fn upgrade_cost(&self, _upgrade: UpgradeId) -> ResourceCost {
+        ResourceCost::new(100, 100, 0f32)
+    }
+
+    fn supply_provided(&self, unit_type: UnitTypeId) -> u32 {
+        match unit_type {
+            UnitTypeId::Overlord | UnitTypeId::Pylon => 8,
+            UnitTypeId::Hatchery | UnitTypeId::Lair | UnitTypeId::Hive => 6,
+            _ => 0,
+        }
+    }
+
+    // Only ground weapons are modelled
+    fn can_attack_unit(&self, unit: u64, target: u64) -> bool {
+        match (self.unit(unit), self.unit(target)) {
+            (Some(unit), Some(target)) => unit.can_attack_ground && !target.is_flying,
+            _ => false,
+        }
+    }
+
+    fn in_range(&self, unit: u64, target: u64, gap: f32) -> bool {
+        match (self.unit(unit), self.unit(target)) {
+            (Some(unit), Some(target)) => {
+                unit.distance(target.position)
+                    <= unit.ground_range + unit.radius + target.radius + gap
+            }
+            _ => false,
+        }
+    }
+
+    fn expansions(&self) -> Cow<'_, [ExpansionSnapshot]> {
+        Cow::Borrowed(&self.expansions)
+    }
+
+    fn ground_distance(&self, from: Point2, to: Point2) -> Option<f32> {
+        self.pathing_queries.set(self.pathing_queries.get() + 1);
+        Some(from.distance(to))
+    }
+
+    fn ground_distances(&self, paths: &[(Point2, Point2)]) -> Vec<Option<f32>> {
+        self.pathing_queries.set(self.pathing_queries.get() + 1);
+        paths
+            .iter()
+            .map(|(from, to)| Some(from.distance(*to)))
+            .collect()
+    }
+
+    fn is_pathable(&self, position: Point2) -> bool {
+        !self
+            .unpathable
+            .contains(&(position.x as usize, position.y as usize))
+    }
+
+    fn find_placement(
+        &self,
+        _unit_type: UnitTypeId,
+        near: Point2,
+        _max_distance: u32,
+        _step: u32,
+    ) -> Option<Point2> {
+        Some(near)
+    }
+
+    fn find_gas_placement(&self, expansion: Point2) -> Option<UnitSnapshot> {
+        self.vespene_geysers
+            .iter()
+            .filter(|g| g.distance(expansion) < 10f32)
+            .find(|g| !self.units.iter().any(|u| u.distance(g.position) < 0.5f32))
+            .cloned()
+    }
+
+    fn train(&mut self, producer: u64, unit_type: UnitTypeId) {
+        self.used_larva.insert(producer);
+        self.actions.push(Action::Train {
+            unit: producer,
+            unit_type,
+        });
+    }
+
+    fn research(&mut self, building: u64, upgrade: UpgradeId) {
+        self.actions.push(Action::Research {
+            unit: building,
+            upgrade,
+        });
+    }
+
+    fn build(&mut self, builder: u64, unit_type: UnitTypeId, location: Point2) {
+        self.actions.push(Action::Build {
+            unit: builder,
+            unit_type,
+            location,
+        });
+    }
+
+    fn build_gas(&mut self, builder: u64, geyser: u64) {
+        self.actions.push(Action::BuildGas {
+            unit: builder,
+            geyser,
+        });
+    }
+
+    fn use_ability(&mut self, unit: u64, ability: AbilityId) {
+        self.actions.push(Action::UseAbility { unit, ability });
+    }
+
+    fn gather(&mut self, unit: u64, resource: u64) {
+        self.actions.push(Action::Gather {
+            unit,
+            target: resource,
+        });
+    }
+
+    fn return_resource(&mut self, unit: u64) {
+        self.actions.push(Action::ReturnResource { unit });
+    }
+
+    fn attack(&mut self, unit: u64, target: u64) {
+        self.actions.push(Action::Attack { unit, target });
+    }
+
+    fn attack_move(&mut self, unit: u64, location: Point2) {
+        self.actions.push(Action::AttackMove { unit, location });
+    }
+
+    fn move_to(&mut self, unit: u64, location: Point2) {
+        self.actions.push(Action::Move { unit, location });
+    }
+
+    fn rally_workers(&mut self, townhall: u64, resource: u64) {
+        self.actions.push(Action::RallyWorkers {
+            unit: townhall,
+            resource,
+        });
+    }
+
+    fn rally_units(&mut self, townhall: u64, location: Point2) {
+        self.actions.push(Action::RallyUnits {
+            unit: townhall,
+            location,
+        });
+    }
+
+    fn spend(&mut self, unit_type: UnitTypeId, use_supply: bool) {
+        if let Some(cost) = self.unit_cost(unit_type) {
+            self.minerals = self.minerals.saturating_sub(cost.minerals);
+            self.vespene = self.vespene.saturating_sub(cost.vespene);
+            if use_supply {
+                self.supply_left = self.supply_left.saturating_sub(cost.supply.ceil() as u32);
+            }
+        }
+    }
+
+    fn spend_upgrade(&mut self, upgrade: UpgradeId) {
+        let cost = self.upgrade_cost(upgrade);
+        self.minerals = self.minerals.saturating_sub(cost.minerals);
+        self.vespene = self.vespene.saturating_sub(cost.vespene);
+    }
+
+    fn chat(&mut self, message: &str) {
+        self.actions.push(Action::Chat(message.to_string()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_sc2::prelude::*;
+
+    use crate::fake_game::FakeGame;
+    use crate::game_view::{GameView, UnitSnapshot};
+
+    #[test]
+    fn fake_spends_and_uses_larva() {
+        let mut game = FakeGame {
+            minerals: 120,
+            ..Default::default()
+        };
+        game.units = vec![
+            UnitSnapshot::new(1, UnitTypeId::Larva, Point2::new(30f32, 30f32)),
+            UnitSnapshot::new(2, UnitTypeId::Larva, Point2::new(31f32, 30f32)),
+            UnitSnapshot::new(3, UnitTypeId::Hatchery, Point2::new(30f32, 32f32)),
+        ];
+        assert!(game.can_afford(UnitTypeId::Drone, true));
+        game.train(1, UnitTypeId::Drone);
+        game.spend(UnitTypeId::Drone, true);
+        assert_eq!(game.minerals, 70);
+        assert_eq!(game.supply_left, 1);
+        assert_eq!(game.my_larvas().len(), 1);
+        assert_eq!(game.my_townhalls().len(), 1);
+    }
+}
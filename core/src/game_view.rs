@@ -0,0 +1,776 @@
+use std::borrow::Cow;
+use std::cell::OnceCell;
+
+use rust_sc2::bot::Bot;
+use rust_sc2::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{cost_strength, strength_multiplier, Center, PathingDistance, UnitOrderCheck};
+
+// What the managers read from a unit, detached from the game client.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UnitSnapshot {
+    pub tag: u64,
+    pub type_id: UnitTypeId,
+    pub position: Point2,
+    pub radius: f32,
+    pub build_progress: f32,
+    pub hits: Option<u32>,
+    pub health_percentage: Option<f32>,
+    pub is_flying: bool,
+    pub is_cloaked: bool,
+    pub is_hallucination: bool,
+    pub can_attack: bool,
+    pub can_attack_ground: bool,
+    pub ground_range: f32,
+    pub speed: f32,
+    pub weapon_cooldown: Option<f32>,
+    pub is_idle: bool,
+    pub is_almost_idle: bool,
+    pub is_constructing: bool,
+    pub is_returning: bool,
+    pub is_carrying_resource: bool,
+    pub target_tag: Option<u64>,
+    pub mineral_contents: Option<u32>,
+    pub vespene_contents: Option<u32>,
+    pub ideal_harvesters: Option<u32>,
+    pub assigned_harvesters: Option<u32>,
+}
+
+impl Default for UnitSnapshot {
+    fn default() -> Self {
+        Self {
+            tag: 0,
+            type_id: UnitTypeId::NotAUnit,
+            position: Point2::new(0f32, 0f32),
+            radius: 0.5f32,
+            build_progress: 1f32,
+            hits: None,
+            health_percentage: None,
+            is_flying: false,
+            is_cloaked: false,
+            is_hallucination: false,
+            can_attack: false,
+            can_attack_ground: false,
+            ground_range: 0f32,
+            speed: 0f32,
+            weapon_cooldown: None,
+            is_idle: true,
+            is_almost_idle: true,
+            is_constructing: false,
+            is_returning: false,
+            is_carrying_resource: false,
+            target_tag: None,
+            mineral_contents: None,
+            vespene_contents: None,
+            ideal_harvesters: None,
+            assigned_harvesters: None,
+        }
+    }
+}
+
+impl UnitSnapshot {
+    pub fn new(tag: u64, type_id: UnitTypeId, position: Point2) -> Self {
+        Self {
+            tag,
+            type_id,
+            position,
+            ..Default::default()
+        }
+    }
+
+    pub fn armed(mut self) -> Self {
+        self.can_attack = true;
+        self.can_attack_ground = true;
+        self
+    }
+
+    pub fn with_progress(mut self, build_progress: f32) -> Self {
+        self.build_progress = build_progress;
+        self
+    }
+
+    pub fn with_harvesters(mut self, ideal: u32, assigned: u32) -> Self {
+        self.ideal_harvesters = Some(ideal);
+        self.assigned_harvesters = Some(assigned);
+        self
+    }
+
+    pub fn with_contents(mut self, minerals: u32, vespene: u32) -> Self {
+        self.mineral_contents = Some(minerals);
+        self.vespene_contents = Some(vespene);
+        self
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.build_progress >= 1f32
+    }
+
+    pub fn is_almost_ready(&self) -> bool {
+        self.build_progress >= 0.95f32
+    }
+
+    pub fn is_worker(&self) -> bool {
+        self.type_id.is_worker()
+    }
+
+    pub fn is_structure(&self) -> bool {
+        self.type_id.is_structure()
+    }
+
+    pub fn distance(&self, position: Point2) -> f32 {
+        self.position.distance(position)
+    }
+
+    pub fn resources_left(&self) -> u32 {
+        self.mineral_contents.unwrap_or_default() + self.vespene_contents.unwrap_or_default()
+    }
+}
+
+impl From<&Unit> for UnitSnapshot {
+    fn from(unit: &Unit) -> Self {
+        Self {
+            tag: unit.tag(),
+            type_id: unit.type_id(),
+            position: unit.position(),
+            radius: unit.radius(),
+            build_progress: unit.build_progress(),
+            hits: unit.hits(),
+            health_percentage: unit.hits_percentage(),
+            is_flying: unit.is_flying(),
+            is_cloaked: unit.is_cloaked(),
+            is_hallucination: unit.is_hallucination(),
+            can_attack: unit.can_attack(),
+            can_attack_ground: unit.can_attack_ground(),
+            ground_range: unit.ground_range(),
+            speed: unit.speed(),
+            weapon_cooldown: unit.weapon_cooldown(),
+            is_idle: unit.is_idle(),
+            is_almost_idle: unit.is_almost_idle(),
+            is_constructing: unit.is_constructing(),
+            is_returning: unit.is_returning(),
+            is_carrying_resource: unit.is_carrying_resource(),
+            target_tag: unit.target_tag(),
+            mineral_contents: unit.mineral_contents(),
+            vespene_contents: unit.vespene_contents(),
+            ideal_harvesters: unit.ideal_harvesters(),
+            assigned_harvesters: unit.assigned_harvesters(),
+        }
+    }
+}
+
+pub fn snapshots(units: &Units) -> Vec<UnitSnapshot> {
+    units.iter().map(UnitSnapshot::from).collect()
+}
+
+pub fn closest<'a, I>(units: I, position: Point2) -> Option<&'a UnitSnapshot>
+where
+    I: IntoIterator<Item = &'a UnitSnapshot>,
+{
+    units.into_iter().min_by(|a, b| {
+        a.distance(position)
+            .partial_cmp(&b.distance(position))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    })
+}
+
+pub fn center(units: &[UnitSnapshot]) -> Option<Point2> {
+    if units.is_empty() {
+        None
+    } else {
+        let sum = units
+            .iter()
+            .fold(Point2::new(0f32, 0f32), |sum, u| sum + u.position);
+        Some(sum / units.len() as f32)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum BaseOwner {
+    Neutral,
+    Own,
+    Enemy,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExpansionSnapshot {
+    pub loc: Point2,
+    pub geysers: usize,
+    pub owner: BaseOwner,
+    pub base: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResourceCost {
+    pub minerals: u32,
+    pub vespene: u32,
+    pub supply: f32,
+}
+
+impl ResourceCost {
+    pub fn new(minerals: u32, vespene: u32, supply: f32) -> Self {
+        Self {
+            minerals,
+            vespene,
+            supply,
+        }
+    }
+}
+
+// Everything the production, worker and resource logic reads from the game and
+// the commands it issues. Backed by a `BotView` in games and by `FakeGame` in tests.
+pub trait GameView {
+    fn time(&self) -> f32;
+    fn game_loop(&self) -> u32;
+    fn minerals(&self) -> u32;
+    fn vespene(&self) -> u32;
+    // Totals gathered since the game started
+    fn collected_minerals(&self) -> u32;
+    fn collected_vespene(&self) -> u32;
+    fn supply_left(&self) -> u32;
+    fn supply_cap(&self) -> u32;
+    fn start_location(&self) -> Point2;
+    fn start_center(&self) -> Point2;
+    // Where the enemy started, the first of the possible locations if unknown
+    fn enemy_start(&self) -> Point2;
+    fn map_center(&self) -> Point2;
+    fn my_ramp_center(&self) -> Option<Point2>;
+
+    fn worker_type(&self) -> UnitTypeId;
+    fn townhall_type(&self) -> UnitTypeId;
+    fn townhall_types(&self) -> Vec<UnitTypeId>;
+    fn gas_types(&self) -> Vec<UnitTypeId>;
+
+    fn my_units(&self) -> Cow<'_, [UnitSnapshot]>;
+    fn my_structures(&self) -> Cow<'_, [UnitSnapshot]>;
+    fn my_townhalls(&self) -> Cow<'_, [UnitSnapshot]>;
+    fn my_workers(&self) -> Cow<'_, [UnitSnapshot]>;
+    fn my_larvas(&self) -> Cow<'_, [UnitSnapshot]>;
+    fn my_gas_buildings(&self) -> Cow<'_, [UnitSnapshot]>;
+    fn enemy_units(&self) -> Cow<'_, [UnitSnapshot]>;
+    fn enemy_townhalls(&self) -> Cow<'_, [UnitSnapshot]>;
+    fn mineral_fields(&self) -> Cow<'_, [UnitSnapshot]>;
+    fn vespene_geysers(&self) -> Cow<'_, [UnitSnapshot]>;
+    // Any visible unit, ours, theirs or neutral
+    fn unit(&self, tag: u64) -> Option<UnitSnapshot>;
+
+    // Ready units
+    fn count(&self, unit_type: UnitTypeId) -> usize;
+    // Units in progress or ordered this step
+    fn count_ordered(&self, unit_type: UnitTypeId) -> usize;
+    fn count_alias(&self, unit_type: UnitTypeId) -> usize;
+    fn has_upgrade(&self, upgrade: UpgradeId) -> bool;
+    fn is_ordered_upgrade(&self, upgrade: UpgradeId) -> bool;
+    fn unit_cost(&self, unit_type: UnitTypeId) -> Option<ResourceCost>;
+    fn upgrade_cost(&self, upgrade: UpgradeId) -> ResourceCost;
+    fn supply_provided(&self, unit_type: UnitTypeId) -> u32;
+    fn can_attack_unit(&self, unit: u64, target: u64) -> bool;
+    // Target within the unit weapon range plus the gap
+    fn in_range(&self, unit: u64, target: u64, gap: f32) -> bool;
+
+    fn expansions(&self) -> Cow<'_, [ExpansionSnapshot]>;
+    fn ground_distance(&self, from: Point2, to: Point2) -> Option<f32>;
+    // Several distances in a single query, None for unreachable ones
+    fn ground_distances(&self, paths: &[(Point2, Point2)]) -> Vec<Option<f32>>;
+    fn is_pathable(&self, position: Point2) -> bool;
+    fn find_placement(
+        &self,
+        unit_type: UnitTypeId,
+        near: Point2,
+        max_distance: u32,
+        step: u32,
+    ) -> Option<Point2>;
+    fn find_gas_placement(&self, expansion: Point2) -> Option<UnitSnapshot>;
+
+    fn train(&mut self, producer: u64, unit_type: UnitTypeId);
+    fn research(&mut self, building: u64, upgrade: UpgradeId);
+    fn build(&mut self, builder: u64, unit_type: UnitTypeId, location: Point2);
+    fn build_gas(&mut self, builder: u64, geyser: u64);
+    fn use_ability(&mut self, unit: u64, ability: AbilityId);
+    fn gather(&mut self, unit: u64, resource: u64);
+    fn return_resource(&mut self, unit: u64);
+    fn attack(&mut self, unit: u64, target: u64);
+    fn attack_move(&mut self, unit: u64, location: Point2);
+    fn move_to(&mut self, unit: u64, location: Point2);
+    fn rally_workers(&mut self, townhall: u64, resource: u64);
+    fn rally_units(&mut self, townhall: u64, location: Point2);
+    // Takes the cost out of the resources left for this step
+    fn spend(&mut self, unit_type: UnitTypeId, use_supply: bool);
+    fn spend_upgrade(&mut self, upgrade: UpgradeId);
+    fn chat(&mut self, message: &str);
+
+    fn count_all(&self, unit_type: UnitTypeId) -> usize {
+        self.count(unit_type) + self.count_ordered(unit_type)
+    }
+
+    // Townhalls away from the main and every expansion, built for their larva
+    fn macro_townhalls(&self) -> Vec<UnitSnapshot> {
+        let expansions = self.expansions();
+        let main = self.start_location();
+        self.my_townhalls()
+            .iter()
+            .filter(|u| {
+                u.distance(main) > 3f32 && !expansions.iter().any(|e| u.distance(e.loc) < 3f32)
+            })
+            .cloned()
+            .collect()
+    }
+
+    fn has_ready(&self, unit_types: &[UnitTypeId]) -> bool {
+        self.my_units()
+            .iter()
+            .any(|u| u.is_ready() && unit_types.contains(&u.type_id))
+    }
+
+    fn can_afford(&self, unit_type: UnitTypeId, check_supply: bool) -> bool {
+        self.unit_cost(unit_type).map_or(false, |cost| {
+            self.minerals() >= cost.minerals
+                && self.vespene() >= cost.vespene
+                && (!check_supply || self.supply_left() as f32 >= cost.supply)
+        })
+    }
+
+    fn can_afford_upgrade(&self, upgrade: UpgradeId) -> bool {
+        let cost = self.upgrade_cost(upgrade);
+        self.minerals() >= cost.minerals && self.vespene() >= cost.vespene
+    }
+
+    fn free_expansions(&self) -> Vec<ExpansionSnapshot> {
+        self.expansions()
+            .iter()
+            .filter(|e| e.owner == BaseOwner::Neutral)
+            .cloned()
+            .collect()
+    }
+
+    fn owned_expansions(&self) -> Vec<ExpansionSnapshot> {
+        self.expansions()
+            .iter()
+            .filter(|e| e.owner == BaseOwner::Own)
+            .cloned()
+            .collect()
+    }
+
+    fn enemy_expansions(&self) -> Vec<ExpansionSnapshot> {
+        self.expansions()
+            .iter()
+            .filter(|e| e.owner == BaseOwner::Enemy)
+            .cloned()
+            .collect()
+    }
+
+    fn type_strength(&self, unit_type: UnitTypeId) -> f32 {
+        self.unit_cost(unit_type).map_or(0f32, |cost| {
+            cost_strength(unit_type, cost.minerals, cost.vespene)
+        })
+    }
+
+    fn unit_strength(&self, unit: &UnitSnapshot) -> f32 {
+        strength_multiplier(
+            unit.is_almost_ready(),
+            unit.is_hallucination,
+            unit.can_attack,
+            unit.is_cloaked,
+        ) * self.type_strength(unit.type_id)
+            * unit.health_percentage.unwrap_or(1f32)
+    }
+
+    fn strength<'a, I>(&self, units: I) -> f32
+    where
+        I: IntoIterator<Item = &'a UnitSnapshot>,
+        Self: Sized,
+    {
+        units.into_iter().map(|u| self.unit_strength(u)).sum()
+    }
+}
+
+// The Bot seen through the GameView. Each unit group is snapshot the first time
+// it's read, so a view made once per step doesn't copy units on every call.
+pub struct BotView<'a> {
+    bot: &'a mut Bot,
+    my_units: OnceCell<Vec<UnitSnapshot>>,
+    my_structures: OnceCell<Vec<UnitSnapshot>>,
+    my_townhalls: OnceCell<Vec<UnitSnapshot>>,
+    my_workers: OnceCell<Vec<UnitSnapshot>>,
+    my_larvas: OnceCell<Vec<UnitSnapshot>>,
+    my_gas_buildings: OnceCell<Vec<UnitSnapshot>>,
+    enemy_units: OnceCell<Vec<UnitSnapshot>>,
+    enemy_townhalls: OnceCell<Vec<UnitSnapshot>>,
+    mineral_fields: OnceCell<Vec<UnitSnapshot>>,
+    vespene_geysers: OnceCell<Vec<UnitSnapshot>>,
+    expansions: OnceCell<Vec<ExpansionSnapshot>>,
+}
+
+impl<'a> BotView<'a> {
+    pub fn new(bot: &'a mut Bot) -> Self {
+        Self {
+            bot,
+            my_units: OnceCell::new(),
+            my_structures: OnceCell::new(),
+            my_townhalls: OnceCell::new(),
+            my_workers: OnceCell::new(),
+            my_larvas: OnceCell::new(),
+            my_gas_buildings: OnceCell::new(),
+            enemy_units: OnceCell::new(),
+            enemy_townhalls: OnceCell::new(),
+            mineral_fields: OnceCell::new(),
+            vespene_geysers: OnceCell::new(),
+            expansions: OnceCell::new(),
+        }
+    }
+}
+
+impl GameView for BotView<'_> {
+    fn time(&self) -> f32 {
+        self.bot.time
+    }
+
+    fn game_loop(&self) -> u32 {
+        self.bot.state.observation.game_loop()
+    }
+
+    fn minerals(&self) -> u32 {
+        self.bot.minerals
+    }
+
+    fn vespene(&self) -> u32 {
+        self.bot.vespene
+    }
+
+    fn collected_minerals(&self) -> u32 {
+        self.bot.state.observation.score.collected_minerals as u32
+    }
+
+    fn collected_vespene(&self) -> u32 {
+        self.bot.state.observation.score.collected_vespene as u32
+    }
+
+    fn supply_left(&self) -> u32 {
+        self.bot.supply_left
+    }
+
+    fn supply_cap(&self) -> u32 {
+        self.bot.supply_cap
+    }
+
+    fn start_location(&self) -> Point2 {
+        self.bot.start_location
+    }
+
+    fn start_center(&self) -> Point2 {
+        self.bot.start_center
+    }
+
+    fn enemy_start(&self) -> Point2 {
+        self.bot.enemy_start
+    }
+
+    fn map_center(&self) -> Point2 {
+        self.bot.game_info.map_center
+    }
+
+    fn my_ramp_center(&self) -> Option<Point2> {
+        self.bot.ramps.my.points.center_point()
+    }
+
+    fn worker_type(&self) -> UnitTypeId {
+        self.bot.race_values.worker
+    }
+
+    fn townhall_type(&self) -> UnitTypeId {
+        self.bot.race_values.start_townhall
+    }
+
+    fn townhall_types(&self) -> Vec<UnitTypeId> {
+        self.bot.race_values.townhalls.iter().copied().collect()
+    }
+
+    fn gas_types(&self) -> Vec<UnitTypeId> {
+        vec![self.bot.race_values.gas, self.bot.race_values.rich_gas]
+    }
+
+    fn my_units(&self) -> Cow<'_, [UnitSnapshot]> {
+        Cow::Borrowed(
+            self.my_units
+                .get_or_init(|| snapshots(&self.bot.units.my.all)),
+        )
+    }
+
+    fn my_structures(&self) -> Cow<'_, [UnitSnapshot]> {
+        Cow::Borrowed(
+            self.my_structures
+                .get_or_init(|| snapshots(&self.bot.units.my.structures)),
+        )
+    }
+
+    fn my_townhalls(&self) -> Cow<'_, [UnitSnapshot]> {
+        Cow::Borrowed(
+            self.my_townhalls
+                .get_or_init(|| snapshots(&self.bot.units.my.townhalls)),
+        )
+    }
+
+    fn my_workers(&self) -> Cow<'_, [UnitSnapshot]> {
+        Cow::Borrowed(
+            self.my_workers
+                .get_or_init(|| snapshots(&self.bot.units.my.workers)),
+        )
+    }
+
+    fn my_larvas(&self) -> Cow<'_, [UnitSnapshot]> {
+        Cow::Borrowed(
+            self.my_larvas
+                .get_or_init(|| snapshots(&self.bot.units.my.larvas)),
+        )
+    }
+
+    fn my_gas_buildings(&self) -> Cow<'_, [UnitSnapshot]> {
+        Cow::Borrowed(
+            self.my_gas_buildings
+                .get_or_init(|| snapshots(&self.bot.units.my.gas_buildings)),
+        )
+    }
+
+    fn enemy_units(&self) -> Cow<'_, [UnitSnapshot]> {
+        Cow::Borrowed(
+            self.enemy_units
+                .get_or_init(|| snapshots(&self.bot.units.enemy.all)),
+        )
+    }
+
+    fn enemy_townhalls(&self) -> Cow<'_, [UnitSnapshot]> {
+        Cow::Borrowed(
+            self.enemy_townhalls
+                .get_or_init(|| snapshots(&self.bot.units.enemy.townhalls)),
+        )
+    }
+
+    fn mineral_fields(&self) -> Cow<'_, [UnitSnapshot]> {
+        Cow::Borrowed(
+            self.mineral_fields
+                .get_or_init(|| snapshots(&self.bot.units.mineral_fields)),
+        )
+    }
+
+    fn vespene_geysers(&self) -> Cow<'_, [UnitSnapshot]> {
+        Cow::Borrowed(
+            self.vespene_geysers
+                .get_or_init(|| snapshots(&self.bot.units.vespene_geysers)),
+        )
+    }
+
+    fn unit(&self, tag: u64) -> Option<UnitSnapshot> {
+        self.bot.units.all.get(tag).map(UnitSnapshot::from)
+    }
+
+    fn count(&self, unit_type: UnitTypeId) -> usize {
+        self.bot.counter().count(unit_type)
+    }
+
+    fn count_ordered(&self, unit_type: UnitTypeId) -> usize {
+        self.bot.counter().ordered().count(unit_type)
+    }
+
+    fn count_alias(&self, unit_type: UnitTypeId) -> usize {
+        self.bot.counter().alias().count(unit_type)
+    }
+
+    fn count_all(&self, unit_type: UnitTypeId) -> usize {
+        self.bot.counter().all().count(unit_type)
+    }
+
+    fn has_upgrade(&self, upgrade: UpgradeId) -> bool {
+        self.bot.has_upgrade(upgrade)
+    }
+
+    fn is_ordered_upgrade(&self, upgrade: UpgradeId) -> bool {
+        self.bot.is_ordered_upgrade(upgrade)
+    }
+
+    fn unit_cost(&self, unit_type: UnitTypeId) -> Option<ResourceCost> {
+        self.bot
+            .game_data
+            .units
+            .get(&unit_type)
+            .map(|data| ResourceCost::new(data.mineral_cost, data.vespene_cost, data.food_required))
+    }
+
+    fn upgrade_cost(&self, upgrade: UpgradeId) -> ResourceCost {
+        let cost = self.bot.get_upgrade_cost(upgrade);
+        ResourceCost::new(cost.minerals, cost.vespene, 0f32)
+    }
+
+    fn supply_provided(&self, unit_type: UnitTypeId) -> u32 {
+        self.bot
+            .game_data
+            .units
+            .get(&unit_type)
+            .map_or(0, |data| data.food_provided as u32)
+    }
+
+    fn can_attack_unit(&self, unit: u64, target: u64) -> bool {
+        match (self.bot.units.all.get(unit), self.bot.units.all.get(target)) {
+            (Some(unit), Some(target)) => unit.can_attack_unit(target),
+            _ => false,
+        }
+    }
+
+    fn in_range(&self, unit: u64, target: u64, gap: f32) -> bool {
+        match (self.bot.units.all.get(unit), self.bot.units.all.get(target)) {
+            (Some(unit), Some(target)) => unit.in_range(target, gap),
+            _ => false,
+        }
+    }
+
+    fn expansions(&self) -> Cow<'_, [ExpansionSnapshot]> {
+        Cow::Borrowed(self.expansions.get_or_init(|| {
+            self.bot
+                .expansions
+                .iter()
+                .map(|e| ExpansionSnapshot {
+                    loc: e.loc,
+                    geysers: e.geysers.len(),
+                    owner: match e.alliance {
+                        Alliance::Own => BaseOwner::Own,
+                        Alliance::Enemy => BaseOwner::Enemy,
+                        _ => BaseOwner::Neutral,
+                    },
+                    base: e.base,
+                })
+                .collect()
+        }))
+    }
+
+    fn ground_distance(&self, from: Point2, to: Point2) -> Option<f32> {
+        self.bot.pathing_distance(from, to)
+    }
+
+    fn ground_distances(&self, paths: &[(Point2, Point2)]) -> Vec<Option<f32>> {
+        self.bot
+            .query_pathing(
+                paths
+                    .iter()
+                    .map(|(from, to)| (Target::Pos(*from), *to))
+                    .collect(),
+            )
+            .unwrap_or_else(|_| vec![None; paths.len()])
+    }
+
+    fn is_pathable(&self, position: Point2) -> bool {
+        self.bot.is_pathable(position)
+    }
+
+    fn find_placement(
+        &self,
+        unit_type: UnitTypeId,
+        near: Point2,
+        max_distance: u32,
+        step: u32,
+    ) -> Option<Point2> {
+        self.bot.find_placement(
+            unit_type,
+            near,
+            PlacementOptions {
+                max_distance,
+                step,
+                random: false,
+                addon: false,
+            },
+        )
+    }
+
+    fn find_gas_placement(&self, expansion: Point2) -> Option<UnitSnapshot> {
+        self.bot
+            .find_gas_placement(expansion)
+            .map(|geyser| UnitSnapshot::from(&geyser))
+    }
+
+    fn train(&mut self, producer: u64, unit_type: UnitTypeId) {
+        if let Some(unit) = self.bot.units.my.all.get(producer) {
+            unit.train(unit_type, false);
+        }
+        self.bot.units.my.larvas.remove(producer);
+        if let Some(larvas) = self.my_larvas.get_mut() {
+            larvas.retain(|u| u.tag != producer);
+        }
+    }
+
+    fn research(&mut self, building: u64, upgrade: UpgradeId) {
+        if let Some(unit) = self.bot.units.my.structures.get(building) {
+            unit.research(upgrade, false);
+        }
+    }
+
+    fn build(&mut self, builder: u64, unit_type: UnitTypeId, location: Point2) {
+        if let Some(unit) = self.bot.units.my.workers.get(builder) {
+            unit.build(unit_type, location, false);
+        }
+    }
+
+    fn build_gas(&mut self, builder: u64, geyser: u64) {
+        if let Some(unit) = self.bot.units.my.workers.get(builder) {
+            unit.build_gas(geyser, false);
+        }
+    }
+
+    fn use_ability(&mut self, unit: u64, ability: AbilityId) {
+        if let Some(unit) = self.bot.units.my.all.get(unit) {
+            unit.use_ability(ability, false);
+        }
+    }
+
+    fn gather(&mut self, unit: u64, resource: u64) {
+        if let Some(unit) = self.bot.units.my.all.get(unit) {
+            unit.order_gather(resource, false);
+        }
+    }
+
+    fn return_resource(&mut self, unit: u64) {
+        if let Some(unit) = self.bot.units.my.all.get(unit) {
+            unit.return_resource(false);
+        }
+    }
+
+    fn attack(&mut self, unit: u64, target: u64) {
+        if let Some(unit) = self.bot.units.my.all.get(unit) {
+            unit.order_attack(Target::Tag(target), false);
+        }
+    }
+
+    fn attack_move(&mut self, unit: u64, location: Point2) {
+        if let Some(unit) = self.bot.units.my.all.get(unit) {
+            unit.order_attack(Target::Pos(location), false);
+        }
+    }
+
+    fn move_to(&mut self, unit: u64, location: Point2) {
+        if let Some(unit) = self.bot.units.my.all.get(unit) {
+            unit.order_move_to(Target::Pos(location), 0.1f32, false);
+        }
+    }
+
+    fn rally_workers(&mut self, townhall: u64, resource: u64) {
+        if let Some(unit) = self.bot.units.my.townhalls.get(townhall) {
+            unit.command(AbilityId::RallyWorkers, Target::Tag(resource), false);
+        }
+    }
+
+    fn rally_units(&mut self, townhall: u64, location: Point2) {
+        if let Some(unit) = self.bot.units.my.townhalls.get(townhall) {
+            unit.command(AbilityId::RallyUnits, Target::Pos(location), false);
+        }
+    }
+
+    fn spend(&mut self, unit_type: UnitTypeId, use_supply: bool) {
+        self.bot.subtract_resources(unit_type, use_supply);
+    }
+
+    fn spend_upgrade(&mut self, upgrade: UpgradeId) {
+        self.bot.subtract_upgrade_cost(upgrade);
+    }
+
+    fn chat(&mut self, message: &str) {
+        self.bot.chat_ally(message);
+    }
+}
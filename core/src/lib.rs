@@ -1,48 +1,196 @@
+pub mod bus;
 pub mod command_queue;
+pub mod economy;
+pub mod expansions;
+#[cfg(any(test, feature = "testing"))]
+pub mod fake_game;
+pub mod game_view;
 pub mod managers;
-mod params;
+pub mod params;
+pub mod pipeline;
+pub mod placement;
+pub mod profiling;
+pub mod random;
+pub mod reservation;
+#[cfg(any(test, feature = "testing"))]
+pub mod scenario;
+pub mod trace;
 pub mod units;
 pub mod utils;
+pub mod wall;
 
+use crate::bus::MessageBus;
 use crate::command_queue::CommandQueue;
+use crate::economy::Economy;
+use crate::expansions::ThreatHistory;
 use crate::managers::cache_manager::UnitsCache;
 use crate::managers::squad_manager::Squads;
+use crate::managers::strategy_manager::Strategy;
+use crate::managers::summary_manager::MatchSummary;
+use crate::params::Params;
+use crate::profiling::StepStats;
+use crate::random::GameRng;
+use crate::reservation::Reservations;
+use crate::trace::Trace;
+use log::error;
 use rust_sc2::bot::Bot;
+use rust_sc2::geometry::Point2;
 use rust_sc2::Event;
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::time::Instant;
 
 pub trait Opening {
-    fn opening(&mut self, bot: &Bot, bot_state: &mut BotState);
+    fn opening(&mut self, bot: &mut Bot, bot_state: &mut BotState);
+
+    // Called every step after the opening was set up, for steps waiting on conditions.
+    fn on_step(&mut self, _bot: &mut Bot, _bot_state: &mut BotState) {}
+}
+
+// Runs the opening as a component, so a ProcessLimiter can contain its panics.
+// The opening is set up on the first process, later ones run its steps.
+pub struct OpeningComponent {
+    opening: Box<dyn Opening>,
+    started: bool,
+}
+
+impl OpeningComponent {
+    pub fn new(opening: Box<dyn Opening>) -> Self {
+        Self {
+            opening,
+            started: false,
+        }
+    }
+}
+
+impl AIComponent for OpeningComponent {
+    fn process(&mut self, bot: &mut Bot, bot_state: &mut BotState) {
+        // Not retried after a panic, the opening could be half queued
+        if !self.started {
+            self.started = true;
+            self.opening.opening(bot, bot_state);
+        }
+        self.opening.on_step(bot, bot_state);
+    }
 }
 
 pub struct ProcessLimiter {
+    name: String,
     delay: u32,
     last_loop: u32,
     component: Box<dyn AIComponent>,
+    failures: u32,
+    cooldown_until: u32,
+    deferrable: bool,
+    deferred_in_row: u32,
+    stats: StepStats,
 }
 
 impl ProcessLimiter {
-    pub fn new(delay: u32, component: Box<dyn AIComponent>) -> Self {
+    pub fn new(name: &str, delay: u32, component: Box<dyn AIComponent>) -> Self {
         Self {
+            name: name.to_string(),
             delay,
             last_loop: 0,
             component,
+            failures: 0,
+            cooldown_until: 0,
+            deferrable: false,
+            deferred_in_row: 0,
+            stats: StepStats::default(),
+        }
+    }
+
+    // Deferrable components are skipped when the step is over budget.
+    pub fn deferrable(mut self, deferrable: bool) -> Self {
+        self.deferrable = deferrable;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_deferrable(&self) -> bool {
+        self.deferrable
+    }
+
+    pub fn stats(&self) -> &StepStats {
+        &self.stats
+    }
+
+    pub fn is_due(&self, game_loop: u32, params: &Params) -> bool {
+        self.last_loop + self.delay <= game_loop
+            && game_loop >= self.cooldown_until
+            && !self.is_disabled(params)
+    }
+
+    // Not running now, the component stays due and runs on a later loop.
+    pub fn defer(&mut self) {
+        self.stats.deferred += 1;
+        self.deferred_in_row += 1;
+    }
+
+    // Deferred for too long, runs even when the step is over budget
+    pub fn is_starved(&self, params: &Params) -> bool {
+        self.deferred_in_row >= params.max_deferred_steps
+    }
+
+    pub fn is_disabled(&self, params: &Params) -> bool {
+        self.failures >= params.component_max_failures
+    }
+
+    // A panicking component is put on cooldown (longer each time) and disabled
+    // after too many failures, so it can't take the whole game down.
+    fn handle_failure(&mut self, cause: Box<dyn Any + Send>, game_loop: u32, params: &Params) {
+        let message = if let Some(message) = cause.downcast_ref::<&str>() {
+            message.to_string()
+        } else if let Some(message) = cause.downcast_ref::<String>() {
+            message.clone()
+        } else {
+            "unknown cause".to_string()
+        };
+        self.failures += 1;
+        error!(
+            "Component {} panicked at loop {} ({} failures): {}",
+            self.name, game_loop, self.failures, message
+        );
+        if self.is_disabled(params) {
+            error!("Component {} disabled", self.name);
+        } else {
+            self.cooldown_until = game_loop + params.component_failure_cooldown * self.failures;
         }
     }
 }
 
 impl AIComponent for ProcessLimiter {
     fn process(&mut self, bot: &mut Bot, bot_state: &mut BotState) {
-        let last_loop = self.last_loop;
         let game_loop = bot.state.observation.game_loop();
-        if last_loop + self.delay > game_loop {
+        if !self.is_due(game_loop, &bot_state.params) {
             return;
         }
         self.last_loop = game_loop;
-        self.component.process(bot, bot_state);
+        self.deferred_in_row = 0;
+        let component = &mut self.component;
+        let start = Instant::now();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| component.process(bot, bot_state)));
+        self.stats.record(start.elapsed());
+        if let Err(cause) = result {
+            self.handle_failure(cause, game_loop, &bot_state.params);
+        }
     }
 
     fn on_event(&mut self, event: &Event, bot_state: &mut BotState) {
-        self.component.on_event(event, bot_state)
+        if self.is_disabled(&bot_state.params) {
+            return;
+        }
+        let component = &mut self.component;
+        if let Err(cause) =
+            panic::catch_unwind(AssertUnwindSafe(|| component.on_event(event, bot_state)))
+        {
+            self.handle_failure(cause, self.last_loop, &bot_state.params);
+        }
     }
 }
 
@@ -53,7 +201,7 @@ pub trait AIComponent {
     fn on_event(&mut self, _: &Event, _: &mut BotState) {}
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum SpendingFocus {
     Economy,
     Balance,
@@ -70,7 +218,89 @@ impl Default for SpendingFocus {
 pub struct BotState {
     pub squads: Squads,
     pub build_queue: CommandQueue,
+    pub reservations: Reservations,
+    pub economy: Economy,
     pub enemy_cache: UnitsCache,
+    pub threats: ThreatHistory,
+    // Where new army units gather, chosen by the army logic
+    pub rally_point: Option<Point2>,
     pub spending_focus: SpendingFocus,
     pub minimum_strength: f32,
+    pub strategy: Strategy,
+    pub params: Params,
+    pub match_summary: MatchSummary,
+    pub rng: GameRng,
+    pub trace: Trace,
+    pub messages: MessageBus,
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_sc2::bot::Bot;
+
+    use crate::{AIComponent, BotState, Opening, OpeningComponent, ProcessLimiter};
+
+    struct FailingComponent {}
+
+    struct FailingOpening {}
+
+    impl Opening for FailingOpening {
+        fn opening(&mut self, _: &mut Bot, _: &mut BotState) {}
+
+        fn on_step(&mut self, _: &mut Bot, _: &mut BotState) {
+            panic!("Failing on purpose");
+        }
+    }
+
+    struct FailingSetup {}
+
+    impl Opening for FailingSetup {
+        fn opening(&mut self, _: &mut Bot, _: &mut BotState) {
+            panic!("Failing on purpose");
+        }
+    }
+
+    impl AIComponent for FailingComponent {
+        fn process(&mut self, _: &mut Bot, _: &mut BotState) {
+            panic!("Failing on purpose");
+        }
+    }
+
+    #[test]
+    fn panic_contained_and_disabled() {
+        let mut bot = Bot::default();
+        let mut bot_state = BotState::default();
+        bot_state.params.component_failure_cooldown = 0;
+        let mut limiter = ProcessLimiter::new("Failing", 0, Box::new(FailingComponent {}));
+        for _ in 0..bot_state.params.component_max_failures {
+            assert!(!limiter.is_disabled(&bot_state.params));
+            limiter.process(&mut bot, &mut bot_state);
+        }
+        assert!(limiter.is_disabled(&bot_state.params));
+    }
+
+    #[test]
+    fn opening_panic_contained() {
+        let mut bot = Bot::default();
+        let mut bot_state = BotState::default();
+        bot_state.params.component_failure_cooldown = 0;
+        let opening = OpeningComponent::new(Box::new(FailingOpening {}));
+        let mut limiter = ProcessLimiter::new("Opening", 0, Box::new(opening));
+        limiter.process(&mut bot, &mut bot_state);
+        assert!(!limiter.is_disabled(&bot_state.params));
+    }
+
+    #[test]
+    fn opening_setup_once() {
+        let mut bot = Bot::default();
+        let mut bot_state = BotState::default();
+        bot_state.params.component_failure_cooldown = 0;
+        let opening = OpeningComponent::new(Box::new(FailingSetup {}));
+        let mut limiter = ProcessLimiter::new("Opening", 0, Box::new(opening));
+        // Only the setup panics, it isn't run again on the next steps
+        for _ in 0..bot_state.params.component_max_failures {
+            limiter.process(&mut bot, &mut bot_state);
+        }
+        assert!(!limiter.is_disabled(&bot_state.params));
+    }
 }
@@ -1,14 +1,17 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 use log::debug;
 use rand::prelude::IteratorRandom;
-use rand::thread_rng;
 use rust_sc2::bot::Bot;
 use rust_sc2::prelude::*;
 use rust_sc2::units::Container;
+use serde_json::json;
 
+use crate::bus::{Message, Subscription};
 use crate::command_queue::Command;
-use crate::params::*;
+use crate::game_view::{snapshots, BotView, UnitSnapshot};
+use crate::managers::rally_manager::safe_waypoint;
+use crate::managers::squad_manager::Squad;
 use crate::utils::*;
 use crate::*;
 
@@ -23,9 +26,13 @@ enum UnitDecision {
 #[derive(Default)]
 pub struct ArmyManager {
     defending: bool,
+    worker_rush: bool,
+    expanding_to: Option<Point2>,
+    subscription: Subscription,
     money_engaging: bool,
     strength_engaging: bool,
     allowed_tech: HashSet<UnitTypeId>,
+    plan_tech: HashSet<UnitTypeId>,
     allied_decision: HashMap<u64, UnitDecision>,
     last_scout: f32,
     scouting_ling: Option<u64>,
@@ -33,7 +40,27 @@ pub struct ArmyManager {
 }
 
 impl ArmyManager {
-    fn army_unit_unlock(&mut self, bot: &mut Bot, bot_state: &BotState) {
+    fn read_messages(&mut self, bot_state: &BotState) {
+        for message in bot_state.messages.receive(&mut self.subscription) {
+            match message {
+                Message::WorkerRushDetected => self.worker_rush = true,
+                Message::ExpansionStarted(location) => self.expanding_to = Some(location),
+                Message::TechSwitchRequested => {
+                    // Only what the dropped plan unlocked, scouting reactions stay
+                    for unit_type in self.plan_tech.drain() {
+                        self.allowed_tech.remove(&unit_type);
+                    }
+                }
+                Message::BaseUnderAttack(_)
+                | Message::BuilderSent(_)
+                | Message::BuilderReleased(_)
+                | Message::ActionFailed { .. }
+                | Message::UnitMorphed { .. } => {}
+            }
+        }
+    }
+
+    fn army_unit_unlock(&mut self, bot: &mut Bot, bot_state: &BotState, enemies: &[UnitSnapshot]) {
         let workers = bot.counter().all().count(bot.race_values.worker);
         // for unit in bot.units.enemy.all.iter() {
         //     for counter in unit.type_id().countered_by() {
@@ -42,55 +69,77 @@ impl ArmyManager {
         //         }
         //     }
         // }
-        self.allowed_tech.insert(UnitTypeId::Zergling);
-
-        // Don't tech up if we're investing on producing an army
-        if bot_state.spending_focus == SpendingFocus::Army {
-            return;
-        }
-        if workers >= UNLOCK_ROACH_WORKERS
-            || bot_state
-                .enemy_cache
-                .units
-                .filter(|u| u.need_roaches())
-                .len()
-                > 2
-        {
-            self.unlock_tech(bot, UnitTypeId::Roach);
-        }
-        if workers >= UNLOCK_HYDRA_WORKERS
-            || !bot_state
-                .enemy_cache
-                .units
-                .filter(|u| u.is_flying() && u.can_attack_ground())
-                .is_empty()
-        {
-            self.unlock_tech(bot, UnitTypeId::Hydralisk);
+        for unit_type in self.update_allowed_tech(workers, enemies, bot_state) {
+            if bot_state.params.debug_text {
+                bot.chat_ally(format!("Unlocking {:?}", unit_type).as_str())
+            }
         }
-        if workers >= UNLOCK_LATE_TECH_WORKERS
-            || !bot_state
-                .enemy_cache
-                .units
-                .filter(|u| u.need_corruptors())
-                .is_empty()
-        {
-            self.unlock_tech(bot, UnitTypeId::Corruptor);
+    }
+
+    // Returns the newly allowed unit types
+    fn update_allowed_tech(
+        &mut self,
+        workers: usize,
+        enemies: &[UnitSnapshot],
+        bot_state: &BotState,
+    ) -> Vec<UnitTypeId> {
+        let mut unlocked = vec![];
+        // The opening follow up adds its composition, scouting reactions still apply
+        let plan_units = bot_state
+            .strategy
+            .active
+            .iter()
+            .flat_map(|plan| plan.composition.iter().map(|(unit_type, _)| *unit_type));
+        for unit_type in Self::reactive_tech(workers, enemies, bot_state) {
+            self.plan_tech.remove(&unit_type);
+            if self.allowed_tech.insert(unit_type) {
+                unlocked.push(unit_type);
+            }
         }
-        if workers >= UNLOCK_REALLY_LATE_TECH_WORKERS {
-            self.unlock_tech(bot, UnitTypeId::BroodLord);
+        for unit_type in plan_units {
+            if self.allowed_tech.insert(unit_type) {
+                self.plan_tech.insert(unit_type);
+                unlocked.push(unit_type);
+            }
         }
+        unlocked
     }
 
-    fn unlock_tech(&mut self, bot: &mut Bot, unit_type: UnitTypeId) {
-        if DEBUG_TEXT && !self.allowed_tech.contains(&unit_type) {
-            bot.chat_ally(format!("Unlocking {:?}", unit_type).as_str())
+    // Tech asked for by our worker count and the enemy units seen
+    fn reactive_tech(
+        workers: usize,
+        enemies: &[UnitSnapshot],
+        bot_state: &BotState,
+    ) -> Vec<UnitTypeId> {
+        let params = &bot_state.params;
+        let mut tech = vec![UnitTypeId::Zergling];
+        // Don't tech up if we're investing on producing an army
+        if bot_state.spending_focus != SpendingFocus::Army {
+            if workers >= params.unlock_roach_workers
+                || enemies.iter().filter(|u| u.need_roaches()).count() > 2
+            {
+                tech.push(UnitTypeId::Roach);
+            }
+            if workers >= params.unlock_hydra_workers
+                || enemies.iter().any(|u| u.is_flying && u.can_attack_ground)
+            {
+                tech.push(UnitTypeId::Hydralisk);
+            }
+            if workers >= params.unlock_late_tech_workers
+                || enemies.iter().any(|u| u.need_corruptors())
+            {
+                tech.push(UnitTypeId::Corruptor);
+            }
+            if workers >= params.unlock_really_late_tech_workers {
+                tech.push(UnitTypeId::BroodLord);
+            }
         }
-        self.allowed_tech.insert(unit_type);
+        tech
     }
 }
 
 impl ArmyManager {
-    fn micro(&mut self, bot: &mut Bot, bot_state: &BotState) {
+    fn micro(&mut self, bot: &mut Bot, bot_state: &mut BotState) {
         let mut my_army = Units::new();
         my_army.extend(bot.units.my.units.ready().of_type(UnitTypeId::Zergling));
         my_army.extend(bot.units.my.units.ready().of_type(UnitTypeId::Baneling));
@@ -125,8 +174,11 @@ impl ArmyManager {
         );
 
         // Defend our townhalls
-        let defense_range = if self.defending { 16f32 } else { 8f32 }
-            + 12f32.min(bot.owned_expansions().count() as f32 * 4f32);
+        let defense_range = if self.defending || self.worker_rush {
+            16f32
+        } else {
+            8f32
+        } + 12f32.min(bot.owned_expansions().count() as f32 * 4f32);
 
         if self.defending {
             my_army.extend(
@@ -155,13 +207,24 @@ impl ArmyManager {
             .map(|u| u.position())
             .collect::<Vec<Point2>>();
 
-        if defense_points.len() < 5 {
-            if let Some(next_expansion) = bot
-                .expansions
+        if let Some(location) = self.expanding_to {
+            if bot
+                .units
+                .my
+                .townhalls
                 .iter()
-                .find(|e| e.alliance.is_neutral())
-                .map(|e| e.loc)
+                .any(|t| t.is_closer(3f32, location))
             {
+                self.expanding_to = None;
+            }
+        }
+        if defense_points.len() < 5 {
+            if let Some(next_expansion) = self.expanding_to.or_else(|| {
+                bot.expansions
+                    .iter()
+                    .find(|e| e.alliance.is_neutral())
+                    .map(|e| e.loc)
+            }) {
                 defense_points.push(next_expansion);
             }
         }
@@ -171,6 +234,19 @@ impl ArmyManager {
                 .iter()
                 .any(|h| h.is_closer(defense_range, *e))
         });
+        for townhall in bot.units.my.townhalls.iter() {
+            if enemy_attack_force
+                .iter()
+                .any(|e| townhall.is_closer(defense_range, e.position()))
+            {
+                bot_state
+                    .messages
+                    .publish(Message::BaseUnderAttack(townhall.tag()));
+            }
+        }
+        if self.worker_rush && !enemy_attack_force.iter().any(|e| e.is_worker()) {
+            self.worker_rush = false;
+        }
 
         let mut priority_targets = Units::new();
         let mut secondary_targets = Units::new();
@@ -198,8 +274,10 @@ impl ArmyManager {
             && our_global_strength >= their_global_strength * 0.9f32)
             || our_global_strength >= their_global_strength * 1.3f32;
 
-        let engaging =
-            (self.money_engaging || self.strength_engaging) && self.can_be_aggressive(bot);
+        let engaging = match bot_state.strategy.timing_attack_ready(bot) {
+            Some(ready) => ready && self.can_be_aggressive(bot),
+            None => (self.money_engaging || self.strength_engaging) && self.can_be_aggressive(bot),
+        };
 
         for unit in priority_targets.iter() {
             let their_strength = priority_targets
@@ -239,15 +317,17 @@ impl ArmyManager {
             .units
             .ready()
             .of_type(UnitTypeId::Queen)
-            .filter(|u| u.energy().unwrap_or_default() > TRANSFUSION_MIN_ENERGY)
+            .filter(|u| u.energy().unwrap_or_default() > bot_state.params.transfusion_min_energy)
             .is_empty();
 
         if let Some(scouting_ling) = furthest_ling.as_ref() {
-            if self.last_scout + LING_ADVANCED_SCOUT_DELAY < bot.time {
-                let mut rng = thread_rng();
+            if self.last_scout + bot_state.params.ling_advanced_scout_delay < bot.time {
                 self.last_scout = bot.time;
                 self.scouting_ling = Some(scouting_ling.tag());
-                self.scouting_place = bot.enemy_expansions().choose(&mut rng).map(|u| u.loc);
+                self.scouting_place = bot
+                    .enemy_expansions()
+                    .choose(&mut bot_state.rng)
+                    .map(|u| u.loc);
             }
         }
         for unit in my_army.iter() {
@@ -293,12 +373,15 @@ impl ArmyManager {
             );
 
             let fallback = (unit.type_id() == UnitTypeId::Roach
-                && unit.hits_percentage().unwrap_or_default() < BURROW_HEALTH_PERCENTAGE)
+                && unit.hits_percentage().unwrap_or_default()
+                    < bot_state.params.burrow_health_percentage)
                 || (!unit.can_attack()
-                    && unit.hits_percentage().unwrap_or_default() < UNBURROW_HEALTH_PERCENTAGE)
+                    && unit.hits_percentage().unwrap_or_default()
+                        < bot_state.params.unburrow_health_percentage)
                 || (has_healing_queen
-                    && unit.base_strength(bot) >= RETREAT_BASE_STRENGTH
-                    && unit.hits_percentage().unwrap_or_default() < RETREAT_HEALTH_PERCENTAGE);
+                    && unit.base_strength(bot) >= bot_state.params.retreat_base_strength
+                    && unit.hits_percentage().unwrap_or_default()
+                        < bot_state.params.retreat_health_percentage);
 
             let defensive_unit = defense_points
                 .iter()
@@ -328,14 +411,25 @@ impl ArmyManager {
             self.allied_decision.insert(unit.tag(), decision);
         }
 
+        let gather_point = self.gather_point(bot);
+        let main_squad = bot_state
+            .squads
+            .squads
+            .iter()
+            .max_by(|a, b| a.squad.strength(bot).total_cmp(&b.squad.strength(bot)))
+            .cloned();
+        let enemies = bot_state.enemy_cache.snapshots();
+        let townhalls = snapshots(&bot.units.my.townhalls);
         for unit in my_army.iter() {
             let decision = *self.allied_decision.get(&unit.tag()).unwrap();
-            let avoid_burrow = (bot.detection_close_by(unit, BURROW_DETECTION_RANGE)
+            let avoid_burrow = (bot
+                .detection_close_by(unit, bot_state.params.burrow_detection_range)
                 || unit.is_revealed())
                 && !bot.has_upgrade(UpgradeId::TunnelingClaws);
             if unit.type_id() == UnitTypeId::Roach
                 && unit.has_ability(AbilityId::BurrowDownRoach)
-                && unit.hits_percentage().unwrap_or_default() < BURROW_HEALTH_PERCENTAGE
+                && unit.hits_percentage().unwrap_or_default()
+                    < bot_state.params.burrow_health_percentage
                 && !avoid_burrow
             {
                 unit.use_ability(AbilityId::BurrowDownRoach, false);
@@ -343,7 +437,8 @@ impl ArmyManager {
             } else if unit.type_id() == UnitTypeId::RoachBurrowed {
                 if unit.has_ability(AbilityId::BurrowUpRoach)
                     && (decision == UnitDecision::Advance
-                        && unit.hits_percentage().unwrap_or_default() >= UNBURROW_HEALTH_PERCENTAGE
+                        && unit.hits_percentage().unwrap_or_default()
+                            >= bot_state.params.unburrow_health_percentage
                         || avoid_burrow)
                 {
                     unit.use_ability(AbilityId::BurrowUpRoach, false);
@@ -360,7 +455,7 @@ impl ArmyManager {
                     .ready()
                     .of_type(UnitTypeId::Queen)
                     .filter(|u| {
-                        u.energy().unwrap_or_default() > TRANSFUSION_MIN_ENERGY
+                        u.energy().unwrap_or_default() > bot_state.params.transfusion_min_energy
                             && !u.position().is_closer(8f32, unit)
                     })
                     .closest(unit)
@@ -490,6 +585,14 @@ impl ArmyManager {
                 if let Some(target) = possible_target {
                     unit.order_attack(Target::Pos(target.position()), false);
                     final_target = Some(target.clone());
+                } else if let Some(waypoint) = Self::reinforcement_waypoint(
+                    &townhalls,
+                    bot_state,
+                    unit,
+                    main_squad.as_ref(),
+                    &enemies,
+                ) {
+                    unit.order_move_to(Target::Pos(waypoint), 2f32, false);
                 } else {
                     unit.order_attack(Target::Pos(attack_goal), false);
                 }
@@ -498,21 +601,10 @@ impl ArmyManager {
                     Self::move_towards(bot, unit, -2f32);
                 } else if let Some(ref queen) = healing_queen {
                     unit.order_move_to(Target::Pos(queen.position()), 5f32, false);
-                } else if !self.defending {
-                    if let Some(center) = bot.units.my.townhalls.center() {
-                        unit.order_move_to(
-                            Target::Pos(center.towards(bot.start_location, 1f32)),
-                            10f32,
-                            false,
-                        );
-                    }
-                } else if let Some(allied) = bot.units.my.townhalls.closest(bot.start_location) {
-                    unit.order_move_to(
-                        Target::Pos(allied.position().towards(bot.start_center, 7f32)),
-                        2f32,
-                        false,
-                    );
-                } else {
+                } else if let Some(gather) = gather_point {
+                    let distance = if self.defending { 2f32 } else { 10f32 };
+                    unit.order_move_to(Target::Pos(gather), distance, false);
+                } else if self.defending {
                     unit.order_move_to(Target::Pos(bot.start_location), 7f32, false);
                 }
             }
@@ -548,6 +640,52 @@ impl ArmyManager {
         }
     }
 
+    // Where retreating and newly produced units gather
+    fn gather_point(&self, bot: &Bot) -> Option<Point2> {
+        if self.defending {
+            bot.units
+                .my
+                .townhalls
+                .closest(bot.start_location)
+                .map(|t| t.position().towards(bot.start_center, 7f32))
+        } else {
+            bot.units
+                .my
+                .townhalls
+                .center()
+                .map(|c| c.towards(bot.start_location, 1f32))
+        }
+    }
+
+    // Units away from the main squad join it through a safe way instead of
+    // walking into the enemy on their own
+    fn reinforcement_waypoint(
+        townhalls: &[UnitSnapshot],
+        bot_state: &BotState,
+        unit: &Unit,
+        main_squad: Option<&Squad>,
+        enemies: &[UnitSnapshot],
+    ) -> Option<Point2> {
+        let squad = main_squad?;
+        if unit.is_flying()
+            || unit.type_id() == UnitTypeId::Queen
+            || squad.squad.contains_tag(unit.tag())
+        {
+            return None;
+        }
+        let center = squad.center();
+        if unit.is_closer(bot_state.params.reinforcement_distance, center) {
+            return None;
+        }
+        Some(safe_waypoint(
+            townhalls,
+            enemies,
+            unit.position(),
+            center,
+            bot_state.params.reinforcement_danger_range,
+        ))
+    }
+
     fn move_towards(bot: &Bot, unit: &Unit, multiplier: f32) {
         let center = if let Some(threat_center) = bot
             .units
@@ -591,11 +729,14 @@ impl ArmyManager {
             SpendingFocus::Balance => 4,
             SpendingFocus::Army => 6,
         };
-        let min_queens = MAX_QUEENS.min(bot.units.my.townhalls.len() + extra_queens);
+        let min_queens = bot_state
+            .params
+            .max_queens
+            .min(bot.units.my.townhalls.len() + extra_queens);
         bot_state.build_queue.push(
             Command::new_unit(UnitTypeId::Queen, min_queens, false),
             false,
-            PRIORITY_QUEEN,
+            bot_state.params.priority_queen,
         );
         if !bot
             .units
@@ -605,9 +746,9 @@ impl ArmyManager {
             .is_empty()
         {
             bot_state.build_queue.push(
-                Command::new_unit(UnitTypeId::Zergling, MIN_LINGS, false),
+                Command::new_unit(UnitTypeId::Zergling, bot_state.params.min_lings, false),
                 false,
-                PRIORITY_MIN_LINGS,
+                bot_state.params.priority_min_lings,
             );
         }
 
@@ -645,15 +786,24 @@ impl ArmyManager {
     ) -> HashMap<UnitTypeId, (usize, usize)> {
         let mut unit_distribution = HashMap::new();
 
-        for unit_type in self.allowed_tech.iter() {
-            let (weight, priority) = Self::unit_value(bot, bot_state, *unit_type);
+        let plan = bot_state.strategy.active.clone();
+        let unit_types = if let Some(plan) = &plan {
+            plan.composition.iter().map(|(t, _)| *t).collect::<Vec<_>>()
+        } else {
+            self.allowed_tech.iter().copied().collect::<Vec<_>>()
+        };
+        for unit_type in unit_types.iter() {
+            let (mut weight, priority) = Self::unit_value(bot, bot_state, *unit_type);
+            if let Some(plan_weight) = plan.as_ref().and_then(|p| p.unit_weight(*unit_type)) {
+                weight = plan_weight as isize;
+            }
             if unit_type.has_requirement(bot) {
                 unit_distribution.insert(*unit_type, (weight, priority));
             } else {
                 bot_state.build_queue.push(
                     Command::new_unit(*unit_type, 1, true),
                     false,
-                    priority + PRIORITY_ARMY_REQUIREMENT,
+                    priority + bot_state.params.priority_army_requirement,
                 );
             }
         }
@@ -714,7 +864,7 @@ impl ArmyManager {
             bot_state.build_queue.push(
                 Command::new_upgrade(UpgradeId::Zerglingmovementspeed, true),
                 false,
-                PRIORITY_LING_SPEED,
+                bot_state.params.priority_ling_speed,
             );
         }
         if bot_state.spending_focus == SpendingFocus::Army {
@@ -730,28 +880,32 @@ impl ArmyManager {
             );
         }
         let workers = bot.counter().all().count(bot.race_values.worker);
-        if workers >= UNLOCK_BURROW_WORKERS && bot.counter().all().count(UnitTypeId::Lair) > 0 {
+        if workers >= bot_state.params.unlock_burrow_workers
+            && bot.counter().all().count(UnitTypeId::Lair) > 0
+        {
             bot_state.build_queue.push(
                 Command::new_upgrade(UpgradeId::Burrow, true),
                 false,
-                PRIORITY_BURROW,
+                bot_state.params.priority_burrow,
             );
         }
-        if workers >= UNLOCK_TUNNELING_CLAWS_WORKERS {
+        if workers >= bot_state.params.unlock_tunneling_claws_workers {
             bot_state.build_queue.push(
                 Command::new_upgrade(UpgradeId::TunnelingClaws, true),
                 false,
-                PRIORITY_TUNNELING_CLAWS,
+                bot_state.params.priority_tunneling_claws,
             );
         }
-        if workers >= OVERLORD_SPEED_WORKERS && bot.counter().all().count(UnitTypeId::Lair) > 0 {
+        if workers >= bot_state.params.overlord_speed_workers
+            && bot.counter().all().count(UnitTypeId::Lair) > 0
+        {
             bot_state.build_queue.push(
                 Command::new_upgrade(
                     UpgradeId::Overlordspeed,
                     bot.can_afford_vespene_upgrade(UpgradeId::Overlordspeed),
                 ),
                 false,
-                PRIORITY_LORD_SPEED,
+                bot_state.params.priority_lord_speed,
             );
         }
         if bot.counter().all().count(UnitTypeId::Baneling) > 0 {
@@ -790,19 +944,20 @@ impl ArmyManager {
                     bot_state.spending_focus == SpendingFocus::Economy,
                 ),
                 false,
-                PRIORITY_EVOLUTION_CHAMBER,
+                bot_state.params.priority_evolution_chamber,
             );
         }
-        if workers >= UNLOCK_UPGRADES_WORKERS {
+        if workers >= bot_state.params.unlock_upgrades_workers {
             let melee_number = bot
                 .units
                 .my
                 .units
                 .filter(|u| u.is_melee() && !u.is_worker())
                 .len();
-            if melee_number > SAVE_FOR_ATTACK_UPGRADES_ON_UNITS
+            if melee_number > bot_state.params.save_for_attack_upgrades_on_units
                 && bot.upgrade_progress(UpgradeId::ZergGroundArmorsLevel1) > 0.1f32
             {
+                let priority = bot_state.params.priority_melee_weapon;
                 self.queue_upgrade(
                     bot,
                     bot_state,
@@ -811,7 +966,7 @@ impl ArmyManager {
                         UpgradeId::ZergMeleeWeaponsLevel2,
                         UpgradeId::ZergMeleeWeaponsLevel3,
                     ],
-                    PRIORITY_MELEE_WEAPON,
+                    priority,
                 );
             }
 
@@ -821,9 +976,10 @@ impl ArmyManager {
                 .units
                 .filter(|u| !u.is_flying() && !u.is_worker())
                 .len();
-            if ground_number > SAVE_FOR_DEFENSE_UPGRADES_ON_UNITS
+            if ground_number > bot_state.params.save_for_defense_upgrades_on_units
                 && bot.can_afford_vespene_upgrade(UpgradeId::ZergGroundArmorsLevel1)
             {
+                let priority = bot_state.params.priority_ground_armor;
                 self.queue_upgrade(
                     bot,
                     bot_state,
@@ -832,7 +988,7 @@ impl ArmyManager {
                         UpgradeId::ZergGroundArmorsLevel2,
                         UpgradeId::ZergGroundArmorsLevel3,
                     ],
-                    PRIORITY_GROUND_ARMOR,
+                    priority,
                 );
             }
 
@@ -842,9 +998,10 @@ impl ArmyManager {
                 .units
                 .filter(|u| !u.is_melee() && !u.is_worker())
                 .len();
-            if ranged_number > SAVE_FOR_ATTACK_UPGRADES_ON_UNITS
+            if ranged_number > bot_state.params.save_for_attack_upgrades_on_units
                 && bot.upgrade_progress(UpgradeId::ZergGroundArmorsLevel1) > 0.1f32
             {
+                let priority = bot_state.params.priority_missile_weapon;
                 self.queue_upgrade(
                     bot,
                     bot_state,
@@ -853,13 +1010,14 @@ impl ArmyManager {
                         UpgradeId::ZergMissileWeaponsLevel2,
                         UpgradeId::ZergMissileWeaponsLevel3,
                     ],
-                    PRIORITY_MISSILE_WEAPON,
+                    priority,
                 );
             }
 
             if bot.counter().count(UnitTypeId::GreaterSpire) > 0 {
                 let flying_number = bot.units.my.units.filter(|u| u.is_flying()).len();
-                if flying_number > SAVE_FOR_DEFENSE_UPGRADES_ON_UNITS {
+                if flying_number > bot_state.params.save_for_defense_upgrades_on_units {
+                    let priority = bot_state.params.priority_flyer_armor;
                     self.queue_upgrade(
                         bot,
                         bot_state,
@@ -868,10 +1026,11 @@ impl ArmyManager {
                             UpgradeId::ZergFlyerArmorsLevel2,
                             UpgradeId::ZergFlyerArmorsLevel3,
                         ],
-                        PRIORITY_FLYER_ARMOR,
+                        priority,
                     );
                 }
-                if flying_number > SAVE_FOR_ATTACK_UPGRADES_ON_UNITS {
+                if flying_number > bot_state.params.save_for_attack_upgrades_on_units {
+                    let priority = bot_state.params.priority_flyer_weapon;
                     self.queue_upgrade(
                         bot,
                         bot_state,
@@ -880,7 +1039,7 @@ impl ArmyManager {
                             UpgradeId::ZergFlyerWeaponsLevel2,
                             UpgradeId::ZergFlyerWeaponsLevel3,
                         ],
-                        PRIORITY_FLYER_WEAPON,
+                        priority,
                     );
                 }
             }
@@ -919,6 +1078,32 @@ impl ArmyManager {
         }
     }
 
+    fn trace(&self, bot: &Bot, bot_state: &mut BotState) {
+        if !bot_state.trace.is_enabled() {
+            return;
+        }
+        let decisions = self
+            .allied_decision
+            .iter()
+            .filter(|(tag, _)| bot.units.my.units.contains_tag(**tag))
+            .map(|(tag, decision)| (*tag, format!("{:?}", decision)))
+            .collect::<BTreeMap<u64, String>>();
+        let units = decisions.keys().copied().collect();
+        let data = json!({
+            "defending": self.defending,
+            "worker_rush": self.worker_rush,
+            "money_engaging": self.money_engaging,
+            "strength_engaging": self.strength_engaging,
+            "decisions": decisions,
+        });
+        bot_state.trace.record(
+            bot.state.observation.game_loop(),
+            "ArmyManager",
+            units,
+            data,
+        );
+    }
+
     fn can_be_aggressive(&self, bot: &Bot) -> bool {
         (bot.units.my.units.of_type(UnitTypeId::Zergling).is_empty()
             || bot.enemy_race != Race::Zerg
@@ -930,9 +1115,77 @@ impl ArmyManager {
 
 impl AIComponent for ArmyManager {
     fn process(&mut self, bot: &mut Bot, bot_state: &mut BotState) {
-        self.army_unit_unlock(bot, bot_state);
+        self.read_messages(bot_state);
+        let enemies = bot_state.enemy_cache.snapshots();
+        bot_state.threats.record(&BotView::new(bot), &enemies);
+        self.army_unit_unlock(bot, bot_state, &enemies);
         self.queue_upgrades(bot, bot_state);
         self.queue_units(bot, bot_state);
         self.micro(bot, bot_state);
+        bot_state.rally_point = self.gather_point(bot);
+        self.trace(bot, bot_state);
+    }
+
+    fn on_event(&mut self, event: &Event, _: &mut BotState) {
+        if let Event::RandomRaceDetected(race) = event {
+            debug!("Random opponent is {:?}", race);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_sc2::prelude::*;
+
+    use crate::bus::Message;
+    use crate::game_view::UnitSnapshot;
+    use crate::managers::army_manager::ArmyManager;
+    use crate::managers::strategy_manager::StrategyPlan;
+    use crate::BotState;
+
+    #[test]
+    fn plan_keeps_scouting_reactions() {
+        let mut bot_state = BotState::default();
+        bot_state.strategy.active =
+            Some(StrategyPlan::new("Macro", 240f32).with_unit(UnitTypeId::Zergling, 1));
+        let mut manager = ArmyManager::default();
+        let mut enemies = vec![];
+        assert_eq!(
+            manager.update_allowed_tech(16, &enemies, &bot_state),
+            vec![UnitTypeId::Zergling]
+        );
+
+        for tag in 1..=3 {
+            enemies.push(UnitSnapshot::new(
+                tag,
+                UnitTypeId::Roach,
+                Point2::new(100f32, 100f32),
+            ));
+        }
+        assert_eq!(
+            manager.update_allowed_tech(16, &enemies, &bot_state),
+            vec![UnitTypeId::Roach]
+        );
+    }
+
+    #[test]
+    fn switch_drops_only_plan_tech() {
+        let mut bot_state = BotState::default();
+        bot_state.strategy.active =
+            Some(StrategyPlan::new("Hydras", 300f32).with_unit(UnitTypeId::Hydralisk, 10));
+        let mut manager = ArmyManager::default();
+        let enemies = (1..=3)
+            .map(|tag| UnitSnapshot::new(tag, UnitTypeId::Roach, Point2::new(100f32, 100f32)))
+            .collect::<Vec<_>>();
+        manager.update_allowed_tech(16, &enemies, &bot_state);
+        assert!(manager.allowed_tech.contains(&UnitTypeId::Hydralisk));
+
+        bot_state.strategy.active = None;
+        bot_state.messages.start_step(10);
+        bot_state.messages.publish(Message::TechSwitchRequested);
+        manager.read_messages(&bot_state);
+        assert!(manager.allowed_tech.contains(&UnitTypeId::Zergling));
+        assert!(manager.allowed_tech.contains(&UnitTypeId::Roach));
+        assert!(!manager.allowed_tech.contains(&UnitTypeId::Hydralisk));
     }
 }
@@ -5,6 +5,8 @@ use rust_sc2::bot::Bot;
 use rust_sc2::prelude::*;
 use rust_sc2::Event::UnitDestroyed;
 
+use crate::bus::Message;
+use crate::game_view::{snapshots, UnitSnapshot};
 use crate::utils::DetectionCloseBy;
 use crate::{AIComponent, BotState};
 
@@ -20,6 +22,10 @@ impl UnitsCache {
     const ON_DETECTION_CACHE_TIME: f32 = 1f32;
     const TACTICAL_JUMP_CACHE_TIME: f32 = 4f32;
 
+    pub fn snapshots(&self) -> Vec<UnitSnapshot> {
+        snapshots(&self.units)
+    }
+
     pub fn destroy_unit(&mut self, tag: u64) {
         if self.cache.contains_key(&tag) {
             debug!("Unit [{tag:?}] destroyed")
@@ -73,16 +79,56 @@ impl CacheEntry {
 }
 
 #[derive(Default)]
-pub struct CacheManager {}
+pub struct CacheManager {
+    own_types: HashMap<u64, UnitTypeId>,
+}
+
+impl CacheManager {
+    // The game has no morph event, type changes are found by comparing steps.
+    fn check_morphs(&mut self, bot: &Bot, bot_state: &mut BotState) {
+        for unit in bot.units.my.all.iter() {
+            if let Some(from) = self.own_types.insert(unit.tag(), unit.type_id()) {
+                if from != unit.type_id() {
+                    debug!(
+                        "Unit [{:?}] morphed {:?} -> {:?}",
+                        unit.tag(),
+                        from,
+                        unit.type_id()
+                    );
+                    bot_state.messages.publish(Message::UnitMorphed {
+                        tag: unit.tag(),
+                        from,
+                        to: unit.type_id(),
+                    });
+                }
+            }
+        }
+    }
+
+    // Errors are only in the observation right after the command, components
+    // running less often read them from the bus.
+    fn publish_action_errors(&self, bot: &Bot, bot_state: &mut BotState) {
+        for error in bot.state.action_errors.iter() {
+            bot_state.messages.publish(Message::ActionFailed {
+                unit: error.unit,
+                ability: error.ability,
+                reason: format!("{:?}", error.result),
+            });
+        }
+    }
+}
 
 impl AIComponent for CacheManager {
     fn process(&mut self, bot: &mut Bot, bot_state: &mut BotState) {
         bot_state.enemy_cache.check_unit_cache(bot);
+        self.check_morphs(bot, bot_state);
+        self.publish_action_errors(bot, bot_state);
     }
 
     fn on_event(&mut self, event: &Event, bot_state: &mut BotState) {
         if let UnitDestroyed(tag, _) = event {
             bot_state.enemy_cache.destroy_unit(*tag);
+            self.own_types.remove(tag);
         }
     }
 }
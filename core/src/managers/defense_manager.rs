@@ -2,7 +2,6 @@ use rust_sc2::bot::Bot;
 use rust_sc2::prelude::*;
 
 use crate::command_queue::Command;
-use crate::params::PRIORITY_SPORE_CRAWLER;
 use crate::*;
 
 #[derive(Default)]
@@ -48,7 +47,7 @@ impl DefenseManager {
             bot_state.build_queue.push(
                 Command::new_unit(UnitTypeId::SporeCrawler, spores, true),
                 false,
-                PRIORITY_SPORE_CRAWLER,
+                bot_state.params.priority_spore_crawler,
             );
         };
     }
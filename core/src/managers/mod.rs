@@ -2,6 +2,9 @@ pub mod army_manager;
 pub mod cache_manager;
 pub mod defense_manager;
 pub mod production_manager;
+pub mod rally_manager;
 pub mod resource_manager;
 pub mod squad_manager;
+pub mod strategy_manager;
+pub mod summary_manager;
 pub mod worker_manager;
@@ -1,20 +1,69 @@
-use log::debug;
+use itertools::Itertools;
+use log::{debug, warn};
 use rust_sc2::bot::Bot;
 use rust_sc2::prelude::*;
-use std::collections::HashSet;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
 
+use crate::bus::{Message, Subscription};
 use crate::command_queue::Command;
 use crate::command_queue::Command::*;
+use crate::expansions::{rank_expansions, ExpansionDistances};
+#[cfg(any(test, feature = "testing"))]
+use crate::fake_game::FakeGame;
+use crate::game_view::{center, closest, BotView, GameView};
+use crate::placement::{placement_candidates, policy_candidates, PlacementPolicy};
+use crate::reservation::{Funds, Reservation};
+#[cfg(any(test, feature = "testing"))]
+use crate::scenario::ScenarioComponent;
 use crate::utils::*;
+use crate::wall::{plan_natural_wall, WallPlan};
 use crate::*;
 
 #[derive(Default)]
 pub struct ProductionManager {
     producing: HashSet<u64>,
+    // Drones walking to where a structure will be placed
+    builders: HashMap<UnitTypeId, (u64, Point2)>,
+    // Spots picked for the structures builders may be sent ahead to
+    premove_locations: HashMap<UnitTypeId, Point2>,
+    subscription: Subscription,
+    // Build orders waiting for a possible rejection, by builder
+    issued_builds: HashMap<u64, IssuedBuild>,
+    // Placements rejected recently and the game time they can be used again
+    blacklist: Vec<(Point2, f32)>,
+    // Rejections in a row by structure type
+    build_failures: HashMap<UnitTypeId, u32>,
+    // Drones sent to build macro hatcheries, where and when
+    macro_builders: HashMap<u64, (Point2, f32)>,
+    // Free expansions best first, ranked once per step
+    expansion_ranking: Vec<Point2>,
+    expansion_distances: ExpansionDistances,
+    // Natural wall, planned once the opening asks for it
+    wall: Option<WallPlan>,
+    wall_planned: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct IssuedBuild {
+    unit_type: UnitTypeId,
+    location: Point2,
+    time: f32,
 }
 
 impl ProductionManager {
     const REQUIREMENT_QUEUE_PRIORITY: usize = 100_000;
+    // Unit speeds are per normal game second
+    const FASTER_SPEED: f32 = 1.4f32;
+    // Longest walk a builder is sent ahead for, in seconds
+    const MAX_PREMOVE_TRAVEL: f32 = 20f32;
+    // Rejections arrive on the next observation, older orders went through
+    const BUILD_CONFIRM_TIME: f32 = 2f32;
+    const RESOURCE_FAILURES: [&str; 3] = ["NotEnoughMinerals", "NotEnoughVespene", "NotEnoughFood"];
+    // Each placement check is a game query, only the best spots are tried
+    const PLACEMENT_QUERIES: usize = 10;
+    // Macro hatchery orders still without a hatchery are dropped after this
+    const MACRO_HATCH_TIMEOUT: f32 = 30f32;
 
     fn cancel_buildings(&self, bot: &mut Bot) {
         for structure in bot
@@ -30,8 +79,13 @@ impl ProductionManager {
         }
     }
 
-    fn produce_units(&mut self, bot: &mut Bot, bot_state: &mut BotState) {
-        bot_state.build_queue.check_completion(bot);
+    fn produce_units<G: GameView>(&mut self, game: &mut G, bot_state: &mut BotState) {
+        bot_state.build_queue.check_completion(game);
+        self.read_build_failures(game, bot_state);
+        bot_state.economy.sample(game);
+        bot_state.reservations.start_step(game.time());
+        self.plan_wall(game, bot_state);
+        self.rank_expansions(game, bot_state);
         self.producing.clear();
         for element in bot_state.build_queue.into_iter() {
             match element.command {
@@ -41,7 +95,7 @@ impl ProductionManager {
                     save_resources,
                 } => {
                     self.produce(
-                        bot,
+                        game,
                         bot_state,
                         unit_type,
                         wanted_amount,
@@ -53,65 +107,251 @@ impl ProductionManager {
                     upgrade,
                     save_resources,
                 } => {
-                    self.upgrade(bot, bot_state, upgrade, save_resources, element.priority);
+                    self.upgrade(game, bot_state, upgrade, save_resources, element.priority);
+                }
+                MacroHatchCommand {
+                    wanted_amount,
+                    save_resources,
+                } => {
+                    self.produce_macro_hatch(
+                        game,
+                        bot_state,
+                        wanted_amount,
+                        save_resources,
+                        element.priority,
+                    );
                 }
             }
         }
+        for starved in bot_state
+            .reservations
+            .starved(bot_state.params.reservation_starved_time)
+        {
+            debug!("Starved queue entry: {}", starved);
+        }
+    }
+
+    fn available_funds<G: GameView>(game: &G) -> Funds {
+        Funds {
+            minerals: game.minerals(),
+            vespene: game.vespene(),
+            larva: game.my_larvas().len() as u32,
+            supply: game.supply_left(),
+        }
+    }
+
+    // What is left after the reservations of higher priority entries
+    fn free_funds<G: GameView>(game: &G, bot_state: &BotState) -> Funds {
+        bot_state.reservations.free(Self::available_funds(game))
+    }
+
+    fn can_afford<G: GameView>(game: &G, bot_state: &BotState, unit_type: UnitTypeId) -> bool {
+        game.unit_cost(unit_type).map_or(false, |cost| {
+            Self::free_funds(game, bot_state).covers(&cost, 0, true)
+        })
+    }
+
+    fn can_afford_upgrade<G: GameView>(game: &G, bot_state: &BotState, upgrade: UpgradeId) -> bool {
+        Self::free_funds(game, bot_state).covers(&game.upgrade_cost(upgrade), 0, false)
+    }
+
+    // Maps rejected build orders back to the structure, so the next attempt
+    // looks somewhere else.
+    fn read_build_failures<G: GameView>(&mut self, game: &G, bot_state: &mut BotState) {
+        let time = game.time();
+        for message in bot_state.messages.receive(&mut self.subscription) {
+            if let Message::ActionFailed {
+                unit,
+                ability,
+                reason,
+            } = message
+            {
+                if Self::RESOURCE_FAILURES.contains(&reason.as_str()) {
+                    continue;
+                }
+                if let Some(build) = self.issued_builds.remove(&unit) {
+                    self.blacklist.push((
+                        build.location,
+                        time + bot_state.params.placement_blacklist_time,
+                    ));
+                    let failures = self.build_failures.entry(build.unit_type).or_default();
+                    *failures += 1;
+                    if *failures >= bot_state.params.build_failures_reported {
+                        warn!(
+                            "{:?} rejected {} times in a row, last at {:?} ({:?} {})",
+                            build.unit_type, failures, build.location, ability, reason
+                        );
+                    } else {
+                        debug!(
+                            "{:?} rejected at {:?}: {}",
+                            build.unit_type, build.location, reason
+                        );
+                    }
+                }
+            }
+        }
+        let confirmed = self
+            .issued_builds
+            .iter()
+            .filter(|(_, build)| build.time + Self::BUILD_CONFIRM_TIME < time)
+            .map(|(builder, build)| (*builder, build.unit_type))
+            .collect::<Vec<_>>();
+        for (builder, unit_type) in confirmed {
+            self.issued_builds.remove(&builder);
+            self.build_failures.remove(&unit_type);
+        }
+        self.blacklist.retain(|(_, until)| *until > time);
+    }
+
+    fn is_blacklisted(&self, location: Point2) -> bool {
+        self.blacklist
+            .iter()
+            .any(|(spot, _)| spot.distance(location) < 1f32)
+    }
+
+    // Placement near a spot, turning around `pivot` when the closest ones were
+    // rejected recently
+    fn find_placement<G: GameView>(
+        &self,
+        game: &G,
+        unit_type: UnitTypeId,
+        near: Point2,
+        pivot: Point2,
+        max_distance: u32,
+        step: u32,
+    ) -> Option<Point2> {
+        (0..8)
+            .filter_map(|turn| {
+                let (sin, cos) = (turn as f32 * std::f32::consts::FRAC_PI_4).sin_cos();
+                let offset = near - pivot;
+                let near = pivot
+                    + Point2::new(
+                        offset.x * cos - offset.y * sin,
+                        offset.x * sin + offset.y * cos,
+                    );
+                game.find_placement(unit_type, near, max_distance, step)
+            })
+            .find(|location| !self.is_blacklisted(*location))
+    }
+
+    fn issue_build<G: GameView>(
+        &mut self,
+        game: &mut G,
+        builder: u64,
+        unit_type: UnitTypeId,
+        location: Point2,
+    ) {
+        game.build(builder, unit_type, location);
+        game.spend(unit_type, false);
+        self.issued_builds.insert(
+            builder,
+            IssuedBuild {
+                unit_type,
+                location,
+                time: game.time(),
+            },
+        );
     }
 
     // TODO: Check if we have tech to produce (order it if we don't)
     // TODO: Check if we have building to produce (order it if we don't)
-    fn produce(
-        &self,
-        bot: &mut Bot,
+    fn produce<G: GameView>(
+        &mut self,
+        game: &mut G,
         bot_state: &mut BotState,
         unit_type: UnitTypeId,
         wanted_amount: usize,
         save_resources: bool,
         priority: usize,
     ) {
-        if bot.counter().all().count(unit_type) >= wanted_amount {
+        if game.count_all(unit_type) >= wanted_amount {
             return;
-        } else if !bot.can_afford(unit_type, true) {
-            self.save_unit_resources(bot, bot_state, unit_type, save_resources, true, true);
+        } else if !Self::can_afford(game, bot_state, unit_type) {
+            self.save_unit_resources(game, bot_state, unit_type, save_resources, priority);
             return;
         }
-        if self.missing_unit_requirements(bot, bot_state, unit_type, save_resources, priority) {
+        if self.missing_unit_requirements(game, bot_state, unit_type, save_resources, priority) {
             return;
         }
         let upgrade_ability = unit_type.morph_ability();
         if upgrade_ability.is_some() {
-            self.morph_upgrade(bot, bot_state, unit_type, save_resources);
+            self.morph_upgrade(game, bot_state, unit_type, save_resources);
         } else if unit_type.is_structure() {
-            self.build(bot, bot_state, unit_type, wanted_amount);
+            self.build(game, bot_state, unit_type, wanted_amount);
         } else {
-            let current_amount = bot.counter().all().count(unit_type);
+            let current_amount = game.count_all(unit_type);
             for _ in current_amount..wanted_amount {
-                if !bot.can_afford(unit_type, true) {
-                    self.save_unit_resources(bot, bot_state, unit_type, save_resources, true, true);
+                if !Self::can_afford(game, bot_state, unit_type) {
+                    self.save_unit_resources(game, bot_state, unit_type, save_resources, priority);
                     break;
                 }
-                self.produce_unit(bot, bot_state, unit_type, save_resources);
+                self.produce_unit(game, bot_state, unit_type, save_resources, priority);
             }
         }
     }
 
-    fn missing_unit_requirements(
+    fn produce_macro_hatch<G: GameView>(
+        &mut self,
+        game: &mut G,
+        bot_state: &mut BotState,
+        wanted_amount: usize,
+        save_resources: bool,
+        priority: usize,
+    ) {
+        let townhall = game.townhall_type();
+        let halls = game.macro_townhalls();
+        let time = game.time();
+        let rejected = self
+            .macro_builders
+            .values()
+            .filter(|(location, _)| self.is_blacklisted(*location))
+            .map(|(location, _)| *location)
+            .collect::<Vec<_>>();
+        self.macro_builders.retain(|builder, (location, since)| {
+            time - *since < Self::MACRO_HATCH_TIMEOUT
+                && !rejected.contains(location)
+                && game.unit(*builder).map_or(false, |u| u.is_worker())
+                && !halls.iter().any(|h| h.distance(*location) < 1f32)
+        });
+        if halls.len() + self.macro_builders.len() >= wanted_amount {
+            return;
+        } else if !Self::can_afford(game, bot_state, townhall) {
+            self.save_unit_resources(game, bot_state, townhall, save_resources, priority);
+            return;
+        }
+        if let Some(location) = self.macro_hatch_location(game) {
+            if let Some(builder) = self.get_builder(game, location) {
+                debug!("Placing a macro {:?} at {:?}", townhall, location);
+                self.issue_build(game, builder, townhall, location);
+                self.macro_builders.insert(builder, (location, time));
+            } else {
+                debug!("Can't find a builder");
+            }
+        } else {
+            debug!("No macro hatchery location");
+        }
+    }
+
+    fn has_requirement<G: GameView>(game: &G, requirements: &[UnitTypeId]) -> bool {
+        requirements.is_empty() || game.has_ready(requirements)
+    }
+
+    fn missing_unit_requirements<G: GameView>(
         &self,
-        bot: &Bot,
+        game: &G,
         bot_state: &mut BotState,
         unit_type: UnitTypeId,
         save_resources: bool,
         priority: usize,
     ) -> bool {
-        let has_requirement = unit_type.has_requirement(bot);
+        let has_requirement = Self::has_requirement(game, &unit_type.building_requirements());
         if has_requirement {
             return false;
         }
 
         if let Some(requirement) = unit_type.building_requirements().first() {
             if !self.missing_unit_requirements(
-                bot,
+                game,
                 bot_state,
                 *requirement,
                 save_resources,
@@ -127,22 +367,22 @@ impl ProductionManager {
         true
     }
 
-    fn missing_upgrade_requirements(
+    fn missing_upgrade_requirements<G: GameView>(
         &self,
-        bot: &Bot,
+        game: &G,
         bot_state: &mut BotState,
         upgrade_id: UpgradeId,
         save_resources: bool,
         priority: usize,
     ) -> bool {
-        let has_requirement = upgrade_id.has_requirement(bot);
+        let has_requirement = Self::has_requirement(game, &upgrade_id.building_requirements());
         if has_requirement {
             return false;
         }
 
         if let Some(requirement) = upgrade_id.building_requirements().first() {
             if !self.missing_unit_requirements(
-                bot,
+                game,
                 bot_state,
                 *requirement,
                 save_resources,
@@ -158,42 +398,40 @@ impl ProductionManager {
         true
     }
 
-    fn produce_unit(
+    fn produce_unit<G: GameView>(
         &self,
-        bot: &mut Bot,
+        game: &mut G,
         bot_state: &mut BotState,
         unit_type: UnitTypeId,
         save_resources: bool,
+        priority: usize,
     ) {
         let produced_on = unit_type.produced_on();
         if produced_on.contains(&UnitTypeId::Larva) {
-            let larvas = bot.units.my.larvas.clone();
-            let larva = if unit_type.is_worker() || unit_type == UnitTypeId::Overlord {
-                larvas.first()
+            let larvas = game.my_larvas();
+            let larva = if Self::free_funds(game, bot_state).larva == 0 {
+                None
+            } else if unit_type.is_worker() || unit_type == UnitTypeId::Overlord {
+                larvas.first().map(|u| u.tag)
             } else {
-                larvas.closest(bot.start_location)
+                closest(larvas.iter(), game.start_location()).map(|u| u.tag)
             };
             if let Some(larva) = larva {
                 debug!("training a {:?} at {:?}", unit_type, produced_on);
-                bot.units.my.larvas.remove(larva.tag());
-                larva.train(unit_type, false);
-                bot.subtract_resources(unit_type, true);
+                game.train(larva, unit_type);
+                game.spend(unit_type, true);
             }
-        } else if let Some(train_at) = bot
-            .units
-            .my
-            .structures
-            .ready()
-            .of_types(&produced_on)
-            .almost_idle()
-            .first()
+        } else if let Some(train_at) = game
+            .my_structures()
+            .iter()
+            .find(|u| u.is_ready() && produced_on.contains(&u.type_id) && u.is_almost_idle)
         {
-            if self.producing.contains(&train_at.tag()) {
-                self.save_unit_resources(bot, bot_state, unit_type, save_resources, true, true);
+            if self.producing.contains(&train_at.tag) {
+                self.save_unit_resources(game, bot_state, unit_type, save_resources, priority);
             } else {
                 debug!("training a {:?} at {:?}", unit_type, produced_on);
-                train_at.train(unit_type, false);
-                bot.subtract_resources(unit_type, true);
+                game.train(train_at.tag, unit_type);
+                game.spend(unit_type, true);
             }
         } else {
             debug!(
@@ -209,48 +447,45 @@ impl ProductionManager {
         }
     }
 
-    fn upgrade(
+    fn upgrade<G: GameView>(
         &mut self,
-        bot: &mut Bot,
+        game: &mut G,
         bot_state: &mut BotState,
         upgrade: UpgradeId,
         save_resources: bool,
         priority: usize,
     ) {
-        if !bot.can_afford_vespene_upgrade(upgrade) {
+        if game.vespene() < game.upgrade_cost(upgrade).vespene {
             return;
         }
-        if bot.is_ordered_upgrade(upgrade) {
+        if game.is_ordered_upgrade(upgrade) {
             return;
         }
         let produced_on = upgrade.produced_on();
-        if bot.can_afford_upgrade(upgrade) {
-            if self.missing_upgrade_requirements(bot, bot_state, upgrade, save_resources, priority)
+        if Self::can_afford_upgrade(game, bot_state, upgrade) {
+            if self.missing_upgrade_requirements(game, bot_state, upgrade, save_resources, priority)
             {
                 return;
             }
-            if let Some(building) = bot
-                .units
-                .my
-                .structures
-                .of_types(&produced_on)
-                .almost_idle()
-                .first()
+            if let Some(building) = game
+                .my_structures()
+                .iter()
+                .find(|u| produced_on.contains(&u.type_id) && u.is_almost_idle)
             {
-                if !self.producing.contains(&building.tag()) {
-                    building.research(upgrade, false);
-                    self.producing.insert(building.tag());
+                if !self.producing.contains(&building.tag) {
+                    game.research(building.tag, upgrade);
+                    self.producing.insert(building.tag);
                 }
-                bot.subtract_upgrade_cost(upgrade);
+                game.spend_upgrade(upgrade);
             }
         } else if save_resources {
-            self.save_upgrade_cost(bot, bot_state, upgrade);
+            self.save_upgrade_cost(game, bot_state, upgrade, priority);
         }
     }
 
-    fn morph_upgrade(
+    fn morph_upgrade<G: GameView>(
         &self,
-        bot: &mut Bot,
+        game: &mut G,
         bot_state: &mut BotState,
         unit_type: UnitTypeId,
         save_resources: bool,
@@ -260,19 +495,20 @@ impl ProductionManager {
             return;
         }
         let produced_on = unit_type.produced_on();
-        if let Some(unit) = bot
-            .units
-            .my
-            .all
-            .of_types(&produced_on)
-            .closest(bot.start_location)
-        {
+        let candidate = closest(
+            game.my_units()
+                .iter()
+                .filter(|u| produced_on.contains(&u.type_id)),
+            game.start_location(),
+        )
+        .map(|u| u.tag);
+        if let Some(unit) = candidate {
             debug!(
                 "Morphing a {:?} from {:?} using {:?}",
                 unit_type, produced_on, upgrade_ability
             );
-            unit.use_ability(upgrade_ability.unwrap(), false);
-            bot.subtract_resources(unit_type, false);
+            game.use_ability(unit, upgrade_ability.unwrap());
+            game.spend(unit_type, false);
         } else {
             debug!(
                 "No unit to produce [{:?}], pushing one [{:?}] to the queue",
@@ -287,52 +523,241 @@ impl ProductionManager {
         }
     }
 
-    fn get_builder(&self, bot: &Bot, pos: Point2) -> Option<Unit> {
-        let result = bot
-            .units
-            .my
-            .workers
+    fn get_builder<G: GameView>(&self, game: &G, pos: Point2) -> Option<u64> {
+        closest(
+            game.my_workers()
+                .iter()
+                .filter(|u| !(u.is_constructing || u.is_returning || u.is_carrying_resource)),
+            pos,
+        )
+        .map(|u| u.tag)
+    }
+
+    // The drone sent ahead for this structure if it's still around
+    fn builder<G: GameView>(&self, game: &G, unit_type: UnitTypeId, pos: Point2) -> Option<u64> {
+        self.builders
+            .get(&unit_type)
+            .map(|(tag, _)| *tag)
+            .filter(|tag| game.unit(*tag).map_or(false, |u| u.is_worker()))
+            .or_else(|| self.get_builder(game, pos))
+    }
+
+    // Expansions are hidden away from the enemy when our army is behind
+    fn rank_expansions<G: GameView>(&mut self, game: &G, bot_state: &BotState) {
+        let our_strength = game.strength(
+            game.my_units()
+                .iter()
+                .filter(|u| !u.is_worker() && !u.is_structure()),
+        );
+        let hidden = bot_state.threats.enemy_army_strength()
+            > our_strength * bot_state.params.hidden_expansion_strength_ratio;
+        self.expansion_ranking = rank_expansions(
+            game,
+            &bot_state.threats,
+            &mut self.expansion_distances,
+            &bot_state.params,
+            hidden,
+        );
+    }
+
+    fn expansion_location(&self) -> Option<Point2> {
+        self.expansion_ranking
             .iter()
-            .filter(|u| !(u.is_constructing() || u.is_returning() || u.is_carrying_resource()))
-            .closest(pos);
-        result.cloned()
+            .copied()
+            .find(|loc| !self.is_blacklisted(*loc))
     }
 
-    fn build(
+    fn plan_wall<G: GameView>(&mut self, game: &G, bot_state: &BotState) {
+        if bot_state.strategy.natural_wall && !self.wall_planned {
+            self.wall = plan_natural_wall(game);
+            self.wall_planned = true;
+            debug!("Natural wall {:?}", self.wall);
+        }
+    }
+
+    // Free wall position for the structure once the natural is ours
+    fn wall_location<G: GameView>(
         &self,
-        bot: &mut Bot,
+        game: &G,
         bot_state: &BotState,
         unit_type: UnitTypeId,
+    ) -> Option<Point2> {
+        let wall = self
+            .wall
+            .as_ref()
+            .filter(|_| bot_state.strategy.natural_wall)?;
+        if !game
+            .owned_expansions()
+            .iter()
+            .any(|e| e.loc == wall.natural)
+        {
+            return None;
+        }
+        let structures = game.my_structures();
+        wall.positions(unit_type).into_iter().find(|position| {
+            !self.is_blacklisted(*position)
+                && !structures.iter().any(|s| s.distance(*position) < 1.5f32)
+                && game.find_placement(unit_type, *position, 0, 1).is_some()
+        })
+    }
+
+    // First placeable spot the placement policy allows for the structure
+    fn policy_location<G: GameView>(&self, game: &G, unit_type: UnitTypeId) -> Option<Point2> {
+        placement_candidates(game, unit_type)
+            .into_iter()
+            .filter(|p| !self.is_blacklisted(*p))
+            .take(Self::PLACEMENT_QUERIES)
+            .find(|p| game.find_placement(unit_type, *p, 0, 1).is_some())
+    }
+
+    // Pocket of the main, next to the main toward the map center otherwise
+    fn macro_hatch_location<G: GameView>(&self, game: &G) -> Option<Point2> {
+        let townhall = game.townhall_type();
+        policy_candidates(game, PlacementPolicy::Pocket, townhall)
+            .into_iter()
+            .filter(|p| !self.is_blacklisted(*p))
+            .take(Self::PLACEMENT_QUERIES)
+            .find(|p| game.find_placement(townhall, *p, 0, 1).is_some())
+            .or_else(|| {
+                let main = game.start_location();
+                self.find_placement(
+                    game,
+                    townhall,
+                    main.towards(game.map_center(), 10f32),
+                    main,
+                    20,
+                    2,
+                )
+            })
+    }
+
+    fn structure_location<G: GameView>(
+        &self,
+        game: &G,
+        bot_state: &BotState,
+        unit_type: UnitTypeId,
+    ) -> Option<Point2> {
+        if game.townhall_type() == unit_type {
+            self.expansion_location()
+        } else if let Some(location) = self.wall_location(game, bot_state, unit_type) {
+            Some(location)
+        } else if let Some(location) = self.policy_location(game, unit_type) {
+            Some(location)
+        } else {
+            // TODO: improve default building placement
+            let base = closest(game.my_townhalls().iter(), game.start_location())
+                .map_or(game.start_location(), |f| f.position);
+            self.find_placement(
+                game,
+                unit_type,
+                base.towards(game.map_center(), 7f32),
+                base,
+                20,
+                2,
+            )
+        }
+    }
+
+    // Sends a drone ahead when a structure gets affordable by the time it walks
+    // to the placement, and back to mining once the plan changed.
+    fn premove_builders<G: GameView>(&mut self, game: &mut G, bot_state: &mut BotState) {
+        let estimates = bot_state.build_queue.time_to_afford(
+            game,
+            &bot_state.economy,
+            Self::available_funds(game),
+        );
+        let mut wanted: HashMap<UnitTypeId, (u64, Point2)> = HashMap::new();
+        let mut considered = HashSet::new();
+        let structures = game.my_structures();
+        for (element, estimate) in estimates {
+            let (unit_type, wanted_amount, wait) = match (element.command, estimate) {
+                (
+                    UnitCommand {
+                        unit_type,
+                        wanted_amount,
+                        ..
+                    },
+                    Some(wait),
+                ) => (unit_type, wanted_amount, wait),
+                _ => continue,
+            };
+            if !unit_type.is_structure()
+                || unit_type.morph_ability().is_some()
+                || unit_type.is_static_defense()
+                || game.gas_types().contains(&unit_type)
+                || wanted.contains_key(&unit_type)
+                || game.count_all(unit_type) >= wanted_amount
+                || !Self::has_requirement(game, &unit_type.building_requirements())
+                || wait > Self::MAX_PREMOVE_TRAVEL + bot_state.params.builder_premove_margin
+            {
+                continue;
+            }
+            considered.insert(unit_type);
+            let cached = self.premove_locations.get(&unit_type).copied().filter(|l| {
+                !self.is_blacklisted(*l) && !structures.iter().any(|s| s.distance(*l) < 1.5f32)
+            });
+            let location = cached.or_else(|| self.structure_location(game, bot_state, unit_type));
+            if let Some(location) = location {
+                self.premove_locations.insert(unit_type, location);
+                let builder = self
+                    .builder(game, unit_type, location)
+                    .filter(|tag| !wanted.values().any(|(b, _)| b == tag))
+                    .and_then(|tag| game.unit(tag));
+                if let Some(builder) = builder {
+                    let sent = self.builders.get(&unit_type).map(|(b, _)| *b) == Some(builder.tag);
+                    let travel = game
+                        .ground_distance(builder.position, location)
+                        .map_or(f32::MAX, |d| {
+                            d / (builder.speed * Self::FASTER_SPEED).max(1f32)
+                        });
+                    if sent || wait <= travel + bot_state.params.builder_premove_margin {
+                        wanted.insert(unit_type, (builder.tag, location));
+                    }
+                }
+            }
+        }
+        for (unit_type, (builder, _)) in self.builders.iter() {
+            if wanted.get(unit_type).map(|(b, _)| b) != Some(builder) {
+                debug!("Builder {} for {:?} back to mining", builder, unit_type);
+                bot_state
+                    .messages
+                    .publish(Message::BuilderReleased(*builder));
+            }
+        }
+        for (unit_type, (builder, location)) in wanted.iter() {
+            if self.builders.get(unit_type) != Some(&(*builder, *location)) {
+                debug!(
+                    "Sending {} ahead to build a {:?} at {:?}",
+                    builder, unit_type, location
+                );
+                bot_state.messages.publish(Message::BuilderSent(*builder));
+                game.move_to(*builder, *location);
+            }
+        }
+        self.builders = wanted;
+        self.premove_locations
+            .retain(|unit_type, _| considered.contains(unit_type));
+    }
+
+    fn build<G: GameView>(
+        &mut self,
+        game: &mut G,
+        bot_state: &mut BotState,
+        unit_type: UnitTypeId,
         wanted_amount: usize,
     ) {
         debug!("Trying to build {:?} {:?}", unit_type, wanted_amount);
         if unit_type.is_structure() {
-            if bot.race_values.gas == unit_type || bot.race_values.rich_gas == unit_type {
-                self.build_gas(bot);
-            } else if bot.race_values.start_townhall == unit_type {
-                self.build_expansion(bot, bot_state, unit_type);
+            if game.gas_types().contains(&unit_type) {
+                self.build_gas(game);
+            } else if game.townhall_type() == unit_type {
+                self.build_expansion(game, bot_state, unit_type);
             } else if unit_type.is_static_defense() {
-                self.build_static_defense(bot, unit_type);
-            } else if let Some(location) = bot.find_placement(
-                unit_type,
-                bot.units
-                    .my
-                    .townhalls
-                    .closest(bot.start_location)
-                    .map_or(bot.start_location, |f| f.position())
-                    .towards(bot.game_info.map_center, 7f32),
-                PlacementOptions {
-                    max_distance: 20,
-                    step: 2,
-                    random: false,
-                    addon: false,
-                },
-            ) {
+                self.build_static_defense(game, bot_state, unit_type);
+            } else if let Some(location) = self.structure_location(game, bot_state, unit_type) {
                 debug!("Placing a {:?} at {:?}", unit_type, location);
-                // TODO: improve default building placement
-                if let Some(builder) = self.get_builder(bot, location) {
-                    builder.build(unit_type, location, false);
-                    bot.subtract_resources(unit_type, false);
+                if let Some(builder) = self.builder(game, unit_type, location) {
+                    self.issue_build(game, builder, unit_type, location);
                 } else {
                     debug!("Can't find a builder");
                 }
@@ -340,30 +765,25 @@ impl ProductionManager {
         }
     }
 
-    fn build_expansion(&self, bot: &mut Bot, _bot_state: &BotState, unit_type: UnitTypeId) {
-        if !bot
-            .units
-            .my
-            .townhalls
-            .filter(|u| u.build_progress() < 0.1f32)
-            .is_empty()
+    fn build_expansion<G: GameView>(
+        &mut self,
+        game: &mut G,
+        bot_state: &mut BotState,
+        unit_type: UnitTypeId,
+    ) {
+        if game
+            .my_townhalls()
+            .iter()
+            .any(|u| u.build_progress < 0.1f32)
         {
             return;
         }
-        if let Some(expansion_location) = bot
-            .expansions
-            .iter()
-            .filter(|e| {
-                e.alliance.is_neutral()
-                    && bot.pathing_distance(bot.start_location, e.loc).is_some()
-                    && e.geysers.len() > 1
-            })
-            .map(|e| e.loc)
-            .next()
-        {
-            if let Some(builder) = self.get_builder(bot, expansion_location) {
-                builder.build(unit_type, expansion_location, false);
-                bot.subtract_resources(unit_type, false);
+        if let Some(expansion_location) = self.expansion_location() {
+            if let Some(builder) = self.builder(game, unit_type, expansion_location) {
+                self.issue_build(game, builder, unit_type, expansion_location);
+                bot_state
+                    .messages
+                    .publish(Message::ExpansionStarted(expansion_location));
             } else {
                 debug!("No builder");
             }
@@ -372,36 +792,58 @@ impl ProductionManager {
         }
     }
 
-    fn build_static_defense(&self, bot: &mut Bot, unit_type: UnitTypeId) {
-        let defenses = bot.units.my.all.filter(|unit| unit.type_id() == unit_type);
-        let defenseless_halls = bot
-            .units
-            .my
-            .townhalls
-            .filter(|u| u.is_ready() && defenses.closer(11f32, u.position()).is_empty());
-        if let Some(townhall) = defenseless_halls.iter().closest(bot.start_center) {
-            let resources = bot.units.resources.closer(9f32, townhall.position());
-            if let Some(defense_center) = resources.center() {
+    fn build_static_defense<G: GameView>(
+        &mut self,
+        game: &mut G,
+        bot_state: &BotState,
+        unit_type: UnitTypeId,
+    ) {
+        if let Some(location) = self.wall_location(game, bot_state, unit_type) {
+            if let Some(builder) = self.get_builder(game, location) {
+                self.issue_build(game, builder, unit_type, location);
+            }
+            return;
+        }
+        let defenses = game
+            .my_units()
+            .iter()
+            .filter(|unit| unit.type_id == unit_type)
+            .map(|unit| unit.position)
+            .collect::<Vec<_>>();
+        let defenseless_hall = closest(
+            game.my_townhalls()
+                .iter()
+                .filter(|u| u.is_ready() && !defenses.iter().any(|d| u.distance(*d) < 11f32)),
+            game.start_center(),
+        )
+        .cloned();
+        if let Some(townhall) = defenseless_hall {
+            let resources = game
+                .mineral_fields()
+                .iter()
+                .chain(game.vespene_geysers().iter())
+                .filter(|r| r.distance(townhall.position) < 9f32)
+                .cloned()
+                .collect::<Vec<_>>();
+            if let Some(defense_center) = center(&resources) {
                 let multiplier = if unit_type == UnitTypeId::SpineCrawler {
                     -1f32
                 } else {
                     1f32
                 };
                 let placement_position = townhall
-                    .position()
-                    .towards(defense_center, (townhall.radius() + 1f32) * multiplier);
-                if let Some(builder) = self.get_builder(bot, placement_position) {
-                    let options = PlacementOptions {
-                        max_distance: 3,
-                        step: 1,
-                        random: false,
-                        addon: false,
-                    };
-                    if let Some(placement) =
-                        bot.find_placement(unit_type, placement_position, options)
-                    {
-                        builder.build(unit_type, placement, false);
-                        bot.subtract_resources(unit_type, false);
+                    .position
+                    .towards(defense_center, (townhall.radius + 1f32) * multiplier);
+                if let Some(builder) = self.get_builder(game, placement_position) {
+                    if let Some(placement) = self.find_placement(
+                        game,
+                        unit_type,
+                        placement_position,
+                        townhall.position,
+                        3,
+                        1,
+                    ) {
+                        self.issue_build(game, builder, unit_type, placement);
                     }
                 } else {
                     debug!("No builder");
@@ -414,55 +856,462 @@ impl ProductionManager {
         }
     }
 
-    fn build_gas(&self, bot: &mut Bot) {
+    fn build_gas<G: GameView>(&self, game: &mut G) {
         let mut subtract_resources = false;
-        for base in bot.units.my.townhalls.sorted(|u| u.tag()) {
-            if base.is_ready() {
-                for owned_expansion in bot.owned_expansions() {
-                    if let Some(base_tag) = owned_expansion.base {
-                        if base_tag == base.tag() {
-                            if let Some(geyser) = bot.find_gas_placement(owned_expansion.loc) {
-                                if let Some(builder) = self.get_builder(bot, geyser.position()) {
-                                    builder.build_gas(geyser.tag(), false);
-                                    subtract_resources = true;
-                                }
-                            }
+        let owned_expansions = game.owned_expansions();
+        let bases = game
+            .my_townhalls()
+            .iter()
+            .filter(|u| u.is_ready())
+            .map(|u| u.tag)
+            .sorted()
+            .collect::<Vec<_>>();
+        for base in bases {
+            for owned_expansion in owned_expansions.iter() {
+                if owned_expansion.base == Some(base) {
+                    if let Some(geyser) = game.find_gas_placement(owned_expansion.loc) {
+                        if let Some(builder) = self.get_builder(game, geyser.position) {
+                            game.build_gas(builder, geyser.tag);
+                            subtract_resources = true;
                         }
                     }
                 }
             }
         }
         if subtract_resources {
-            bot.subtract_resources(bot.race_values.gas, false);
+            let gas = game.gas_types()[0];
+            game.spend(gas, false);
         }
     }
 
-    fn save_unit_resources(
+    fn save_unit_resources<G: GameView>(
         &self,
-        bot: &mut Bot,
-        _bot_state: &BotState,
+        game: &G,
+        bot_state: &mut BotState,
         unit_type: UnitTypeId,
         save_resources: bool,
-        use_supply: bool,
-        save_larva: bool,
+        priority: usize,
     ) {
         if !save_resources {
             return;
         }
-        bot.subtract_resources(unit_type, use_supply);
-        if unit_type.produced_on().contains(&UnitTypeId::Larva) && save_larva {
-            bot.units.my.larvas.pop();
+        if let Some(cost) = game.unit_cost(unit_type) {
+            let free = Self::free_funds(game, bot_state);
+            let uses_larva = unit_type.produced_on().contains(&UnitTypeId::Larva);
+            let wait = bot_state
+                .economy
+                .time_to_afford(free, &cost, u32::from(uses_larva));
+            // Larva isn't kept idle for entries that are far from affordable
+            let larva = uses_larva && wait.map_or(false, |w| w <= bot_state.params.larva_save_time);
+            let mut reservation =
+                Reservation::for_unit(unit_type, priority, cost, u32::from(larva), true);
+            reservation.expected_start = game.time() + wait.unwrap_or(f32::INFINITY);
+            bot_state.reservations.reserve(reservation);
         }
     }
 
-    fn save_upgrade_cost(&self, bot: &mut Bot, _bot_state: &BotState, upgrade_id: UpgradeId) {
-        bot.subtract_upgrade_cost(upgrade_id);
+    fn trace<G: GameView>(&self, game: &G, bot_state: &mut BotState) {
+        if !bot_state.trace.is_enabled() {
+            return;
+        }
+        let queue = bot_state
+            .build_queue
+            .time_to_afford(game, &bot_state.economy, Self::available_funds(game))
+            .into_iter()
+            .map(|(element, estimate)| {
+                json!({
+                    "command": format!("{:?}", element.command),
+                    "priority": element.priority,
+                    "affordable_in": estimate,
+                })
+            })
+            .collect::<Vec<_>>();
+        let reservations = bot_state
+            .reservations
+            .iter()
+            .map(|r| r.to_string())
+            .collect::<Vec<_>>();
+        let data = json!({
+            "queue": queue,
+            "reservations": reservations,
+            "builders": self
+                .builders
+                .iter()
+                .map(|(unit_type, (tag, _))| (format!("{:?}", unit_type), *tag))
+                .collect::<HashMap<String, u64>>(),
+            "minerals": game.minerals(),
+            "vespene": game.vespene(),
+        });
+        let units = self
+            .producing
+            .iter()
+            .chain(self.builders.values().map(|(tag, _)| tag))
+            .copied()
+            .sorted()
+            .collect();
+        bot_state
+            .trace
+            .record(game.game_loop(), "ProductionManager", units, data);
+    }
+
+    fn save_upgrade_cost<G: GameView>(
+        &self,
+        game: &G,
+        bot_state: &mut BotState,
+        upgrade_id: UpgradeId,
+        priority: usize,
+    ) {
+        let cost = game.upgrade_cost(upgrade_id);
+        let wait = bot_state
+            .economy
+            .time_to_afford(Self::free_funds(game, bot_state), &cost, 0);
+        let mut reservation = Reservation::for_upgrade(upgrade_id, priority, cost);
+        reservation.expected_start = game.time() + wait.unwrap_or(f32::INFINITY);
+        bot_state.reservations.reserve(reservation);
     }
 }
 
 impl AIComponent for ProductionManager {
     fn process(&mut self, bot: &mut Bot, bot_state: &mut BotState) {
-        self.produce_units(bot, bot_state);
+        {
+            let mut game = BotView::new(bot);
+            self.produce_units(&mut game, bot_state);
+            self.premove_builders(&mut game, bot_state);
+            self.trace(&game, bot_state);
+        }
         self.cancel_buildings(bot);
+        if bot_state.params.debug_draw {
+            bot_state
+                .reservations
+                .draw(bot, bot_state.params.reservation_starved_time);
+        }
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl ScenarioComponent for ProductionManager {
+    fn simulate(&mut self, game: &mut FakeGame, bot_state: &mut BotState) {
+        self.produce_units(game, bot_state);
+        self.premove_builders(game, bot_state);
+        self.trace(game, bot_state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_sc2::prelude::*;
+
+    use crate::bus::Message;
+    use crate::command_queue::Command;
+    use crate::fake_game::{Action, FakeGame};
+    use crate::game_view::{BaseOwner, ExpansionSnapshot, GameView, UnitSnapshot};
+    use crate::managers::production_manager::ProductionManager;
+    use crate::BotState;
+
+    fn hatchery_game(minerals: u32, larvas: u64) -> FakeGame {
+        let mut game = FakeGame {
+            minerals,
+            supply_left: 10,
+            ..Default::default()
+        };
+        let start = game.start_location;
+        game.units
+            .push(UnitSnapshot::new(1, UnitTypeId::Hatchery, start));
+        for tag in 0..larvas {
+            game.units
+                .push(UnitSnapshot::new(100 + tag, UnitTypeId::Larva, start));
+        }
+        game
+    }
+
+    fn trained(game: &FakeGame) -> Vec<(u64, UnitTypeId)> {
+        game.actions
+            .iter()
+            .filter_map(|a| match a {
+                Action::Train { unit, unit_type } => Some((*unit, *unit_type)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn drones_trained_while_affordable() {
+        let mut game = hatchery_game(100, 3);
+        let mut bot_state = BotState::default();
+        bot_state
+            .build_queue
+            .push(Command::new_unit(UnitTypeId::Drone, 14, false), false, 10);
+        ProductionManager::default().produce_units(&mut game, &mut bot_state);
+        assert_eq!(
+            trained(&game),
+            vec![(100, UnitTypeId::Drone), (101, UnitTypeId::Drone)]
+        );
+        assert_eq!(game.minerals, 0);
+        assert_eq!(game.my_larvas().len(), 1);
+    }
+
+    #[test]
+    fn saved_resources_hold_larva() {
+        let mut game = hatchery_game(200, 2);
+        let mut bot_state = BotState::default();
+        bot_state.economy.sample(&game);
+        game.time = 10f32;
+        game.collected_minerals = 100;
+        game.collected_vespene = 50;
+        bot_state
+            .build_queue
+            .push(Command::new_unit(UnitTypeId::Roach, 4, true), false, 100);
+        bot_state
+            .build_queue
+            .push(Command::new_unit(UnitTypeId::Drone, 20, false), false, 10);
+        ProductionManager::default().produce_units(&mut game, &mut bot_state);
+        assert_eq!(trained(&game), vec![(100, UnitTypeId::Drone)]);
+        assert_eq!(game.minerals, 150);
+        let reserved = bot_state.reservations.reserved();
+        assert_eq!((reserved.minerals, reserved.larva), (75, 1));
+    }
+
+    #[test]
+    fn distant_entries_leave_larva() {
+        let mut game = hatchery_game(200, 2);
+        let mut bot_state = BotState::default();
+        bot_state
+            .build_queue
+            .push(Command::new_unit(UnitTypeId::Roach, 4, true), false, 100);
+        bot_state
+            .build_queue
+            .push(Command::new_unit(UnitTypeId::Drone, 20, false), false, 10);
+        ProductionManager::default().produce_units(&mut game, &mut bot_state);
+        assert_eq!(
+            trained(&game),
+            vec![(100, UnitTypeId::Drone), (101, UnitTypeId::Drone)]
+        );
+        let roach = bot_state.reservations.iter().next().unwrap();
+        assert_eq!((roach.funds.minerals, roach.funds.larva), (75, 0));
+        assert_eq!(roach.expected_start, f32::INFINITY);
+    }
+
+    #[test]
+    fn missing_requirement_queued() {
+        let mut game = hatchery_game(500, 1);
+        game.vespene = 100;
+        let mut bot_state = BotState::default();
+        bot_state
+            .build_queue
+            .push(Command::new_unit(UnitTypeId::Roach, 5, false), false, 10);
+        ProductionManager::default().produce_units(&mut game, &mut bot_state);
+        assert!(trained(&game).is_empty());
+        assert!(bot_state.build_queue.into_iter().any(|e| e.command
+            == Command::new_unit(UnitTypeId::RoachWarren, 1, false)
+            && e.priority == ProductionManager::REQUIREMENT_QUEUE_PRIORITY + 10));
+    }
+
+    #[test]
+    fn structure_built_by_closest_drone() {
+        let mut game = hatchery_game(250, 0);
+        let start = game.start_location;
+        game.units.push(UnitSnapshot::new(
+            20,
+            UnitTypeId::Drone,
+            start.towards(game.map_center, 20f32),
+        ));
+        game.units.push(UnitSnapshot::new(
+            21,
+            UnitTypeId::Drone,
+            start.towards(game.map_center, 6f32),
+        ));
+        let mut bot_state = BotState::default();
+        bot_state.build_queue.push(
+            Command::new_unit(UnitTypeId::SpawningPool, 1, false),
+            false,
+            10,
+        );
+        ProductionManager::default().produce_units(&mut game, &mut bot_state);
+        assert_eq!(
+            game.actions,
+            vec![Action::Build {
+                unit: 21,
+                unit_type: UnitTypeId::SpawningPool,
+                location: start.towards(game.map_center, 7f32),
+            }]
+        );
+        assert_eq!(game.minerals, 50);
+    }
+
+    #[test]
+    fn tech_follows_requested_wall() {
+        let mut game = hatchery_game(250, 0);
+        game.enemy_start = Point2::new(30f32, 170f32);
+        let natural = Point2::new(30f32, 60f32);
+        game.units
+            .push(UnitSnapshot::new(2, UnitTypeId::Hatchery, natural));
+        game.units.push(UnitSnapshot::new(
+            20,
+            UnitTypeId::Drone,
+            Point2::new(30f32, 62f32),
+        ));
+        game.expansions.push(ExpansionSnapshot {
+            loc: natural,
+            geysers: 2,
+            owner: BaseOwner::Own,
+            base: Some(2),
+        });
+        for y in 68..=72 {
+            for x in (0..=24).chain(35..=60) {
+                game.unpathable.insert((x, y));
+            }
+        }
+        let mut bot_state = BotState::default();
+        bot_state.strategy.natural_wall = true;
+        bot_state.build_queue.push(
+            Command::new_unit(UnitTypeId::SpawningPool, 1, false),
+            false,
+            10,
+        );
+        ProductionManager::default().produce_units(&mut game, &mut bot_state);
+        assert_eq!(
+            game.actions,
+            vec![Action::Build {
+                unit: 20,
+                unit_type: UnitTypeId::SpawningPool,
+                location: Point2::new(29.5f32, 68.5f32),
+            }]
+        );
+    }
+
+    #[test]
+    fn macro_hatch_placed_in_base() {
+        let mut game = hatchery_game(350, 0);
+        let start = game.start_location;
+        game.ramp = Some(Point2::new(38f32, 38f32));
+        for (tag, y) in (26..=34).step_by(2).enumerate() {
+            game.mineral_fields.push(UnitSnapshot::new(
+                50 + tag as u64,
+                UnitTypeId::MineralField,
+                Point2::new(23f32, y as f32),
+            ));
+        }
+        game.units
+            .push(UnitSnapshot::new(20, UnitTypeId::Drone, start));
+        let mut bot_state = BotState::default();
+        bot_state
+            .build_queue
+            .push(Command::new_macro_hatch(1, false), false, 390);
+        let mut manager = ProductionManager::default();
+        manager.produce_units(&mut game, &mut bot_state);
+        let expected = vec![Action::Build {
+            unit: 20,
+            unit_type: UnitTypeId::Hatchery,
+            location: Point2::new(33.5f32, 22.5f32),
+        }];
+        assert_eq!(game.actions, expected);
+        // The drone on its way counts
+        game.minerals = 350;
+        manager.produce_units(&mut game, &mut bot_state);
+        assert_eq!(game.actions, expected);
+    }
+
+    #[test]
+    fn builder_sent_ahead_and_released() {
+        let mut game = hatchery_game(250, 0);
+        let start = game.start_location;
+        let natural = start + Point2::new(0f32, 30f32);
+        game.expansions.push(ExpansionSnapshot {
+            loc: natural,
+            geysers: 2,
+            owner: BaseOwner::Neutral,
+            base: None,
+        });
+        let mut drone = UnitSnapshot::new(20, UnitTypeId::Drone, start);
+        drone.speed = 3f32;
+        game.units.push(drone);
+        let mut bot_state = BotState::default();
+        bot_state.economy.sample(&game);
+        game.time = 10f32;
+        game.collected_minerals = 100;
+        let hatchery = Command::new_unit(UnitTypeId::Hatchery, 2, false);
+        bot_state.build_queue.push(hatchery.clone(), false, 10);
+        let mut manager = ProductionManager::default();
+        manager.produce_units(&mut game, &mut bot_state);
+        manager.premove_builders(&mut game, &mut bot_state);
+        assert_eq!(
+            game.actions,
+            vec![Action::Move {
+                unit: 20,
+                location: natural,
+            }]
+        );
+        assert!(bot_state
+            .messages
+            .messages()
+            .any(|m| *m == Message::BuilderSent(20)));
+        assert_eq!(manager.premove_locations[&UnitTypeId::Hatchery], natural);
+
+        bot_state.build_queue.cancel(&hatchery, 10);
+        manager.premove_builders(&mut game, &mut bot_state);
+        assert!(bot_state
+            .messages
+            .messages()
+            .any(|m| *m == Message::BuilderReleased(20)));
+        assert!(manager.premove_locations.is_empty());
+    }
+
+    #[test]
+    fn distant_estimates_not_placed() {
+        let mut game = hatchery_game(0, 0);
+        let start = game.start_location;
+        game.units
+            .push(UnitSnapshot::new(20, UnitTypeId::Drone, start));
+        let mut bot_state = BotState::default();
+        bot_state.economy.sample(&game);
+        // 40 seconds away from a pool
+        game.time = 10f32;
+        game.collected_minerals = 50;
+        bot_state.economy.sample(&game);
+        bot_state.build_queue.push(
+            Command::new_unit(UnitTypeId::SpawningPool, 1, false),
+            false,
+            10,
+        );
+        let mut manager = ProductionManager::default();
+        manager.premove_builders(&mut game, &mut bot_state);
+        assert!(game.actions.is_empty());
+        assert!(manager.premove_locations.is_empty());
+    }
+
+    #[test]
+    fn rejected_placement_not_retried() {
+        let mut game = hatchery_game(400, 0);
+        let start = game.start_location;
+        game.units
+            .push(UnitSnapshot::new(21, UnitTypeId::Drone, start));
+        let mut bot_state = BotState::default();
+        let pool = Command::new_unit(UnitTypeId::SpawningPool, 1, false);
+        bot_state.build_queue.push(pool, false, 10);
+        let mut manager = ProductionManager::default();
+        manager.produce_units(&mut game, &mut bot_state);
+        let rejected = start.towards(game.map_center, 7f32);
+        assert_eq!(
+            game.actions,
+            vec![Action::Build {
+                unit: 21,
+                unit_type: UnitTypeId::SpawningPool,
+                location: rejected,
+            }]
+        );
+
+        bot_state.messages.publish(Message::ActionFailed {
+            unit: 21,
+            ability: AbilityId::ZergBuildSpawningPool,
+            reason: "CantBuildLocationInvalid".to_string(),
+        });
+        game.actions.clear();
+        game.time += 1f32;
+        manager.produce_units(&mut game, &mut bot_state);
+        match game.actions.as_slice() {
+            [Action::Build { location, .. }] => assert!(location.distance(rejected) > 1f32),
+            actions => panic!("expected a single build, got {:?}", actions),
+        }
+        assert_eq!(manager.build_failures[&UnitTypeId::SpawningPool], 1);
     }
 }
@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+
+use rust_sc2::bot::Bot;
+use rust_sc2::prelude::*;
+
+use crate::expansions::ThreatHistory;
+#[cfg(any(test, feature = "testing"))]
+use crate::fake_game::FakeGame;
+use crate::game_view::{center, closest, BotView, GameView, UnitSnapshot};
+#[cfg(any(test, feature = "testing"))]
+use crate::scenario::ScenarioComponent;
+use crate::utils::SegmentDistance;
+use crate::{AIComponent, BotState};
+
+const MINERAL_LINE_RANGE: f32 = 10f32;
+const BEHIND_MINERALS: f32 = 3f32;
+
+// Mineral fields around ready townhalls
+fn mineral_lines<G: GameView>(game: &G, townhalls: &[UnitSnapshot]) -> Vec<UnitSnapshot> {
+    game.mineral_fields()
+        .iter()
+        .filter(|f| {
+            townhalls
+                .iter()
+                .any(|t| t.distance(f.position) < MINERAL_LINE_RANGE)
+        })
+        .cloned()
+        .collect()
+}
+
+// Behind the mineral line of the closest townhall to the main that wasn't
+// attacked lately.
+pub fn safe_spot<G: GameView>(game: &G, threats: &ThreatHistory) -> Point2 {
+    let time = game.time();
+    let main = game.start_location();
+    let halls = game.my_townhalls();
+    let mut townhalls = halls.iter().filter(|t| t.is_ready()).collect::<Vec<_>>();
+    townhalls.sort_by(|a, b| a.distance(main).total_cmp(&b.distance(main)));
+    let townhall = match townhalls
+        .iter()
+        .find(|t| threats.threat_around(t.position, time) == 0f32)
+        .or_else(|| townhalls.first())
+    {
+        Some(townhall) => townhall.position,
+        None => return main,
+    };
+    let minerals = game
+        .mineral_fields()
+        .iter()
+        .filter(|f| f.distance(townhall) < MINERAL_LINE_RANGE)
+        .cloned()
+        .collect::<Vec<_>>();
+    match center(&minerals) {
+        Some(mineral_line) => townhall.towards(
+            mineral_line,
+            townhall.distance(mineral_line) + BEHIND_MINERALS,
+        ),
+        None => townhall,
+    }
+}
+
+fn is_safe(enemies: &[UnitSnapshot], from: Point2, to: Point2, danger_range: f32) -> bool {
+    enemies
+        .iter()
+        .all(|e| e.position.segment_distance(from, to) > danger_range)
+}
+
+// Where to head for when going from one point to another without running into
+// the enemy army, through one of our townhalls when the straight way isn't safe.
+// Waits at the closest safe townhall when there is no safe way.
+pub fn safe_waypoint(
+    townhalls: &[UnitSnapshot],
+    enemies: &[UnitSnapshot],
+    from: Point2,
+    to: Point2,
+    danger_range: f32,
+) -> Point2 {
+    let enemies = enemies
+        .iter()
+        .filter(|e| !e.is_worker() && e.can_attack)
+        .cloned()
+        .collect::<Vec<_>>();
+    if is_safe(&enemies, from, to, danger_range) {
+        return to;
+    }
+    townhalls
+        .iter()
+        .filter(|t| {
+            is_safe(&enemies, from, t.position, danger_range)
+                && is_safe(&enemies, t.position, to, danger_range)
+        })
+        .min_by(|a, b| {
+            (a.distance(from) + a.distance(to)).total_cmp(&(b.distance(from) + b.distance(to)))
+        })
+        .or_else(|| {
+            closest(
+                townhalls.iter().filter(|t| {
+                    enemies
+                        .iter()
+                        .all(|e| e.distance(t.position) > danger_range)
+                }),
+                from,
+            )
+        })
+        .map_or(to, |t| t.position)
+}
+
+// Rallies drones to the mineral line of the townhall they come from and army
+// units to the gather point picked by the army logic.
+#[derive(Default)]
+pub struct RallyManager {
+    // (townhall_tag, (mineral_field_tag, gather_point))
+    rallies: HashMap<u64, (Option<u64>, Option<Point2>)>,
+}
+
+impl RallyManager {
+    fn step<G: GameView>(&mut self, game: &mut G, bot_state: &mut BotState) {
+        let townhalls = game
+            .my_townhalls()
+            .iter()
+            .filter(|t| t.is_ready())
+            .cloned()
+            .collect::<Vec<_>>();
+        self.rallies
+            .retain(|tag, _| townhalls.iter().any(|t| t.tag == *tag));
+        let minerals = mineral_lines(game, &townhalls);
+        for townhall in townhalls.iter() {
+            // Mined out bases send drones to the closest mineral line left
+            let own_line = minerals
+                .iter()
+                .filter(|f| f.distance(townhall.position) < MINERAL_LINE_RANGE);
+            let field = closest(own_line, townhall.position)
+                .or_else(|| closest(minerals.iter(), townhall.position))
+                .map(|f| f.tag);
+            let gather = bot_state.rally_point;
+            let (current_field, current_gather) =
+                self.rallies.entry(townhall.tag).or_insert((None, None));
+            if let Some(field) = field.filter(|f| *current_field != Some(*f)) {
+                game.rally_workers(townhall.tag, field);
+                *current_field = Some(field);
+            }
+            if let Some(gather) = gather.filter(|g| *current_gather != Some(*g)) {
+                game.rally_units(townhall.tag, gather);
+                *current_gather = Some(gather);
+            }
+        }
+    }
+}
+
+impl AIComponent for RallyManager {
+    fn process(&mut self, bot: &mut Bot, bot_state: &mut BotState) {
+        self.step(&mut BotView::new(bot), bot_state);
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl ScenarioComponent for RallyManager {
+    fn simulate(&mut self, game: &mut FakeGame, bot_state: &mut BotState) {
+        self.step(game, bot_state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_sc2::prelude::*;
+
+    use crate::expansions::ThreatHistory;
+    use crate::fake_game::{Action, FakeGame};
+    use crate::game_view::{BaseOwner, ExpansionSnapshot, UnitSnapshot};
+    use crate::managers::rally_manager::{safe_spot, safe_waypoint, RallyManager};
+    use crate::scenario::ScenarioComponent;
+    use crate::BotState;
+
+    fn two_bases_game() -> FakeGame {
+        let mut game = FakeGame::default();
+        game.units = vec![
+            UnitSnapshot::new(1, UnitTypeId::Hatchery, Point2::new(30f32, 30f32)),
+            UnitSnapshot::new(2, UnitTypeId::Hatchery, Point2::new(60f32, 30f32)),
+        ];
+        game.mineral_fields = vec![
+            UnitSnapshot::new(10, UnitTypeId::MineralField, Point2::new(23f32, 30f32)),
+            UnitSnapshot::new(11, UnitTypeId::MineralField, Point2::new(67f32, 30f32)),
+        ];
+        game
+    }
+
+    #[test]
+    fn rallies_set_once_per_townhall() {
+        let mut game = two_bases_game();
+        let mut bot_state = BotState::default();
+        let mut manager = RallyManager::default();
+        manager.simulate(&mut game, &mut bot_state);
+        assert_eq!(
+            game.actions,
+            vec![
+                Action::RallyWorkers {
+                    unit: 1,
+                    resource: 10
+                },
+                Action::RallyWorkers {
+                    unit: 2,
+                    resource: 11
+                },
+            ]
+        );
+
+        let gather = Point2::new(45f32, 35f32);
+        bot_state.rally_point = Some(gather);
+        game.actions.clear();
+        manager.simulate(&mut game, &mut bot_state);
+        manager.simulate(&mut game, &mut bot_state);
+        assert_eq!(
+            game.actions,
+            vec![
+                Action::RallyUnits {
+                    unit: 1,
+                    location: gather
+                },
+                Action::RallyUnits {
+                    unit: 2,
+                    location: gather
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reinforcements_go_around_enemy_army() {
+        let mut game = two_bases_game();
+        let from = Point2::new(30f32, 30f32);
+        let to = Point2::new(100f32, 30f32);
+        assert_eq!(
+            safe_waypoint(&game.my_townhalls(), &[], from, to, 10f32),
+            to
+        );
+
+        game.units.push(UnitSnapshot::new(
+            3,
+            UnitTypeId::Hatchery,
+            Point2::new(65f32, 60f32),
+        ));
+        let enemies = vec![
+            UnitSnapshot::new(20, UnitTypeId::Zealot, Point2::new(62f32, 25f32)).armed(),
+            UnitSnapshot::new(21, UnitTypeId::Probe, Point2::new(65f32, 60f32)).armed(),
+        ];
+        assert_eq!(
+            safe_waypoint(&game.my_townhalls(), &enemies, from, to, 10f32),
+            Point2::new(65f32, 60f32)
+        );
+    }
+
+    #[test]
+    fn overlords_leave_attacked_bases() {
+        let mut game = two_bases_game();
+        assert_eq!(
+            safe_spot(&game, &ThreatHistory::default()),
+            Point2::new(20f32, 30f32)
+        );
+
+        game.expansions.push(ExpansionSnapshot {
+            loc: Point2::new(30f32, 30f32),
+            geysers: 2,
+            owner: BaseOwner::Own,
+            base: Some(1),
+        });
+        game.enemies =
+            vec![UnitSnapshot::new(20, UnitTypeId::Zealot, Point2::new(32f32, 30f32)).armed()];
+        let mut threats = ThreatHistory::default();
+        threats.record(&game, &game.enemies.clone());
+        assert_eq!(safe_spot(&game, &threats), Point2::new(70f32, 30f32));
+    }
+}
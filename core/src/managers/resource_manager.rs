@@ -1,9 +1,15 @@
+use std::cmp::Ordering;
+
 use rust_sc2::bot::Bot;
 use rust_sc2::prelude::*;
+use serde_json::json;
 
 use crate::command_queue::Command;
-use crate::params::*;
-use crate::utils::*;
+#[cfg(any(test, feature = "testing"))]
+use crate::fake_game::FakeGame;
+use crate::game_view::{BotView, GameView, UnitSnapshot};
+#[cfg(any(test, feature = "testing"))]
+use crate::scenario::ScenarioComponent;
 use crate::*;
 
 #[derive(Default)]
@@ -40,90 +46,61 @@ impl ResourceManager {
         UnitTypeId::BroodLordCocoon,
     ];
 
-    fn spending_decision(&mut self, bot: &mut Bot, bot_state: &mut BotState) {
-        let their_expansions = bot.enemy_expansions().count();
-        let their_strength = bot_state
-            .enemy_cache
-            .units
-            .filter(|unit| {
-                !unit.is_worker() && !unit.is_structure() && unit.type_id() != UnitTypeId::Queen
-            })
-            .strength(bot);
-        let their_ground_strength = bot_state
-            .enemy_cache
-            .units
-            .filter(|unit| {
-                !unit.is_flying()
-                    && (bot
-                        .units
-                        .enemy
-                        .townhalls
-                        .closest_distance(unit.position())
-                        .unwrap_or_default()
-                        > 16f32
-                        || (!unit.is_worker() && !unit.is_structure()))
-            })
-            .strength(bot);
-        let their_air_strength = bot_state
-            .enemy_cache
-            .units
-            .filter(|unit| {
-                unit.is_flying()
-                    && (bot
-                        .units
-                        .enemy
-                        .townhalls
-                        .closest_distance(unit.position())
-                        .unwrap_or_default()
-                        > 16f32
-                        || (!unit.is_worker() && !unit.is_structure()))
-            })
-            .strength(bot);
-        let our_anti_ground_strength = bot
-            .units
-            .my
-            .all
-            .filter(|unit| Self::ANTI_GROUND_UNITS.contains(&unit.type_id()))
-            .strength(bot)
-            + Self::ANTI_GROUND_UNITS
+    fn spending_decision<G: GameView>(
+        &mut self,
+        game: &mut G,
+        enemies: &[UnitSnapshot],
+        bot_state: &mut BotState,
+    ) {
+        let their_expansions = game.enemy_expansions().len();
+        let their_townhalls = game.enemy_townhalls();
+        let away_from_their_bases = |unit: &UnitSnapshot| {
+            their_townhalls
                 .iter()
-                .map(|t| bot.counter().ordered().count(*t) as f32 * t.base_strength(bot))
-                .sum::<f32>();
-        let our_anti_air_strength = bot
-            .units
-            .my
-            .all
-            .filter(|unit| Self::ANTI_AIR_UNITS.contains(&unit.type_id()))
-            .strength(bot)
-            + Self::ANTI_AIR_UNITS
+                .map(|t| t.distance(unit.position))
+                .min_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+                .unwrap_or_default()
+                > 16f32
+        };
+        let their_strength = game.strength(enemies.iter().filter(|unit| {
+            !unit.is_worker() && !unit.is_structure() && unit.type_id != UnitTypeId::Queen
+        }));
+        let their_ground_strength = game.strength(enemies.iter().filter(|unit| {
+            !unit.is_flying
+                && (away_from_their_bases(*unit) || (!unit.is_worker() && !unit.is_structure()))
+        }));
+        let their_air_strength = game.strength(enemies.iter().filter(|unit| {
+            unit.is_flying
+                && (away_from_their_bases(*unit) || (!unit.is_worker() && !unit.is_structure()))
+        }));
+        let ordered_strength = |unit_types: &[UnitTypeId]| {
+            unit_types
                 .iter()
-                .map(|t| bot.counter().ordered().count(*t) as f32 * t.base_strength(bot))
-                .sum::<f32>();
-        let ordered_offensive_strength = Self::OFFENSIVE_UNITS
-            .iter()
-            .map(|t| bot.counter().ordered().count(*t) as f32 * t.base_strength(bot))
-            .sum::<f32>();
-        let our_offensive_strength = bot
-            .units
-            .my
-            .all
-            .filter(|unit| {
-                !unit.is_worker() && !unit.is_structure() && unit.type_id() != UnitTypeId::Queen
-            })
-            .strength(bot)
-            + ordered_offensive_strength;
+                .map(|t| game.count_ordered(*t) as f32 * game.type_strength(*t))
+                .sum::<f32>()
+        };
+        let my_units = game.my_units();
+        let our_anti_ground_strength = game.strength(
+            my_units
+                .iter()
+                .filter(|unit| Self::ANTI_GROUND_UNITS.contains(&unit.type_id)),
+        ) + ordered_strength(&Self::ANTI_GROUND_UNITS);
+        let our_anti_air_strength = game.strength(
+            my_units
+                .iter()
+                .filter(|unit| Self::ANTI_AIR_UNITS.contains(&unit.type_id)),
+        ) + ordered_strength(&Self::ANTI_AIR_UNITS);
+        let ordered_offensive_strength = ordered_strength(&Self::OFFENSIVE_UNITS);
+        let our_offensive_strength = game.strength(my_units.iter().filter(|unit| {
+            !unit.is_worker() && !unit.is_structure() && unit.type_id != UnitTypeId::Queen
+        })) + ordered_offensive_strength;
 
-        let ordered_defensive_strength = Self::DEFENSIVE_UNITS
-            .iter()
-            .map(|t| bot.counter().ordered().count(*t) as f32 * t.base_strength(bot))
-            .sum::<f32>();
-        let our_strength = bot
-            .units
-            .my
-            .all
-            .filter(|unit| !unit.is_worker() && !unit.is_structure())
-            .strength(bot)
-            + ordered_offensive_strength
+        let ordered_defensive_strength = ordered_strength(&Self::DEFENSIVE_UNITS);
+        let our_strength = game.strength(
+            my_units
+                .iter()
+                .filter(|unit| !unit.is_worker() && !unit.is_structure()),
+        ) + ordered_offensive_strength
             + ordered_defensive_strength;
         let mut conditions: u8 = 0;
         if their_strength * 1.1f32 > our_strength {
@@ -138,37 +115,62 @@ impl ResourceManager {
         if bot_state.minimum_strength > our_offensive_strength {
             conditions += 1;
         }
-        if their_expansions < 2 && bot.time > 155f32 && bot.time < 270f32 {
+        if their_expansions < 2 && game.time() > 155f32 && game.time() < 270f32 {
             conditions += 2;
         }
         bot_state.minimum_strength = bot_state.minimum_strength.max(their_strength * 0.7f32);
 
-        let spending_focus = match conditions {
+        let mut spending_focus = match conditions {
             0 | 1 => SpendingFocus::Economy,
             _ => SpendingFocus::Army,
         };
-        if DEBUG_TEXT && bot_state.spending_focus != spending_focus {
-            bot.chat_ally(format!("Changing decision to {:?}, [S{:.2}|OF{:.2}|AA{:.2}|AG{:.2}] vs [S{:.2}|A{:.2}|G{:.2}]", spending_focus, our_strength, our_offensive_strength, our_anti_air_strength, our_anti_ground_strength, their_strength, their_air_strength, their_ground_strength).as_str());
+        // Stick to the plan unless they are stronger
+        if let Some(plan_focus) = bot_state
+            .strategy
+            .active
+            .as_ref()
+            .and_then(|plan| plan.spending_focus.clone())
+        {
+            if their_strength * 1.1f32 <= our_strength {
+                spending_focus = plan_focus;
+            }
+        }
+        if bot_state.trace.is_enabled() {
+            let data = json!({
+                "spending_focus": format!("{:?}", spending_focus),
+                "conditions": conditions,
+                "our_strength": our_strength,
+                "our_offensive_strength": our_offensive_strength,
+                "our_anti_air_strength": our_anti_air_strength,
+                "our_anti_ground_strength": our_anti_ground_strength,
+                "their_strength": their_strength,
+                "their_air_strength": their_air_strength,
+                "their_ground_strength": their_ground_strength,
+                "minimum_strength": bot_state.minimum_strength,
+            });
+            bot_state
+                .trace
+                .record(game.game_loop(), "ResourceManager", vec![], data);
+        }
+        if bot_state.spending_focus != spending_focus {
+            if bot_state.params.debug_text {
+                game.chat(format!("Changing decision to {:?}, [S{:.2}|OF{:.2}|AA{:.2}|AG{:.2}] vs [S{:.2}|A{:.2}|G{:.2}]", spending_focus, our_strength, our_offensive_strength, our_anti_air_strength, our_anti_ground_strength, their_strength, their_air_strength, their_ground_strength).as_str());
+            }
             bot_state.spending_focus = spending_focus;
         }
     }
 
-    fn order_supply(&self, bot: &mut Bot, bot_state: &mut BotState) {
-        if bot.supply_cap >= 200 {
+    fn order_supply<G: GameView>(&self, game: &G, bot_state: &mut BotState) {
+        if game.supply_cap() >= 200 {
             return;
         }
         const OVERLORD_MAX: usize = 24;
         let wanted_lords = OVERLORD_MAX.min(
-            (bot.supply_cap - bot.supply_left) as usize * 9
+            (game.supply_cap() - game.supply_left()) as usize * 9
                 / 8
-                / bot
-                    .game_data
-                    .units
-                    .get(&UnitTypeId::Overlord)
-                    .unwrap()
-                    .food_provided as usize,
+                / game.supply_provided(UnitTypeId::Overlord) as usize,
         );
-        let overseers = bot.counter().all().count(UnitTypeId::Overseer);
+        let overseers = game.count_all(UnitTypeId::Overseer);
         bot_state.build_queue.push(
             Command::new_unit(
                 UnitTypeId::Overlord,
@@ -180,28 +182,42 @@ impl ResourceManager {
         );
     }
 
-    fn queue_expansion(&self, bot: &mut Bot, bot_state: &mut BotState) {
-        let mut bases = Units::new();
-
-        for unit_type in bot.race_values.townhalls.iter() {
-            bases.extend(bot.units.my.structures.of_type(*unit_type));
-        }
-        let ideal_harvesters = bases.sum(|x| x.ideal_harvesters().unwrap_or(12));
-        let current_harvesters = bases.sum(|x| x.assigned_harvesters().unwrap_or_default())
-            + bot.units.my.workers.idle().len() as u32;
+    fn queue_expansion<G: GameView>(&self, game: &G, bot_state: &mut BotState) {
+        let townhall_types = game.townhall_types();
+        let structures = game.my_structures();
+        let bases = structures
+            .iter()
+            .filter(|u| townhall_types.contains(&u.type_id))
+            .collect::<Vec<_>>();
+        let ideal_harvesters = bases
+            .iter()
+            .map(|x| x.ideal_harvesters.unwrap_or(12))
+            .sum::<u32>();
+        let current_harvesters = bases
+            .iter()
+            .map(|x| x.assigned_harvesters.unwrap_or_default())
+            .sum::<u32>()
+            + game.my_workers().iter().filter(|w| w.is_idle).count() as u32;
         let ideal_diff = if bases.len() < 3 { 12 } else { 2 };
+        let townhall = game.townhall_type();
+        // Macro hatcheries in progress don't count as the next expansion
+        let building_macro = game
+            .macro_townhalls()
+            .iter()
+            .filter(|u| !u.is_ready())
+            .count();
         let halls = if bot_state.spending_focus != SpendingFocus::Army
             && ((ideal_harvesters < 70
                 && ideal_harvesters.saturating_sub(current_harvesters) < ideal_diff)
-                || bot.minerals > 1_000)
+                || game.minerals() > 1_000)
         {
-            bot.counter().count(bot.race_values.start_townhall) + 1
+            game.count(townhall) + building_macro + 1
         } else {
-            bot.counter().all().count(bot.race_values.start_townhall)
+            game.count_all(townhall)
         };
         bot_state.build_queue.push(
             Command::new_unit(
-                bot.race_values.start_townhall,
+                townhall,
                 halls,
                 bot_state.spending_focus != SpendingFocus::Army,
             ),
@@ -210,15 +226,41 @@ impl ResourceManager {
         );
     }
 
-    fn order_geysers(&self, bot: &mut Bot, bot_state: &mut BotState) {
-        let extractor = bot.race_values.gas;
-        let workers = bot.units.my.workers.len();
-        let wanted_extractors = if workers < DOUBLE_GAS_PER_BASE_WORKERS
-            && bot.counter().count(UnitTypeId::RoachWarren) == 0
+    // In-base hatcheries once larva, not minerals, holds production back
+    fn queue_macro_hatch<G: GameView>(&self, game: &G, bot_state: &mut BotState) {
+        let halls = game.macro_townhalls();
+        let ready_townhalls = game.my_townhalls().iter().filter(|u| u.is_ready()).count();
+        let injects_saturated = game.count(UnitTypeId::Queen) >= ready_townhalls;
+        let larva_short = bot_state
+            .economy
+            .larva_demand(bot_state.params.minerals_per_larva)
+            > bot_state.economy.larva_rate();
+        let banking =
+            game.minerals() >= bot_state.params.macro_hatch_bank && game.my_larvas().len() <= 1;
+        let wanted = if injects_saturated
+            && larva_short
+            && banking
+            && halls.len() < bot_state.params.max_macro_hatcheries
+            && halls.iter().all(|u| u.is_ready())
+        {
+            halls.len() + 1
+        } else {
+            halls.len()
+        };
+        bot_state
+            .build_queue
+            .push(Command::new_macro_hatch(wanted, false), false, 390);
+    }
+
+    fn order_geysers<G: GameView>(&self, game: &G, bot_state: &mut BotState) {
+        let extractor = game.gas_types()[0];
+        let workers = game.my_workers().len();
+        let wanted_extractors = if workers < bot_state.params.double_gas_per_base_workers
+            && game.count(UnitTypeId::RoachWarren) == 0
         {
-            1.max(workers as usize / 16)
+            1.max(workers / 16)
         } else {
-            bot.owned_expansions().count().saturating_sub(1) * 2
+            game.owned_expansions().len().saturating_sub(1) * 2
         };
         bot_state.build_queue.push(
             Command::new_unit(extractor, wanted_extractors, false),
@@ -226,13 +268,108 @@ impl ResourceManager {
             260,
         );
     }
+
+    fn step<G: GameView>(
+        &mut self,
+        game: &mut G,
+        enemies: &[UnitSnapshot],
+        bot_state: &mut BotState,
+    ) {
+        self.spending_decision(game, enemies, bot_state);
+        self.order_supply(game, bot_state);
+        self.queue_expansion(game, bot_state);
+        self.queue_macro_hatch(game, bot_state);
+        self.order_geysers(game, bot_state);
+    }
 }
 
 impl AIComponent for ResourceManager {
     fn process(&mut self, bot: &mut Bot, bot_state: &mut BotState) {
-        self.spending_decision(bot, bot_state);
-        self.order_supply(bot, bot_state);
-        self.queue_expansion(bot, bot_state);
-        self.order_geysers(bot, bot_state);
+        let enemies = bot_state.enemy_cache.snapshots();
+        self.step(&mut BotView::new(bot), &enemies, bot_state);
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl ScenarioComponent for ResourceManager {
+    fn simulate(&mut self, game: &mut FakeGame, bot_state: &mut BotState) {
+        let enemies = game.enemy_units().into_owned();
+        self.step(game, &enemies, bot_state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_sc2::prelude::*;
+
+    use crate::command_queue::Command;
+    use crate::fake_game::FakeGame;
+    use crate::game_view::UnitSnapshot;
+    use crate::managers::resource_manager::ResourceManager;
+    use crate::{BotState, SpendingFocus};
+
+    #[test]
+    fn army_focus_when_outnumbered() {
+        let mut game = FakeGame {
+            time: 120f32,
+            ..Default::default()
+        };
+        let enemies = (0..6)
+            .map(|tag| {
+                UnitSnapshot::new(tag, UnitTypeId::Zealot, Point2::new(60f32, 60f32)).armed()
+            })
+            .collect::<Vec<_>>();
+        let mut bot_state = BotState::default();
+        ResourceManager::default().spending_decision(&mut game, &enemies, &mut bot_state);
+        assert_eq!(bot_state.spending_focus, SpendingFocus::Army);
+        assert!((bot_state.minimum_strength - 420f32).abs() < 0.01f32);
+    }
+
+    #[test]
+    fn economy_focus_when_safe() {
+        let mut game = FakeGame {
+            time: 60f32,
+            ..Default::default()
+        };
+        game.units
+            .push(UnitSnapshot::new(1, UnitTypeId::Queen, game.start_location).armed());
+        let mut bot_state = BotState {
+            spending_focus: SpendingFocus::Army,
+            ..Default::default()
+        };
+        ResourceManager::default().spending_decision(&mut game, &[], &mut bot_state);
+        assert_eq!(bot_state.spending_focus, SpendingFocus::Economy);
+    }
+
+    #[test]
+    fn macro_hatch_when_larva_short() {
+        let mut game = FakeGame {
+            minerals: 600,
+            ..Default::default()
+        };
+        let start = game.start_location;
+        game.units
+            .push(UnitSnapshot::new(1, UnitTypeId::Hatchery, start));
+        game.units
+            .push(UnitSnapshot::new(2, UnitTypeId::Queen, start).armed());
+        let mut bot_state = BotState::default();
+        bot_state.economy.sample(&game);
+        game.time = 10f32;
+        game.collected_minerals = 200;
+        bot_state.economy.sample(&game);
+        let manager = ResourceManager::default();
+        manager.queue_macro_hatch(&game, &mut bot_state);
+        assert!(bot_state
+            .build_queue
+            .into_iter()
+            .any(|e| e.command == Command::new_macro_hatch(1, false)));
+
+        game.units
+            .extend((0..3).map(|tag| UnitSnapshot::new(100 + tag, UnitTypeId::Larva, start)));
+        manager.queue_macro_hatch(&game, &mut bot_state);
+        assert!(bot_state
+            .build_queue
+            .into_iter()
+            .any(|e| e.command == Command::new_macro_hatch(0, false)));
     }
 }
@@ -3,7 +3,6 @@ use rust_sc2::geometry::Point3;
 use rust_sc2::prelude::*;
 use rust_sc2::Event::UnitDestroyed;
 
-use crate::params::*;
 use crate::utils::IsDangerous;
 use crate::{AIComponent, BotState};
 
@@ -22,7 +21,7 @@ impl Squad {
         3f32 + (self.squad.len() as f32).log(8f32)
     }
 
-    fn center(&self) -> Point2 {
+    pub fn center(&self) -> Point2 {
         self.squad.sum(|u| u.position()) / self.squad.len() as f32
     }
 
@@ -39,7 +38,7 @@ impl Squad {
 }
 
 impl Squads {
-    fn recalculate_squads(&mut self, bot: &mut Bot) {
+    fn recalculate_squads(&mut self, bot: &mut Bot, debug_draw: bool) {
         for unit in bot
             .units
             .my
@@ -50,7 +49,7 @@ impl Squads {
             self.recalculate_unit_squad(unit);
         }
 
-        if DEBUG_DRAW {
+        if debug_draw {
             for squad in self.squads.iter() {
                 bot.debug.draw_sphere(
                     squad.center3(),
@@ -112,7 +111,9 @@ pub struct SquadManager {}
 
 impl AIComponent for SquadManager {
     fn process(&mut self, bot: &mut Bot, bot_state: &mut BotState) {
-        bot_state.squads.recalculate_squads(bot);
+        bot_state
+            .squads
+            .recalculate_squads(bot, bot_state.params.debug_draw);
     }
 
     fn on_event(&mut self, event: &Event, bot_state: &mut BotState) {
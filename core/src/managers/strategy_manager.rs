@@ -0,0 +1,217 @@
+use log::debug;
+use rust_sc2::bot::Bot;
+use rust_sc2::prelude::*;
+
+use crate::bus::Message;
+use crate::command_queue::Command;
+use crate::wall::wall_needed;
+use crate::*;
+
+// Mid-game plan an opening hands over to once its blocked commands are done.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StrategyPlan {
+    pub name: String,
+    // Army unit types and their weight, replaces the generic tech unlocks
+    pub composition: Vec<(UnitTypeId, usize)>,
+    // Army supply to gather before attacking regardless of strength
+    pub timing_attack_supply: Option<u32>,
+    // Tech structures and upgrades to get while following the plan
+    pub tech_path: Vec<Command>,
+    pub spending_focus: Option<SpendingFocus>,
+    // Game time (in seconds) when the plan is dropped
+    pub expires_at: f32,
+    // Seeing this many enemy units of a type drops the plan
+    pub invalidated_by: Vec<(UnitTypeId, usize)>,
+}
+
+impl StrategyPlan {
+    pub fn new(name: &str, expires_at: f32) -> Self {
+        Self {
+            name: name.to_string(),
+            composition: vec![],
+            timing_attack_supply: None,
+            tech_path: vec![],
+            spending_focus: None,
+            expires_at,
+            invalidated_by: vec![],
+        }
+    }
+
+    pub fn with_unit(mut self, unit_type: UnitTypeId, weight: usize) -> Self {
+        self.composition.push((unit_type, weight));
+        self
+    }
+
+    pub fn with_timing_attack(mut self, supply: u32) -> Self {
+        self.timing_attack_supply = Some(supply);
+        self
+    }
+
+    pub fn with_tech(mut self, command: Command) -> Self {
+        self.tech_path.push(command);
+        self
+    }
+
+    pub fn with_spending_focus(mut self, spending_focus: SpendingFocus) -> Self {
+        self.spending_focus = Some(spending_focus);
+        self
+    }
+
+    pub fn invalidated_by(self, unit_type: UnitTypeId) -> Self {
+        self.invalidated_by_count(unit_type, 1)
+    }
+
+    pub fn invalidated_by_count(mut self, unit_type: UnitTypeId, count: usize) -> Self {
+        self.invalidated_by.push((unit_type, count));
+        self
+    }
+
+    pub fn unit_weight(&self, unit_type: UnitTypeId) -> Option<usize> {
+        self.composition
+            .iter()
+            .find(|(t, _)| *t == unit_type)
+            .map(|(_, weight)| *weight)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Strategy {
+    // Plan declared by the opening, waiting for its blocked commands
+    pub follow_up: Option<StrategyPlan>,
+    pub active: Option<StrategyPlan>,
+    // Opening asks for tech structures and spines to wall off the natural
+    pub wall_requested: bool,
+    // Wall requested and needed against the enemy race
+    pub natural_wall: bool,
+}
+
+impl Strategy {
+    // Random opponents are only walled off once their race is known
+    pub fn update_natural_wall(&mut self, enemy_race: Race) {
+        self.natural_wall = self.wall_requested && wall_needed(enemy_race);
+    }
+
+    // None when there is no timing attack planned
+    pub fn timing_attack_ready(&self, bot: &Bot) -> Option<bool> {
+        self.active
+            .as_ref()
+            .and_then(|plan| plan.timing_attack_supply)
+            .map(|supply| bot.supply_army >= supply)
+    }
+}
+
+#[derive(Default)]
+pub struct StrategyManager {}
+
+impl StrategyManager {
+    fn is_invalid(bot: &Bot, bot_state: &BotState, plan: &StrategyPlan) -> bool {
+        bot.time >= plan.expires_at
+            || plan.invalidated_by.iter().any(|(unit_type, count)| {
+                bot_state.enemy_cache.units.of_type(*unit_type).len() >= *count
+            })
+    }
+
+    fn update_plan(&mut self, bot: &mut Bot, bot_state: &mut BotState) {
+        if let Some(plan) = bot_state.strategy.follow_up.take() {
+            if Self::is_invalid(bot, bot_state, &plan) {
+                debug!("Follow up plan {} dropped before starting", plan.name);
+            } else if bot_state.build_queue.has_blocked() {
+                bot_state.strategy.follow_up = Some(plan);
+            } else {
+                if bot_state.params.debug_text {
+                    bot.chat_ally(format!("Following plan {}", plan.name).as_str());
+                }
+                bot_state.strategy.active = Some(plan);
+            }
+        }
+        if let Some(plan) = bot_state.strategy.active.take() {
+            if Self::is_invalid(bot, bot_state, &plan) {
+                if bot_state.params.debug_text {
+                    bot.chat_ally(format!("Dropping plan {}", plan.name).as_str());
+                }
+                for command in plan.tech_path.iter() {
+                    bot_state
+                        .build_queue
+                        .cancel(command, bot_state.params.priority_strategy_tech);
+                }
+                bot_state.messages.publish(Message::TechSwitchRequested);
+            } else {
+                bot_state.strategy.active = Some(plan);
+            }
+        }
+    }
+
+    fn queue_tech(&self, bot_state: &mut BotState) {
+        if let Some(plan) = &bot_state.strategy.active {
+            for command in plan.tech_path.iter() {
+                bot_state.build_queue.push(
+                    command.clone(),
+                    false,
+                    bot_state.params.priority_strategy_tech,
+                );
+            }
+        }
+    }
+}
+
+impl AIComponent for StrategyManager {
+    fn process(&mut self, bot: &mut Bot, bot_state: &mut BotState) {
+        self.update_plan(bot, bot_state);
+        self.queue_tech(bot_state);
+    }
+
+    fn on_event(&mut self, event: &Event, bot_state: &mut BotState) {
+        if let Event::RandomRaceDetected(race) = event {
+            bot_state.strategy.update_natural_wall(*race);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_sc2::bot::Bot;
+    use rust_sc2::prelude::*;
+
+    use crate::command_queue::Command;
+    use crate::managers::strategy_manager::{StrategyManager, StrategyPlan};
+    use crate::{AIComponent, BotState};
+
+    #[test]
+    fn wall_decided_when_random_race_detected() {
+        let mut bot_state = BotState::default();
+        bot_state.strategy.wall_requested = true;
+        bot_state.strategy.update_natural_wall(Race::Random);
+        assert!(!bot_state.strategy.natural_wall);
+
+        let mut manager = StrategyManager::default();
+        manager.on_event(&Event::RandomRaceDetected(Race::Protoss), &mut bot_state);
+        assert!(bot_state.strategy.natural_wall);
+        manager.on_event(&Event::RandomRaceDetected(Race::Terran), &mut bot_state);
+        assert!(!bot_state.strategy.natural_wall);
+    }
+
+    #[test]
+    fn expired_plan_tech_removed() {
+        let mut bot = Bot::default();
+        let mut bot_state = BotState::default();
+        bot_state.params.debug_text = false;
+        let warren = Command::new_unit(UnitTypeId::RoachWarren, 1, true);
+        bot_state.strategy.active =
+            Some(StrategyPlan::new("RoachTiming", 60f32).with_tech(warren.clone()));
+        let mut manager = StrategyManager::default();
+        manager.process(&mut bot, &mut bot_state);
+        let queued = |bot_state: &BotState| {
+            bot_state
+                .build_queue
+                .into_iter()
+                .map(|e| e.command)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(queued(&bot_state), vec![warren]);
+
+        bot.time = 60f32;
+        manager.process(&mut bot, &mut bot_state);
+        assert!(bot_state.strategy.active.is_none());
+        assert!(queued(&bot_state).is_empty());
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rust_sc2::bot::Bot;
+use rust_sc2::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::bus::{Message, Subscription};
+use crate::utils::Strength;
+use crate::{AIComponent, BotState, SpendingFocus};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FocusChange {
+    pub time: f32,
+    pub focus: SpendingFocus,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimelinePoint {
+    pub time: f32,
+    pub supply: u32,
+    pub workers: usize,
+    pub army_strength: f32,
+    pub enemy_strength: f32,
+}
+
+// What happened in a game, written as JSON to the history directory at game end.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MatchSummary {
+    pub timestamp: u64,
+    pub opponent: String,
+    pub enemy_race: Option<Race>,
+    pub map: String,
+    pub opening: String,
+    pub result: Option<GameResult>,
+    #[serde(default)]
+    pub seed: u64,
+    pub game_length: f32,
+    pub spending_focus: Vec<FocusChange>,
+    pub timeline: Vec<TimelinePoint>,
+    pub units_lost: BTreeMap<String, u32>,
+    pub units_killed: BTreeMap<String, u32>,
+}
+
+impl MatchSummary {
+    pub fn finish(&mut self, bot: &Bot, opponent: &str, opening: &str, result: GameResult) {
+        self.timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        self.opponent = opponent.to_string();
+        self.enemy_race = Some(bot.enemy_race);
+        self.map = bot.game_info.map_name.clone();
+        self.opening = opening.to_string();
+        self.result = Some(result);
+        self.game_length = bot.time;
+    }
+
+    pub fn save(&self, dir: &str) -> io::Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+        let opponent = self
+            .opponent
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect::<String>();
+        let path = Path::new(dir).join(format!("{}_{}.json", self.timestamp, opponent));
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+}
+
+#[derive(Default)]
+pub struct SummaryManager {
+    known_units: HashMap<u64, UnitTypeId>,
+    // Own units created since the last step, typed once they are observed
+    created: Vec<u64>,
+    visible_enemies: HashSet<u64>,
+    started: bool,
+    subscription: Subscription,
+    last_sample: Option<f32>,
+}
+
+impl SummaryManager {
+    const SAMPLE_INTERVAL: f32 = 10f32;
+
+    // Own units are typed when created or morphed, enemies when they enter vision.
+    fn track_units(&mut self, bot: &Bot, bot_state: &BotState) {
+        if !self.started {
+            self.started = true;
+            for unit in bot.units.my.all.iter() {
+                self.known_units.insert(unit.tag(), unit.type_id());
+            }
+        }
+        for tag in self.created.drain(..) {
+            if let Some(unit) = bot.units.my.all.get(tag) {
+                self.known_units.insert(tag, unit.type_id());
+            }
+        }
+        self.read_messages(bot_state);
+        let mut visible_enemies = HashSet::new();
+        for unit in bot.units.enemy.all.iter() {
+            if !self.visible_enemies.contains(&unit.tag()) {
+                self.known_units.insert(unit.tag(), unit.type_id());
+            }
+            visible_enemies.insert(unit.tag());
+        }
+        self.visible_enemies = visible_enemies;
+    }
+
+    fn read_messages(&mut self, bot_state: &BotState) {
+        for message in bot_state.messages.receive(&mut self.subscription) {
+            if let Message::UnitMorphed { tag, to, .. } = message {
+                self.known_units.insert(tag, to);
+            }
+        }
+    }
+
+    fn track_focus(&self, bot: &Bot, bot_state: &mut BotState) {
+        let summary = &mut bot_state.match_summary;
+        if summary.spending_focus.last().map(|c| &c.focus) != Some(&bot_state.spending_focus) {
+            summary.spending_focus.push(FocusChange {
+                time: bot.time,
+                focus: bot_state.spending_focus.clone(),
+            });
+        }
+    }
+
+    fn sample(&mut self, bot: &Bot, bot_state: &mut BotState) {
+        if self
+            .last_sample
+            .map_or(false, |last| last + Self::SAMPLE_INTERVAL > bot.time)
+        {
+            return;
+        }
+        self.last_sample = Some(bot.time);
+        let army_strength = bot.units.my.units.filter(|u| !u.is_worker()).strength(bot);
+        let enemy_strength = bot_state
+            .enemy_cache
+            .units
+            .filter(|u| !u.is_worker() && !u.is_structure())
+            .strength(bot);
+        bot_state.match_summary.timeline.push(TimelinePoint {
+            time: bot.time,
+            supply: bot.supply_used,
+            workers: bot.units.my.workers.len(),
+            army_strength,
+            enemy_strength,
+        });
+    }
+}
+
+impl AIComponent for SummaryManager {
+    fn process(&mut self, bot: &mut Bot, bot_state: &mut BotState) {
+        self.track_units(bot, bot_state);
+        self.track_focus(bot, bot_state);
+        self.sample(bot, bot_state);
+    }
+
+    fn on_event(&mut self, event: &Event, bot_state: &mut BotState) {
+        match event {
+            Event::UnitCreated(tag) | Event::ConstructionStarted(tag) => self.created.push(*tag),
+            Event::UnitDestroyed(tag, alliance) => {
+                if let Some(unit_type) = self.known_units.remove(tag) {
+                    let counter = match alliance {
+                        Some(Alliance::Own) => &mut bot_state.match_summary.units_lost,
+                        Some(Alliance::Enemy) => &mut bot_state.match_summary.units_killed,
+                        _ => return,
+                    };
+                    *counter.entry(format!("{:?}", unit_type)).or_default() += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_sc2::prelude::*;
+
+    use crate::bus::Message;
+    use crate::managers::summary_manager::{MatchSummary, SummaryManager};
+    use crate::{AIComponent, BotState};
+
+    #[test]
+    fn summary_saved_and_loaded() {
+        let mut summary = MatchSummary {
+            timestamp: 1_700_000_000,
+            opponent: "Some Bot!".to_string(),
+            enemy_race: Some(Race::Protoss),
+            game_length: 600f32,
+            ..Default::default()
+        };
+        summary.units_lost.insert("Drone".to_string(), 3);
+        let dir = std::env::temp_dir().join(format!(
+            "caninana_summary_saved_and_loaded_{}",
+            std::process::id()
+        ));
+        let path = summary.save(dir.to_str().unwrap()).unwrap();
+        assert!(path.ends_with("1700000000_Some_Bot_.json"));
+        assert_eq!(MatchSummary::load(&path).unwrap(), summary);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn losses_and_kills_counted() {
+        let mut bot_state = BotState::default();
+        let mut manager = SummaryManager::default();
+        manager.known_units.insert(1, UnitTypeId::Larva);
+        manager.known_units.insert(2, UnitTypeId::Zealot);
+        bot_state.messages.publish(Message::UnitMorphed {
+            tag: 1,
+            from: UnitTypeId::Larva,
+            to: UnitTypeId::Drone,
+        });
+        manager.read_messages(&bot_state);
+        for event in [
+            Event::UnitDestroyed(1, Some(Alliance::Own)),
+            Event::UnitDestroyed(2, Some(Alliance::Enemy)),
+            Event::UnitDestroyed(2, Some(Alliance::Enemy)),
+            Event::UnitDestroyed(3, Some(Alliance::Enemy)),
+        ] {
+            manager.on_event(&event, &mut bot_state);
+        }
+        let summary = &bot_state.match_summary;
+        assert_eq!(summary.units_lost.get("Drone"), Some(&1));
+        assert_eq!(summary.units_lost.len(), 1);
+        assert_eq!(summary.units_killed.get("Zealot"), Some(&1));
+        assert_eq!(summary.units_killed.len(), 1);
+    }
+}
@@ -1,15 +1,20 @@
 use std::cmp::Ordering;
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 
 use itertools::Itertools;
 
 use log::debug;
 use rust_sc2::bot::Bot;
 use rust_sc2::prelude::*;
+use serde_json::{json, Value};
 
+use crate::bus::{Message, Subscription};
 use crate::command_queue::Command;
-use crate::params::*;
-use crate::utils::Center;
+#[cfg(any(test, feature = "testing"))]
+use crate::fake_game::FakeGame;
+use crate::game_view::{center, closest, BotView, GameView};
+#[cfg(any(test, feature = "testing"))]
+use crate::scenario::ScenarioComponent;
 use crate::utils::*;
 use crate::*;
 
@@ -30,6 +35,13 @@ pub struct WorkerManager {
     // (resource_tag, worker_tag)
     resources: HashMap<u64, HashSet<u64>>,
     worker_defense: bool,
+    subscription: Subscription,
+    // Townhalls the army reported under attack since the last step
+    attacked_bases: HashSet<u64>,
+    completed_structures: Vec<u64>,
+    construction_started: bool,
+    // Drones the production sent ahead to build
+    builders: HashSet<u64>,
 }
 
 impl WorkerManager {
@@ -37,6 +49,7 @@ impl WorkerManager {
         debug!("Unit destroyed {:?}", tag);
         self.unassign_worker(tag);
         self.worker_decision.remove(&tag);
+        self.builders.remove(&tag);
     }
 
     fn unassign_resource(&mut self, tag: u64) {
@@ -66,73 +79,164 @@ impl WorkerManager {
     const MINERAL_WORKERS: usize = 2;
     const GEYSERS_WORKERS: usize = 3;
 
-    fn decision(&mut self, bot: &mut Bot) {
-        let defense_range = bot
-            .start_location
-            .distance(bot.ramps.my.points.center_point().unwrap());
+    fn read_messages(&mut self, bot_state: &BotState) {
+        self.attacked_bases.clear();
+        for message in bot_state.messages.receive(&mut self.subscription) {
+            match message {
+                Message::BaseUnderAttack(tag) => {
+                    self.attacked_bases.insert(tag);
+                }
+                Message::BuilderSent(tag) => {
+                    self.builders.insert(tag);
+                }
+                Message::BuilderReleased(tag) => {
+                    self.builders.remove(&tag);
+                }
+                Message::UnitMorphed { tag, from, .. } if from.is_worker() => {
+                    self.unit_destroyed(tag);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Builder drones turn into the structure without a destroyed event. Only
+    // drones that were building are forgotten, drones inside an extractor are
+    // missing from the observation too.
+    fn forget_builders<G: GameView>(&mut self, game: &G) {
+        if !self.construction_started {
+            return;
+        }
+        self.construction_started = false;
+        let builders = self
+            .worker_decision
+            .iter()
+            .filter(|(tag, decision)| {
+                (**decision == WorkerDecision::Build || self.builders.contains(tag))
+                    && game.unit(**tag).map_or(true, |u| {
+                        !u.is_worker() && u.type_id != UnitTypeId::DroneBurrowed
+                    })
+            })
+            .map(|(tag, _)| *tag)
+            .collect::<Vec<u64>>();
+        for builder in builders {
+            self.unit_destroyed(builder);
+        }
+    }
+
+    // Long distance miners look for a closer mineral once a townhall is done.
+    fn rebalance<G: GameView>(&mut self, game: &G) {
+        let townhall_types = game.townhall_types();
+        let townhall_completed = self
+            .completed_structures
+            .drain(..)
+            .filter_map(|tag| game.unit(tag))
+            .any(|u| townhall_types.contains(&u.type_id));
+        if !townhall_completed {
+            return;
+        }
+        let townhalls = game.my_townhalls();
+        let distant_resources = self
+            .resources
+            .keys()
+            .filter(|resource| {
+                game.unit(**resource).map_or(true, |r| {
+                    !townhalls
+                        .iter()
+                        .any(|t| t.is_ready() && t.distance(r.position) < 9f32)
+                })
+            })
+            .copied()
+            .collect::<Vec<u64>>();
+        for resource in distant_resources {
+            debug!("Rebalancing workers from {:?}", resource);
+            self.unassign_resource(resource);
+        }
+    }
+
+    fn decision<G: GameView>(&mut self, game: &G, bot_state: &mut BotState) {
+        let defense_range = game
+            .start_location()
+            .distance(game.my_ramp_center().unwrap());
         let surroundings_range = 15f32;
 
-        let close_units = bot.units.enemy.units.filter(|f| {
-            f.can_attack_ground()
-                && bot
-                    .units
-                    .my
-                    .townhalls
-                    .closest_distance(f.position())
-                    .unwrap_or_max()
-                    <= defense_range
-        });
+        let townhalls = game.my_townhalls();
+        let closest_townhall_distance = |position: Point2| {
+            townhalls
+                .iter()
+                .map(|t| t.distance(position))
+                .min_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+                .unwrap_or_max()
+        };
+        let attacked_bases = self
+            .attacked_bases
+            .iter()
+            .filter_map(|tag| game.unit(*tag))
+            .collect::<Vec<_>>();
+        let enemies = game.enemy_units();
+        let close_units = enemies
+            .iter()
+            .filter(|f| {
+                !f.is_structure()
+                    && f.can_attack_ground
+                    && (closest_townhall_distance(f.position) <= defense_range
+                        || attacked_bases
+                            .iter()
+                            .any(|b| b.distance(f.position) <= defense_range * 2f32))
+            })
+            .collect::<Vec<_>>();
 
-        let units_attacking = bot
-            .units
-            .enemy
-            .all
+        let units_attacking = enemies
+            .iter()
             .filter(|u| {
-                u.can_attack_ground()
-                    && close_units.closest_distance(u.position()).unwrap_or_max()
+                u.can_attack_ground
+                    && close_units
+                        .iter()
+                        .map(|c| c.distance(u.position))
+                        .min_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+                        .unwrap_or_max()
                         < surroundings_range
             })
-            .len();
+            .count();
 
-        let weak_attackers = bot
-            .units
-            .enemy
-            .all
+        let weak_attackers = enemies
+            .iter()
             .filter(|u| {
-                (u.is_worker() || u.type_id() == UnitTypeId::Zergling)
-                    && !close_units.in_range(u, surroundings_range).is_empty()
+                (u.is_worker() || u.type_id == UnitTypeId::Zergling)
+                    && close_units
+                        .iter()
+                        .any(|c| game.in_range(c.tag, u.tag, surroundings_range))
             })
-            .len();
-        let enemy_buildings_close = bot.units.enemy.all.filter(|f| {
-            !f.is_ready()
-                && bot
-                    .units
-                    .my
-                    .townhalls
-                    .closest_distance(f.position())
-                    .unwrap_or_max()
-                    <= defense_range * 2f32
-        });
-        let spines_close = enemy_buildings_close
-            .of_type(UnitTypeId::SpineCrawler)
-            .len();
-        let pylons_close = enemy_buildings_close.of_type(UnitTypeId::Pylon).len();
-        let cannons_close = enemy_buildings_close
-            .of_type(UnitTypeId::PhotonCannon)
-            .len();
-        let halls_close = enemy_buildings_close
-            .of_types(&vec![
-                UnitTypeId::CommandCenter,
-                UnitTypeId::PlanetaryFortress,
-            ])
-            .len();
-
-        let army_supply = bot
-            .units
-            .my
-            .units
-            .filter(|f| f.is_ready() && !f.is_worker() && f.type_id() != UnitTypeId::Queen)
-            .sum(|f| f.supply_cost()) as usize;
+            .count();
+        let enemy_buildings_close = enemies
+            .iter()
+            .filter(|f| {
+                !f.is_ready() && closest_townhall_distance(f.position) <= defense_range * 2f32
+            })
+            .collect::<Vec<_>>();
+        let buildings_close = |unit_types: &[UnitTypeId]| {
+            enemy_buildings_close
+                .iter()
+                .filter(|u| unit_types.contains(&u.type_id))
+                .count()
+        };
+        let spines_close = buildings_close(&[UnitTypeId::SpineCrawler]);
+        let pylons_close = buildings_close(&[UnitTypeId::Pylon]);
+        let cannons_close = buildings_close(&[UnitTypeId::PhotonCannon]);
+        let halls_close =
+            buildings_close(&[UnitTypeId::CommandCenter, UnitTypeId::PlanetaryFortress]);
+
+        let my_units = game.my_units();
+        let army_supply = my_units
+            .iter()
+            .filter(|f| {
+                f.is_ready()
+                    && !f.is_worker()
+                    && !f.is_structure()
+                    && f.type_id != UnitTypeId::Queen
+            })
+            .map(|f| game.unit_cost(f.type_id).map_or(0f32, |cost| cost.supply))
+            .sum::<f32>() as usize;
 
         let mut needed_fighters = spines_close * 5
             + pylons_close * 5
@@ -150,29 +254,28 @@ impl WorkerManager {
             needed_fighters
         );
         needed_fighters = needed_fighters.saturating_sub(army_supply);
-        self.worker_defense = self.worker_defense || weak_attackers > 5;
+        if !self.worker_defense && weak_attackers > 5 {
+            self.worker_defense = true;
+            bot_state.messages.publish(Message::WorkerRushDetected);
+        }
 
-        for worker in bot
-            .units
-            .my
-            .all
-            .of_types(&vec![UnitTypeId::Drone, UnitTypeId::DroneBurrowed])
+        for worker in my_units
             .iter()
-            .sorted_by(|a, b| b.hits().cmp(&a.hits()).then(a.tag().cmp(&b.tag())))
+            .filter(|u| [UnitTypeId::Drone, UnitTypeId::DroneBurrowed].contains(&u.type_id))
+            .sorted_by(|a, b| b.hits.cmp(&a.hits).then(a.tag.cmp(&b.tag)))
         {
-            let close_attackers = !bot
-                .units
-                .enemy
-                .units
-                .filter(|f| f.can_attack_unit(worker) && f.in_range(worker, f.speed()))
-                .is_empty();
-            let decision = if worker.is_constructing() {
+            let close_attackers = enemies.iter().any(|f| {
+                !f.is_structure()
+                    && game.can_attack_unit(f.tag, worker.tag)
+                    && game.in_range(f.tag, worker.tag, f.speed)
+            });
+            let decision = if worker.is_constructing || self.builders.contains(&worker.tag) {
                 WorkerDecision::Build
             } else if needed_fighters > 0 {
                 needed_fighters -= 1;
                 WorkerDecision::Fight
             } else if close_attackers
-                && (worker.hits_percentage().unwrap_or_default() <= 0.55f32
+                && (worker.health_percentage.unwrap_or_default() <= 0.55f32
                     || units_attacking > weak_attackers.max(1) * 2)
             {
                 WorkerDecision::Run
@@ -181,30 +284,31 @@ impl WorkerManager {
             };
             debug!(
                 "W[{:?}] H[{:?}] D[{:?}]",
-                worker.tag(),
-                worker.hits(),
+                worker.tag,
+                worker.hits,
                 decision.clone()
             );
-            self.worker_decision.insert(worker.tag(), decision);
+            self.worker_decision.insert(worker.tag, decision);
         }
     }
 
     // TODO: Remove from long distance mining if needed.
-    fn assignment(&mut self, bot: &Bot) {
+    fn assignment<G: GameView>(&mut self, game: &G) {
         {
+            let townhalls = game.my_townhalls();
             let clear_assignment = self
                 .worker_decision
                 .iter()
                 .filter(|(worker_tag, decision)| {
-                    if let Some(worker) = bot.units.all.get(**worker_tag) {
-                        if worker.is_returning() {
-                            if let Some(target) = worker.target_tag() {
-                                if let Some(unit) = bot.units.my.townhalls.get(target) {
-                                    return worker.distance(unit) > 9f32;
+                    if let Some(worker) = game.unit(**worker_tag) {
+                        if worker.is_returning {
+                            if let Some(target) = worker.target_tag {
+                                if let Some(unit) = townhalls.iter().find(|t| t.tag == target) {
+                                    return worker.distance(unit.position) > 9f32;
                                 }
                             }
                         }
-                        worker.is_idle() || **decision != WorkerDecision::Gather
+                        worker.is_idle || **decision != WorkerDecision::Gather
                     } else {
                         **decision != WorkerDecision::Gather
                     }
@@ -218,59 +322,40 @@ impl WorkerManager {
 
             let clear_resources = self
                 .resources
-                .iter()
-                .filter(|(resource, _)| {
-                    if let Some(unit) = bot.units.all.get(**resource) {
-                        unit.mineral_contents().unwrap_or_default()
-                            + unit.vespene_contents().unwrap_or_default()
-                            == 0
-                    } else if let Some(building) = bot.units.my.gas_buildings.get(**resource) {
-                        building.mineral_contents().unwrap_or_default()
-                            + building.vespene_contents().unwrap_or_default()
-                            == 0
-                    } else if let Some(resource) = bot.units.resources.get(**resource) {
-                        resource.mineral_contents().unwrap_or_default()
-                            + resource.vespene_contents().unwrap_or_default()
-                            == 0
-                    } else {
-                        true
-                    }
+                .keys()
+                .filter(|resource| {
+                    game.unit(**resource)
+                        .map_or(true, |unit| unit.resources_left() == 0)
                 })
-                .map(|(resource, _)| *resource)
+                .copied()
                 .collect::<Vec<u64>>();
 
             for resource in clear_resources {
                 self.unassign_resource(resource);
             }
         }
+        let mineral_fields = game.mineral_fields();
+        let gas_buildings = game.my_gas_buildings();
         let mut resources = VecDeque::new();
-        for townhall in bot
-            .units
-            .my
-            .townhalls
-            .sorted(|t| {
-                if t.is_ready() {
-                    t.ideal_harvesters()
-                        .unwrap_or(12)
-                        .saturating_sub(t.assigned_harvesters().unwrap_or_default())
-                } else {
-                    18
-                }
-            })
-            .iter()
-        {
+        for townhall in game.my_townhalls().iter().sorted_by_key(|t| {
+            if t.is_ready() {
+                t.ideal_harvesters
+                    .unwrap_or(12)
+                    .saturating_sub(t.assigned_harvesters.unwrap_or_default())
+            } else {
+                18
+            }
+        }) {
             let mut minerals = VecDeque::new();
-            for mineral in bot
-                .units
-                .mineral_fields
-                .closer(9f32, townhall.position())
+            for mineral in mineral_fields
                 .iter()
+                .filter(|m| m.distance(townhall.position) < 9f32)
                 .sorted_by(|a, b| {
-                    a.distance(townhall)
-                        .partial_cmp(&b.distance(townhall))
+                    a.distance(townhall.position)
+                        .partial_cmp(&b.distance(townhall.position))
                         .unwrap_or(Ordering::Equal)
                 })
-                .map(|m| m.tag())
+                .map(|m| m.tag)
             {
                 match self.resources.get(&mineral) {
                     None => {
@@ -290,14 +375,14 @@ impl WorkerManager {
             for mineral in minerals {
                 resources.push_back(mineral);
             }
-            for geyser in bot
-                .units
-                .my
-                .gas_buildings
-                .filter(|u| u.is_almost_ready() && u.vespene_contents().unwrap_or_default() > 0)
-                .closer(9f32, townhall.position())
+            for geyser in gas_buildings
                 .iter()
-                .map(|g| g.tag())
+                .filter(|u| {
+                    u.is_almost_ready()
+                        && u.vespene_contents.unwrap_or_default() > 0
+                        && u.distance(townhall.position) < 9f32
+                })
+                .map(|g| g.tag)
             {
                 let missing = if let Some(workers) = self.resources.get(&geyser) {
                     Self::GEYSERS_WORKERS - workers.len()
@@ -309,20 +394,16 @@ impl WorkerManager {
                 }
             }
         }
-        let reference_expansion = bot
+        let reference_expansion = game
             .free_expansions()
-            .map(|e| e.loc)
-            .next()
-            .unwrap_or(bot.start_location);
-        let long_mineral = bot
-            .units
-            .mineral_fields
+            .first()
+            .map_or(game.start_location(), |e| e.loc);
+        let long_mineral = mineral_fields
             .iter()
-            .filter(|m| !self.resources.contains_key(&m.tag()))
+            .filter(|m| !self.resources.contains_key(&m.tag))
             .sorted_by(|a, b| {
-                reference_expansion
-                    .distance(a.position())
-                    .partial_cmp(&reference_expansion.distance(b.position()))
+                a.distance(reference_expansion)
+                    .partial_cmp(&b.distance(reference_expansion))
                     .unwrap_or(Ordering::Equal)
             })
             .next();
@@ -337,165 +418,191 @@ impl WorkerManager {
                 if let Some(resource) = resources.pop_front() {
                     self.assign_worker(worker, resource);
                 } else if let Some(mineral) = long_mineral {
-                    self.assign_worker(worker, mineral.tag());
+                    self.assign_worker(worker, mineral.tag);
                 }
             }
         }
     }
 
-    fn micro(&mut self, bot: &mut Bot) {
-        let retreat_mineral = bot
-            .units
-            .mineral_fields
-            .closest(bot.start_location)
+    fn micro<G: GameView>(&mut self, game: &mut G) {
+        let retreat_mineral = closest(game.mineral_fields().iter(), game.start_location())
+            .cloned()
             .unwrap();
-        for burrowed_worker in bot.units.my.all.of_type(UnitTypeId::DroneBurrowed) {
+        let burrowed_workers = game
+            .my_units()
+            .iter()
+            .filter(|u| u.type_id == UnitTypeId::DroneBurrowed)
+            .map(|u| u.tag)
+            .collect::<Vec<_>>();
+        for burrowed_worker in burrowed_workers {
             if self
                 .worker_decision
-                .get(&burrowed_worker.tag())
+                .get(&burrowed_worker)
                 .unwrap_or(&WorkerDecision::Gather)
                 != &WorkerDecision::Run
             {
-                burrowed_worker.use_ability(AbilityId::BurrowUpDrone, false);
+                game.use_ability(burrowed_worker, AbilityId::BurrowUpDrone);
             }
         }
-        for worker in bot.units.my.workers.iter() {
+        // Copied once, the workers get their orders while the game is still read
+        let enemies = game.enemy_units().into_owned();
+        let workers = game.my_workers().into_owned();
+        let has_townhalls = !game.my_townhalls().is_empty();
+        for worker in workers.iter() {
             let decision = self
                 .worker_decision
-                .get(&worker.tag())
+                .get(&worker.tag)
                 .unwrap_or(&WorkerDecision::Gather);
             match decision {
                 WorkerDecision::Run => {
-                    if bot.has_upgrade(UpgradeId::Burrow) {
-                        worker.use_ability(AbilityId::BurrowDownDrone, false);
+                    if game.has_upgrade(UpgradeId::Burrow) {
+                        game.use_ability(worker.tag, AbilityId::BurrowDownDrone);
                     } else {
-                        let attackers = bot.units.enemy.units.filter(|f| {
-                            f.can_attack_unit(worker)
-                                && f.in_range(worker, 1f32 + f.speed() + worker.speed())
-                        });
-                        if !attackers.filter(|u| u.is_worker()).is_empty() {
-                            worker.order_gather(retreat_mineral.tag(), false);
-                        } else if let Some(run_from) = attackers.center() {
-                            worker.order_move_to(
-                                Target::Pos(worker.position().towards(run_from, -worker.speed())),
-                                0.1f32,
-                                false,
+                        let attackers = enemies
+                            .iter()
+                            .filter(|f| {
+                                !f.is_structure()
+                                    && game.can_attack_unit(f.tag, worker.tag)
+                                    && game.in_range(
+                                        f.tag,
+                                        worker.tag,
+                                        1f32 + f.speed + worker.speed,
+                                    )
+                            })
+                            .cloned()
+                            .collect::<Vec<_>>();
+                        if attackers.iter().any(|u| u.is_worker()) {
+                            game.gather(worker.tag, retreat_mineral.tag);
+                        } else if let Some(run_from) = center(&attackers) {
+                            game.move_to(
+                                worker.tag,
+                                worker.position.towards(run_from, -worker.speed),
                             );
                         }
                     }
                 }
                 WorkerDecision::Fight => {
-                    let weakest_in_range = bot
-                        .units
-                        .enemy
-                        .all
+                    let weakest_in_range = enemies
                         .iter()
-                        .filter(|u| worker.can_attack_unit(u) && worker.in_range(u, 0.3f32))
-                        .sorted_by(|a, b| a.hits().cmp(&b.hits()))
+                        .filter(|u| {
+                            game.can_attack_unit(worker.tag, u.tag)
+                                && game.in_range(worker.tag, u.tag, 0.3f32)
+                        })
+                        .sorted_by(|a, b| a.hits.cmp(&b.hits))
                         .next();
-                    let closest_to_base = bot
-                        .units
-                        .enemy
-                        .all
+                    let attackable = enemies
                         .iter()
-                        .filter(|u| worker.can_attack_unit(u))
-                        .closest(retreat_mineral.position());
-                    if worker.weapon_cooldown().unwrap_or_default() > 5f32 {
-                        worker.order_gather(retreat_mineral.tag(), false);
-                    } else if worker.is_carrying_resource() {
-                        if !worker.is_returning() {
-                            worker.return_resource(false);
+                        .filter(|u| game.can_attack_unit(worker.tag, u.tag));
+                    let closest_to_base = closest(attackable, retreat_mineral.position);
+                    if worker.weapon_cooldown.unwrap_or_default() > 5f32 {
+                        game.gather(worker.tag, retreat_mineral.tag);
+                    } else if worker.is_carrying_resource {
+                        if !worker.is_returning {
+                            game.return_resource(worker.tag);
                         }
                     } else if let Some(target) = weakest_in_range {
-                        worker.order_attack(Target::Tag(target.tag()), false);
+                        game.attack(worker.tag, target.tag);
                     } else if let Some(target) = closest_to_base {
-                        worker.order_attack(Target::Pos(target.position()), false);
+                        game.attack_move(worker.tag, target.position);
                     } else {
-                        worker.order_gather(retreat_mineral.tag(), false);
+                        game.gather(worker.tag, retreat_mineral.tag);
                     }
                 }
                 WorkerDecision::Gather => {
-                    let assignment = self.assignment.get(&worker.tag());
+                    let assignment = self.assignment.get(&worker.tag);
                     if let Some(current_assignment) = assignment {
-                        if worker.is_carrying_resource()
-                            && !worker.is_returning()
-                            && !bot.units.my.townhalls.is_empty()
-                        {
-                            worker.return_resource(false);
+                        if worker.is_carrying_resource && !worker.is_returning && has_townhalls {
+                            game.return_resource(worker.tag);
                         }
-                        if !worker.is_carrying_resource() || worker.is_idle() {
-                            if let Some(worker_assignment) = worker.target_tag() {
+                        if !worker.is_carrying_resource || worker.is_idle {
+                            if let Some(worker_assignment) = worker.target_tag {
                                 if worker_assignment != *current_assignment {
-                                    worker.order_gather(*current_assignment, false);
+                                    game.gather(worker.tag, *current_assignment);
                                 }
                             } else {
-                                worker.order_gather(*current_assignment, false);
+                                game.gather(worker.tag, *current_assignment);
                             }
                         }
                     }
                 }
                 WorkerDecision::Build => {
-                    debug!(
-                        "Worker {:?} is building {:?}",
-                        worker.tag(),
-                        worker.ordered_ability()
-                    );
+                    debug!("Worker {:?} is building", worker.tag);
                 }
             }
         }
     }
 
-    fn queue_worker(&self, bot: &mut Bot, bot_state: &mut BotState) {
-        let ideal_miners = bot
-            .units
-            .my
-            .townhalls
+    fn trace<G: GameView>(&self, game: &G, bot_state: &mut BotState) {
+        if !bot_state.trace.is_enabled() {
+            return;
+        }
+        let decisions = self
+            .worker_decision
+            .iter()
+            .map(|(tag, decision)| {
+                let assignment = self.assignment.get(tag);
+                (
+                    *tag,
+                    json!({"decision": format!("{:?}", decision), "assignment": assignment}),
+                )
+            })
+            .collect::<BTreeMap<u64, Value>>();
+        let units = decisions.keys().copied().collect();
+        let data = json!({
+            "worker_defense": self.worker_defense,
+            "workers": decisions,
+        });
+        bot_state
+            .trace
+            .record(game.game_loop(), "WorkerManager", units, data);
+    }
+
+    fn queue_worker<G: GameView>(&self, game: &G, bot_state: &mut BotState) {
+        let ideal_miners = game
+            .my_townhalls()
             .iter()
             .map(|e| {
                 if e.is_ready() {
-                    e.ideal_harvesters().unwrap_or_default()
+                    e.ideal_harvesters.unwrap_or_default()
                 } else {
                     12
                 }
             })
             .sum::<u32>();
 
-        let ideal_geysers = bot
-            .units
-            .my
-            .gas_buildings
+        let ideal_geysers = game
+            .my_gas_buildings()
             .iter()
-            .map(|e| e.ideal_harvesters().unwrap_or_default().saturating_sub(1))
+            .map(|e| e.ideal_harvesters.unwrap_or_default().saturating_sub(1))
             .sum::<u32>();
 
-        let ideal_workers = MAX_WORKERS.min((ideal_miners + ideal_geysers) as usize);
+        let ideal_workers = bot_state
+            .params
+            .max_workers
+            .min((ideal_miners + ideal_geysers) as usize);
         let min_extra_workers = match bot_state.spending_focus {
-            SpendingFocus::Economy => bot.owned_expansions().count() * 2,
-            SpendingFocus::Balance => bot.owned_expansions().count() + 1,
+            SpendingFocus::Economy => game.owned_expansions().len() * 2,
+            SpendingFocus::Balance => game.owned_expansions().len() + 1,
             SpendingFocus::Army => 0,
         };
-        let drones = bot
-            .counter()
-            .all()
-            .count(bot.race_values.worker)
-            .saturating_sub(bot.counter().count(UnitTypeId::DroneBurrowed));
-        let min_workers =
-            if bot.counter().ordered().count(bot.race_values.worker) < min_extra_workers {
-                ideal_workers.min(drones + min_extra_workers)
-            } else {
-                ideal_workers.min(drones)
-            };
+        let worker = game.worker_type();
+        let drones = game
+            .count_all(worker)
+            .saturating_sub(game.count(UnitTypeId::DroneBurrowed));
+        let min_workers = if game.count_ordered(worker) < min_extra_workers {
+            ideal_workers.min(drones + min_extra_workers)
+        } else {
+            ideal_workers.min(drones)
+        };
         if self.worker_defense {
-            bot_state.build_queue.push(
-                Command::new_unit(bot.race_values.worker, 16, false),
-                true,
-                9999,
-            );
+            bot_state
+                .build_queue
+                .push(Command::new_unit(worker, 16, false), true, 9999);
         }
         bot_state.build_queue.push(
-            Command::new_unit(bot.race_values.worker, min_workers, false),
+            Command::new_unit(worker, min_workers, false),
             true,
-            PRIORITY_DRONE_ECONOMY,
+            bot_state.params.priority_drone_economy,
         );
         let ideal_priority = if bot_state.spending_focus == SpendingFocus::Economy {
             100
@@ -504,31 +611,167 @@ impl WorkerManager {
         };
 
         bot_state.build_queue.push(
-            Command::new_unit(bot.race_values.worker, ideal_workers, false),
+            Command::new_unit(worker, ideal_workers, false),
             false,
             ideal_priority,
         );
     }
+
+    fn step<G: GameView>(&mut self, game: &mut G, bot_state: &mut BotState) {
+        self.read_messages(bot_state);
+        self.forget_builders(game);
+        self.rebalance(game);
+        self.decision(game, bot_state);
+        self.assignment(game);
+        self.micro(game);
+        self.queue_worker(game, bot_state);
+        self.trace(game, bot_state);
+    }
 }
 
 impl AIComponent for WorkerManager {
     fn process(&mut self, bot: &mut Bot, bot_state: &mut BotState) {
-        self.decision(bot);
-        self.assignment(bot);
-        self.micro(bot);
-        self.queue_worker(bot, bot_state);
+        self.step(&mut BotView::new(bot), bot_state);
     }
 
     fn on_event(&mut self, event: &Event, _: &mut BotState) {
-        if let Event::UnitDestroyed(tag, alliance) = event {
-            match alliance {
+        match event {
+            Event::UnitDestroyed(tag, alliance) => match alliance {
                 Some(Alliance::Own) => {
                     self.unit_destroyed(*tag);
                 }
                 // mineral mined out
                 Some(Alliance::Neutral) => self.unassign_resource(*tag),
                 _ => {}
-            }
+            },
+            Event::ConstructionStarted(_) => self.construction_started = true,
+            Event::ConstructionComplete(tag) => self.completed_structures.push(*tag),
+            _ => {}
         }
     }
 }
+
+#[cfg(any(test, feature = "testing"))]
+impl ScenarioComponent for WorkerManager {
+    fn simulate(&mut self, game: &mut FakeGame, bot_state: &mut BotState) {
+        self.step(game, bot_state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_sc2::prelude::*;
+
+    use crate::fake_game::FakeGame;
+    use crate::game_view::UnitSnapshot;
+    use crate::managers::worker_manager::{WorkerDecision, WorkerManager};
+    use crate::{AIComponent, BotState};
+
+    fn mining_game() -> FakeGame {
+        let mut game = FakeGame::default();
+        let start = game.start_location;
+        game.units
+            .push(UnitSnapshot::new(1, UnitTypeId::Hatchery, start).with_harvesters(16, 0));
+        for tag in 2..5 {
+            let mut drone = UnitSnapshot::new(tag, UnitTypeId::Drone, start);
+            drone.is_idle = false;
+            game.units.push(drone);
+        }
+        game.mineral_fields = vec![
+            UnitSnapshot::new(
+                10,
+                UnitTypeId::MineralField,
+                start + Point2::new(7f32, 0f32),
+            )
+            .with_contents(1_500, 0),
+            UnitSnapshot::new(
+                11,
+                UnitTypeId::MineralField,
+                start + Point2::new(7f32, 2f32),
+            )
+            .with_contents(1_500, 0),
+        ];
+        game
+    }
+
+    fn gathering_manager() -> WorkerManager {
+        let mut manager = WorkerManager::default();
+        for tag in 2..5 {
+            manager.worker_decision.insert(tag, WorkerDecision::Gather);
+        }
+        manager
+    }
+
+    #[test]
+    fn closest_mineral_filled_first() {
+        let game = mining_game();
+        let mut manager = gathering_manager();
+        manager.assignment(&game);
+        assert_eq!(manager.assignment.len(), 3);
+        assert_eq!(manager.resources[&10].len(), 2);
+        assert_eq!(manager.resources[&11].len(), 1);
+    }
+
+    #[test]
+    fn running_worker_unassigned() {
+        let game = mining_game();
+        let mut manager = gathering_manager();
+        manager.assignment(&game);
+        manager.worker_decision.insert(2, WorkerDecision::Run);
+        manager.assignment(&game);
+        assert!(!manager.assignment.contains_key(&2));
+        assert_eq!(manager.assignment.len(), 2);
+    }
+
+    #[test]
+    fn distant_miners_rebalanced_when_townhall_completes() {
+        let mut game = mining_game();
+        let far_away = game.start_location + Point2::new(40f32, 0f32);
+        game.mineral_fields.push(
+            UnitSnapshot::new(20, UnitTypeId::MineralField, far_away).with_contents(1_500, 0),
+        );
+        let mut manager = gathering_manager();
+        manager.assign_worker(2, 20);
+        manager.assignment(&game);
+        game.units.push(UnitSnapshot::new(
+            5,
+            UnitTypeId::Hatchery,
+            game.start_location + Point2::new(0f32, 20f32),
+        ));
+        manager.on_event(&Event::ConstructionComplete(5), &mut BotState::default());
+        manager.rebalance(&game);
+        assert!(!manager.assignment.contains_key(&2));
+        manager.assignment(&game);
+        assert_ne!(manager.assignment[&2], 20);
+    }
+
+    #[test]
+    fn gas_workers_kept_when_construction_starts() {
+        let mut game = mining_game();
+        game.vespene_geysers.push(UnitSnapshot::new(
+            30,
+            UnitTypeId::Extractor,
+            game.start_location + Point2::new(0f32, 7f32),
+        ));
+        let mut manager = gathering_manager();
+        manager.assign_worker(2, 30);
+        manager.worker_decision.insert(3, WorkerDecision::Build);
+        // Drone 2 is inside the extractor, drone 3 became the structure
+        game.units.retain(|u| u.tag != 2 && u.tag != 3);
+        manager.on_event(&Event::ConstructionStarted(40), &mut BotState::default());
+        manager.forget_builders(&game);
+        assert_eq!(manager.worker_decision[&2], WorkerDecision::Gather);
+        assert_eq!(manager.assignment[&2], 30);
+        assert!(!manager.worker_decision.contains_key(&3));
+    }
+
+    #[test]
+    fn mined_out_resource_released() {
+        let mut game = mining_game();
+        let mut manager = gathering_manager();
+        manager.assignment(&game);
+        game.mineral_fields.remove(0);
+        manager.assignment(&game);
+        assert!(!manager.resources.contains_key(&10));
+    }
+}
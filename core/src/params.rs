@@ -1,63 +1,437 @@
-pub const DEBUG_DRAW: bool = false;
-pub const DEBUG_TEXT: bool = true;
-
-pub const MAX_WORKERS: usize = 80;
-pub const MAX_QUEENS: usize = 10;
-
-pub const DOUBLE_GAS_PER_BASE_WORKERS: usize = 38;
-pub const UNLOCK_ROACH_WORKERS: usize = 27;
-pub const UNLOCK_HYDRA_WORKERS: usize = 50;
-pub const UNLOCK_LATE_TECH_WORKERS: usize = 60;
-pub const UNLOCK_REALLY_LATE_TECH_WORKERS: usize = 70;
-pub const UNLOCK_OVERSEER_WORKERS: usize = 30;
-pub const UNLOCK_BURROW_WORKERS: usize = 25;
-pub const OVERLORD_SPEED_WORKERS: usize = 30;
-pub const UNLOCK_TUNNELING_CLAWS_WORKERS: usize = 30;
-
-pub const UNLOCK_UPGRADES_WORKERS: usize = 40;
-pub const SAVE_FOR_ATTACK_UPGRADES_ON_UNITS: usize = 5;
-pub const SAVE_FOR_DEFENSE_UPGRADES_ON_UNITS: usize = 10;
-
-pub const CREEP_DISTANCE_TO_HALL: f32 = 4.5f32;
-pub const CREEP_SPREAD_MAP_DISTANCE: usize = 5;
-pub const CREEP_SPREAD_DISTANCE: usize = 5;
-pub const CREEP_SPREAD_ENERGY_MIN: u32 = 40;
-pub const CREEP_SPREAD_ENERGY: u32 = 180;
-pub const CREEP_SPREAD_ENERGY_PER_TUMOR: u32 = 1;
-
-pub const TRANSFUSION_MIN_ENERGY: u32 = 50;
-pub const TRANSFUSION_MAX_RANGE: f32 = 15f32;
-pub const TRANSFUSION_MISSING_HEALTH: u32 = 50;
-
-pub const INJECTION_MAX_LARVA_PER_BASE: usize = 3;
-pub const INJECTION_MAX_LARVA: usize = 30;
-
-pub const RETREAT_HEALTH_PERCENTAGE: f32 = 0.4f32;
-pub const RETREAT_BASE_STRENGTH: f32 = 100f32;
-pub const BURROW_HEALTH_PERCENTAGE: f32 = 0.58f32;
-pub const BURROW_DETECTION_RANGE: f32 = 9f32;
-pub const UNBURROW_HEALTH_PERCENTAGE: f32 = 0.95f32;
-
-pub const PRIORITY_DRONE_ECONOMY: usize = 250;
-pub const PRIORITY_ARMY_REQUIREMENT: usize = 300;
-pub const PRIORITY_QUEEN: usize = 270;
-pub const PRIORITY_TUNNELING_CLAWS: usize = 290;
-pub const PRIORITY_GROUND_ARMOR: usize = 280;
-pub const PRIORITY_MISSILE_WEAPON: usize = 270;
-pub const PRIORITY_MELEE_WEAPON: usize = 260;
-pub const PRIORITY_FLYER_ARMOR: usize = 240;
-pub const PRIORITY_FLYER_WEAPON: usize = 250;
-pub const PRIORITY_EVOLUTION_CHAMBER: usize = 160;
-pub const PRIORITY_SPORE_CRAWLER: usize = 290;
-pub const PRIORITY_BURROW: usize = 300;
-pub const PRIORITY_LORD_SPEED: usize = 290;
-pub const PRIORITY_LING_SPEED: usize = 310;
-pub const PRIORITY_MIN_LINGS: usize = 320;
-
-pub const MIN_LINGS: usize = 2;
-
-pub const OVERSEER_SWAP_DISTANCE: f32 = 9f32;
-pub const OVERSEER_CHANGELING_DELAY: f32 = 5f32;
-
-pub const QUEEN_INJECT_SWITCH_BASE_RANGE: f32 = 3f32;
-pub const LING_ADVANCED_SCOUT_DELAY: f32 = 60f32;
+use std::fmt;
+use std::fs;
+
+use rust_sc2::prelude::*;
+use serde::{Deserialize, Serialize};
+use toml::value::Table;
+use toml::Value;
+
+// Tunable values, defaults can be overridden by a params file (with optional
+// per enemy race tables) and by `key=value` command line overrides.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Params {
+    pub debug_draw: bool,
+    pub debug_text: bool,
+
+    pub max_workers: usize,
+    pub max_queens: usize,
+
+    pub double_gas_per_base_workers: usize,
+    pub unlock_roach_workers: usize,
+    pub unlock_hydra_workers: usize,
+    pub unlock_late_tech_workers: usize,
+    pub unlock_really_late_tech_workers: usize,
+    pub unlock_overseer_workers: usize,
+    pub unlock_burrow_workers: usize,
+    pub overlord_speed_workers: usize,
+    pub unlock_tunneling_claws_workers: usize,
+
+    pub unlock_upgrades_workers: usize,
+    pub save_for_attack_upgrades_on_units: usize,
+    pub save_for_defense_upgrades_on_units: usize,
+
+    pub creep_distance_to_hall: f32,
+    pub creep_spread_map_distance: usize,
+    pub creep_spread_distance: usize,
+    pub creep_spread_energy_min: u32,
+    pub creep_spread_energy: u32,
+    pub creep_spread_energy_per_tumor: u32,
+
+    pub transfusion_min_energy: u32,
+    pub transfusion_max_range: f32,
+    pub transfusion_missing_health: u32,
+
+    pub injection_max_larva_per_base: usize,
+    pub injection_max_larva: usize,
+
+    pub retreat_health_percentage: f32,
+    pub retreat_base_strength: f32,
+    pub burrow_health_percentage: f32,
+    pub burrow_detection_range: f32,
+    pub unburrow_health_percentage: f32,
+
+    pub priority_drone_economy: usize,
+    pub priority_army_requirement: usize,
+    pub priority_queen: usize,
+    pub priority_tunneling_claws: usize,
+    pub priority_ground_armor: usize,
+    pub priority_missile_weapon: usize,
+    pub priority_melee_weapon: usize,
+    pub priority_flyer_armor: usize,
+    pub priority_flyer_weapon: usize,
+    pub priority_evolution_chamber: usize,
+    pub priority_spore_crawler: usize,
+    pub priority_burrow: usize,
+    pub priority_lord_speed: usize,
+    pub priority_ling_speed: usize,
+    pub priority_min_lings: usize,
+    pub priority_strategy_tech: usize,
+
+    pub min_lings: usize,
+
+    pub overseer_swap_distance: f32,
+    pub overseer_changeling_delay: f32,
+
+    pub queen_inject_switch_base_range: f32,
+    pub ling_advanced_scout_delay: f32,
+
+    // Seconds a queue entry can hold resources before it is reported as starved
+    pub reservation_starved_time: f32,
+    // Larva is only held for entries affordable within these seconds
+    pub larva_save_time: f32,
+    // Seconds of slack when sending a drone ahead to a building spot
+    pub builder_premove_margin: f32,
+    // Seconds a rejected building placement isn't tried again
+    pub placement_blacklist_time: f32,
+    // Rejected builds in a row before it is logged as a warning
+    pub build_failures_reported: u32,
+
+    // Expansion ranking, lower scores are taken first
+    pub expansion_distance_weight: f32,
+    pub expansion_enemy_distance_weight: f32,
+    pub expansion_threat_weight: f32,
+    pub expansion_resources_weight: f32,
+    // Expansions are hidden away once the enemy army is this many times stronger
+    pub hidden_expansion_strength_ratio: f32,
+
+    // Average spending per larva, turns income into larva demand
+    pub minerals_per_larva: f32,
+    // Banked minerals that count as larva starved when out of larva
+    pub macro_hatch_bank: u32,
+    pub max_macro_hatcheries: usize,
+
+    // Army units further than this from the main squad join it before attacking
+    pub reinforcement_distance: f32,
+    // Reinforcements route around enemies this close to their way
+    pub reinforcement_danger_range: f32,
+
+    pub component_max_failures: u32,
+    pub component_failure_cooldown: u32,
+    // Deferrable components are skipped once a step took this long, 0 disables it
+    pub step_budget_ms: f32,
+    // Deferred steps in a row before a deferrable component runs anyway
+    pub max_deferred_steps: u32,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            debug_draw: false,
+            debug_text: true,
+
+            max_workers: 80,
+            max_queens: 10,
+
+            double_gas_per_base_workers: 38,
+            unlock_roach_workers: 27,
+            unlock_hydra_workers: 50,
+            unlock_late_tech_workers: 60,
+            unlock_really_late_tech_workers: 70,
+            unlock_overseer_workers: 30,
+            unlock_burrow_workers: 25,
+            overlord_speed_workers: 30,
+            unlock_tunneling_claws_workers: 30,
+
+            unlock_upgrades_workers: 40,
+            save_for_attack_upgrades_on_units: 5,
+            save_for_defense_upgrades_on_units: 10,
+
+            creep_distance_to_hall: 4.5f32,
+            creep_spread_map_distance: 5,
+            creep_spread_distance: 5,
+            creep_spread_energy_min: 40,
+            creep_spread_energy: 180,
+            creep_spread_energy_per_tumor: 1,
+
+            transfusion_min_energy: 50,
+            transfusion_max_range: 15f32,
+            transfusion_missing_health: 50,
+
+            injection_max_larva_per_base: 3,
+            injection_max_larva: 30,
+
+            retreat_health_percentage: 0.4f32,
+            retreat_base_strength: 100f32,
+            burrow_health_percentage: 0.58f32,
+            burrow_detection_range: 9f32,
+            unburrow_health_percentage: 0.95f32,
+
+            priority_drone_economy: 250,
+            priority_army_requirement: 300,
+            priority_queen: 270,
+            priority_tunneling_claws: 290,
+            priority_ground_armor: 280,
+            priority_missile_weapon: 270,
+            priority_melee_weapon: 260,
+            priority_flyer_armor: 240,
+            priority_flyer_weapon: 250,
+            priority_evolution_chamber: 160,
+            priority_spore_crawler: 290,
+            priority_burrow: 300,
+            priority_lord_speed: 290,
+            priority_ling_speed: 310,
+            priority_min_lings: 320,
+            priority_strategy_tech: 300,
+
+            min_lings: 2,
+
+            overseer_swap_distance: 9f32,
+            overseer_changeling_delay: 5f32,
+
+            queen_inject_switch_base_range: 3f32,
+            ling_advanced_scout_delay: 60f32,
+
+            reservation_starved_time: 30f32,
+            larva_save_time: 10f32,
+            builder_premove_margin: 2f32,
+            placement_blacklist_time: 60f32,
+            build_failures_reported: 3,
+
+            expansion_distance_weight: 1f32,
+            expansion_enemy_distance_weight: 0.5f32,
+            expansion_threat_weight: 0.2f32,
+            expansion_resources_weight: 0.004f32,
+            hidden_expansion_strength_ratio: 1.5f32,
+
+            minerals_per_larva: 60f32,
+            macro_hatch_bank: 400,
+            max_macro_hatcheries: 2,
+
+            reinforcement_distance: 15f32,
+            reinforcement_danger_range: 10f32,
+
+            component_max_failures: 5,
+            component_failure_cooldown: 224,
+            step_budget_ms: 40f32,
+            max_deferred_steps: 5,
+        }
+    }
+}
+
+impl Params {
+    // Values that would break the bot in the middle of a game
+    pub fn validate(&self) -> Result<(), ParamsError> {
+        let non_zero = [
+            ("creep_spread_map_distance", self.creep_spread_map_distance),
+            ("creep_spread_distance", self.creep_spread_distance),
+            ("max_workers", self.max_workers),
+            (
+                "component_max_failures",
+                self.component_max_failures as usize,
+            ),
+        ];
+        for (key, value) in non_zero {
+            if value == 0 {
+                return Err(ParamsError::OutOfRange {
+                    key,
+                    expected: "greater than 0",
+                });
+            }
+        }
+        let positive = [
+            ("minerals_per_larva", self.minerals_per_larva),
+            (
+                "hidden_expansion_strength_ratio",
+                self.hidden_expansion_strength_ratio,
+            ),
+            ("reservation_starved_time", self.reservation_starved_time),
+            ("transfusion_max_range", self.transfusion_max_range),
+            ("reinforcement_distance", self.reinforcement_distance),
+        ];
+        for (key, value) in positive {
+            if value.is_nan() || value <= 0f32 {
+                return Err(ParamsError::OutOfRange {
+                    key,
+                    expected: "greater than 0",
+                });
+            }
+        }
+        let non_negative = [
+            ("larva_save_time", self.larva_save_time),
+            ("builder_premove_margin", self.builder_premove_margin),
+            ("placement_blacklist_time", self.placement_blacklist_time),
+            ("step_budget_ms", self.step_budget_ms),
+            (
+                "reinforcement_danger_range",
+                self.reinforcement_danger_range,
+            ),
+        ];
+        for (key, value) in non_negative {
+            if value.is_nan() || value < 0f32 {
+                return Err(ParamsError::OutOfRange {
+                    key,
+                    expected: "0 or greater",
+                });
+            }
+        }
+        let ratios = [
+            ("retreat_health_percentage", self.retreat_health_percentage),
+            ("burrow_health_percentage", self.burrow_health_percentage),
+            (
+                "unburrow_health_percentage",
+                self.unburrow_health_percentage,
+            ),
+        ];
+        for (key, value) in ratios {
+            if !(0f32..=1f32).contains(&value) {
+                return Err(ParamsError::OutOfRange {
+                    key,
+                    expected: "between 0 and 1",
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum ParamsError {
+    Io(std::io::Error),
+    Parse(String),
+    InvalidOverride(String),
+    OutOfRange {
+        key: &'static str,
+        expected: &'static str,
+    },
+}
+
+impl fmt::Display for ParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParamsError::Io(e) => write!(f, "could not read params file: {}", e),
+            ParamsError::Parse(e) => write!(f, "invalid params: {}", e),
+            ParamsError::InvalidOverride(o) => {
+                write!(f, "param override `{}` is not in the key=value format", o)
+            }
+            ParamsError::OutOfRange { key, expected } => {
+                write!(f, "param `{}` must be {}", key, expected)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParamsError {}
+
+// Params file content and command line overrides. Values are applied in order:
+// defaults, file, file race table, overrides, race overrides (`zerg.key=value`).
+#[derive(Debug, Clone, Default)]
+pub struct ParamsConfig {
+    file: Table,
+    overrides: Table,
+}
+
+impl ParamsConfig {
+    const RACES: [&'static str; 4] = ["terran", "zerg", "protoss", "random"];
+
+    pub fn load(path: Option<&str>, overrides: &[String]) -> Result<Self, ParamsError> {
+        let mut config = Self::default();
+        if let Some(path) = path {
+            let content = fs::read_to_string(path).map_err(ParamsError::Io)?;
+            config.file =
+                toml::from_str(&content).map_err(|e| ParamsError::Parse(e.to_string()))?;
+        }
+        for item in overrides {
+            let (key, value) = item
+                .split_once('=')
+                .ok_or_else(|| ParamsError::InvalidOverride(item.clone()))?;
+            // Anything that is not a valid TOML value is taken as a string
+            let value = toml::from_str::<Table>(&format!("value = {}", value))
+                .ok()
+                .and_then(|mut t| t.remove("value"))
+                .unwrap_or_else(|| Value::String(value.to_string()));
+            let key = key.trim();
+            match key.split_once('.') {
+                Some((race, race_key)) if Self::RACES.contains(&race) => {
+                    if let Value::Table(table) = config
+                        .overrides
+                        .entry(race)
+                        .or_insert_with(|| Value::Table(Table::new()))
+                    {
+                        table.insert(race_key.to_string(), value);
+                    }
+                }
+                _ => {
+                    config.overrides.insert(key.to_string(), value);
+                }
+            }
+        }
+        // Report broken values right away instead of in the middle of a game
+        for race in [Race::Terran, Race::Zerg, Race::Protoss, Race::Random] {
+            config.params(race)?;
+        }
+        Ok(config)
+    }
+
+    pub fn params(&self, enemy_race: Race) -> Result<Params, ParamsError> {
+        let race = format!("{:?}", enemy_race).to_lowercase();
+        let mut table = Table::new();
+        for source in [&self.file, &self.overrides] {
+            for (key, value) in source.iter() {
+                if !Self::RACES.contains(&key.as_str()) {
+                    table.insert(key.clone(), value.clone());
+                }
+            }
+            if let Some(Value::Table(race_table)) = source.get(&race) {
+                for (key, value) in race_table.iter() {
+                    table.insert(key.clone(), value.clone());
+                }
+            }
+        }
+        let params: Params = Value::Table(table)
+            .try_into()
+            .map_err(|e| ParamsError::Parse(e.to_string()))?;
+        params.validate()?;
+        Ok(params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_sc2::prelude::*;
+
+    use crate::params::{Params, ParamsConfig, ParamsError};
+
+    #[test]
+    fn defaults_without_config() {
+        let config = ParamsConfig::load(None, &[]).unwrap();
+        assert_eq!(config.params(Race::Zerg).unwrap(), Params::default());
+    }
+
+    #[test]
+    fn overrides_applied_per_race() {
+        let config = ParamsConfig::load(
+            None,
+            &[
+                "max_queens=6".to_string(),
+                "protoss.max_queens=8".to_string(),
+                "retreat_health_percentage=0.5".to_string(),
+            ],
+        )
+        .unwrap();
+        let terran = config.params(Race::Terran).unwrap();
+        assert_eq!(terran.max_queens, 6);
+        assert_eq!(terran.retreat_health_percentage, 0.5f32);
+        assert_eq!(config.params(Race::Protoss).unwrap().max_queens, 8);
+    }
+
+    #[test]
+    fn unknown_param_rejected() {
+        assert!(ParamsConfig::load(None, &["max_queen=6".to_string()]).is_err());
+    }
+
+    #[test]
+    fn out_of_range_param_reported() {
+        for (item, failing) in [
+            ("creep_spread_map_distance=0", "creep_spread_map_distance"),
+            ("component_max_failures=0", "component_max_failures"),
+            ("zerg.minerals_per_larva=-1.0", "minerals_per_larva"),
+            ("retreat_health_percentage=1.5", "retreat_health_percentage"),
+        ] {
+            match ParamsConfig::load(None, &[item.to_string()]) {
+                Err(ParamsError::OutOfRange { key, .. }) => assert_eq!(key, failing),
+                other => panic!("{} accepted: {:?}", item, other),
+            }
+        }
+    }
+}
@@ -0,0 +1,334 @@
+use std::fmt;
+use std::fs;
+use std::time::Instant;
+
+use rust_sc2::bot::Bot;
+use rust_sc2::Event;
+use serde::Deserialize;
+
+use crate::managers::army_manager::ArmyManager;
+use crate::managers::cache_manager::CacheManager;
+use crate::managers::defense_manager::DefenseManager;
+use crate::managers::production_manager::ProductionManager;
+use crate::managers::rally_manager::RallyManager;
+use crate::managers::resource_manager::ResourceManager;
+use crate::managers::squad_manager::SquadManager;
+use crate::managers::strategy_manager::StrategyManager;
+use crate::managers::summary_manager::SummaryManager;
+use crate::managers::worker_manager::WorkerManager;
+use crate::units::overlord_manager::OverlordManager;
+use crate::units::queen_manager::QueenManager;
+use crate::units::ravager_manager::RavagerManager;
+use crate::{AIComponent, BotState, ProcessLimiter};
+
+#[derive(Debug)]
+pub enum PipelineError {
+    Io(std::io::Error),
+    Parse(String),
+    UnknownComponent(String),
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineError::Io(e) => write!(f, "could not read pipeline file: {}", e),
+            PipelineError::Parse(e) => write!(f, "could not parse pipeline file: {}", e),
+            PipelineError::UnknownComponent(name) => write!(f, "unknown component `{}`", name),
+        }
+    }
+}
+
+impl std::error::Error for PipelineError {}
+
+type ComponentFactory = Box<dyn Fn() -> Box<dyn AIComponent>>;
+
+// Named component factories, downstream bots can register their own components.
+pub struct ComponentRegistry {
+    factories: Vec<(String, ComponentFactory)>,
+}
+
+impl ComponentRegistry {
+    pub fn empty() -> Self {
+        Self { factories: vec![] }
+    }
+
+    pub fn register<F>(mut self, name: &str, factory: F) -> Self
+    where
+        F: Fn() -> Box<dyn AIComponent> + 'static,
+    {
+        self.factories.retain(|(n, _)| n != name);
+        self.factories.push((name.to_string(), Box::new(factory)));
+        self
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.factories.iter().map(|(n, _)| n.as_str()).collect()
+    }
+
+    pub fn create(&self, name: &str) -> Option<Box<dyn AIComponent>> {
+        self.factories
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, factory)| factory())
+    }
+}
+
+impl Default for ComponentRegistry {
+    fn default() -> Self {
+        Self::empty()
+            .register("CacheManager", || Box::new(CacheManager::default()))
+            .register("SquadManager", || Box::new(SquadManager::default()))
+            .register("StrategyManager", || Box::new(StrategyManager::default()))
+            .register("ArmyManager", || Box::new(ArmyManager::default()))
+            .register("DefenseManager", || Box::new(DefenseManager::default()))
+            .register("ResourceManager", || Box::new(ResourceManager::default()))
+            .register("RallyManager", || Box::new(RallyManager::default()))
+            .register("WorkerManager", || Box::new(WorkerManager::default()))
+            .register("OverlordManager", || Box::new(OverlordManager::default()))
+            .register("QueenManager", || Box::new(QueenManager::default()))
+            .register("RavagerManager", || Box::new(RavagerManager::default()))
+            .register("ProductionManager", || {
+                Box::new(ProductionManager::default())
+            })
+            .register("SummaryManager", || Box::new(SummaryManager::default()))
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ComponentConfig {
+    pub name: String,
+    #[serde(default)]
+    pub delay: u32,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub deferrable: bool,
+}
+
+// Which components run, in which order and how often (in game loops).
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PipelineConfig {
+    pub components: Vec<ComponentConfig>,
+}
+
+impl PipelineConfig {
+    pub fn new() -> Self {
+        Self { components: vec![] }
+    }
+
+    pub fn component(mut self, name: &str, delay: u32) -> Self {
+        self.components.push(ComponentConfig {
+            name: name.to_string(),
+            delay,
+            enabled: true,
+            deferrable: false,
+        });
+        self
+    }
+
+    pub fn deferrable_component(mut self, name: &str, delay: u32) -> Self {
+        self = self.component(name, delay);
+        if let Some(component) = self.components.last_mut() {
+            component.deferrable = true;
+        }
+        self
+    }
+
+    pub fn load(path: &str) -> Result<Self, PipelineError> {
+        let content = fs::read_to_string(path).map_err(PipelineError::Io)?;
+        toml::from_str(&content).map_err(|e| PipelineError::Parse(e.to_string()))
+    }
+
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> Result<(), PipelineError> {
+        let component = self
+            .components
+            .iter_mut()
+            .find(|c| c.name == name)
+            .ok_or_else(|| PipelineError::UnknownComponent(name.to_string()))?;
+        component.enabled = enabled;
+        Ok(())
+    }
+
+    pub fn build(&self, registry: &ComponentRegistry) -> Result<Pipeline, PipelineError> {
+        let mut components = vec![];
+        for config in self.components.iter() {
+            let component = registry
+                .create(&config.name)
+                .ok_or_else(|| PipelineError::UnknownComponent(config.name.clone()))?;
+            if config.enabled {
+                components.push(
+                    ProcessLimiter::new(&config.name, config.delay, component)
+                        .deferrable(config.deferrable),
+                );
+            }
+        }
+        Ok(Pipeline { components })
+    }
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self::new()
+            .component("CacheManager", 0)
+            .component("SquadManager", 0)
+            .component("StrategyManager", 5)
+            .component("ArmyManager", 5)
+            .component("DefenseManager", 15)
+            .component("ResourceManager", 15)
+            .component("RallyManager", 15)
+            .component("WorkerManager", 5)
+            .deferrable_component("OverlordManager", 15)
+            .deferrable_component("QueenManager", 15)
+            .deferrable_component("RavagerManager", 15)
+            .component("ProductionManager", 10)
+            .component("SummaryManager", 0)
+    }
+}
+
+// Runs the components in order, deferring the deferrable ones to later loops
+// once the step went over the time budget.
+pub struct Pipeline {
+    components: Vec<ProcessLimiter>,
+}
+
+impl Pipeline {
+    pub fn components(&self) -> &[ProcessLimiter] {
+        &self.components
+    }
+
+    pub fn report(&self) -> String {
+        self.components
+            .iter()
+            .map(|c| format!("{:<20} {}", c.name(), c.stats()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl AIComponent for Pipeline {
+    fn process(&mut self, bot: &mut Bot, bot_state: &mut BotState) {
+        let start = Instant::now();
+        let game_loop = bot.state.observation.game_loop();
+        bot_state.messages.start_step(game_loop);
+        for component in self.components.iter_mut() {
+            let budget = bot_state.params.step_budget_ms;
+            if budget > 0f32
+                && component.is_deferrable()
+                && !component.is_starved(&bot_state.params)
+                && start.elapsed().as_secs_f32() * 1_000f32 > budget
+                && component.is_due(game_loop, &bot_state.params)
+            {
+                component.defer();
+                continue;
+            }
+            component.process(bot, bot_state);
+        }
+    }
+
+    fn on_event(&mut self, event: &Event, bot_state: &mut BotState) {
+        for component in self.components.iter_mut() {
+            component.on_event(event, bot_state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::thread;
+    use std::time::Duration;
+
+    use rust_sc2::bot::Bot;
+
+    use crate::pipeline::{ComponentRegistry, Pipeline, PipelineConfig, PipelineError};
+    use crate::{AIComponent, BotState, ProcessLimiter};
+
+    struct SlowComponent {}
+
+    impl AIComponent for SlowComponent {
+        fn process(&mut self, _: &mut Bot, _: &mut BotState) {
+            thread::sleep(Duration::from_millis(2));
+        }
+    }
+
+    struct CountingComponent {
+        runs: Rc<Cell<u32>>,
+    }
+
+    impl AIComponent for CountingComponent {
+        fn process(&mut self, _: &mut Bot, _: &mut BotState) {
+            self.runs.set(self.runs.get() + 1);
+        }
+    }
+
+    #[test]
+    fn default_pipeline_builds() {
+        let registry = ComponentRegistry::default();
+        let pipeline = PipelineConfig::default().build(&registry).unwrap();
+        assert_eq!(pipeline.components().len(), registry.names().len());
+    }
+
+    #[test]
+    fn disabled_component_skipped() {
+        let mut config = PipelineConfig::default();
+        config.set_enabled("RavagerManager", false).unwrap();
+        let pipeline = config.build(&ComponentRegistry::default()).unwrap();
+        assert!(pipeline
+            .components()
+            .iter()
+            .all(|c| c.name() != "RavagerManager"));
+    }
+
+    #[test]
+    fn deferred_component_not_starved() {
+        let runs = Rc::new(Cell::new(0));
+        let mut pipeline = Pipeline {
+            components: vec![
+                ProcessLimiter::new("Slow", 0, Box::new(SlowComponent {})),
+                ProcessLimiter::new(
+                    "Counting",
+                    0,
+                    Box::new(CountingComponent { runs: runs.clone() }),
+                )
+                .deferrable(true),
+            ],
+        };
+        let mut bot = Bot::default();
+        let mut bot_state = BotState::default();
+        bot_state.params.step_budget_ms = 1f32;
+        bot_state.params.max_deferred_steps = 2;
+        for _ in 0..2 {
+            pipeline.process(&mut bot, &mut bot_state);
+        }
+        assert_eq!(runs.get(), 0);
+        pipeline.process(&mut bot, &mut bot_state);
+        assert_eq!(runs.get(), 1);
+        pipeline.process(&mut bot, &mut bot_state);
+        assert_eq!(runs.get(), 1);
+    }
+
+    #[test]
+    fn unknown_component_rejected() {
+        let config: PipelineConfig = toml::from_str(
+            r#"
+            [[components]]
+            name = "CacheManager"
+            [[components]]
+            name = "MacroManager"
+            delay = 10
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(
+            config.build(&ComponentRegistry::default()),
+            Err(PipelineError::UnknownComponent(name)) if name == "MacroManager"
+        ));
+    }
+}
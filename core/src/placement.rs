@@ -0,0 +1,180 @@
+use itertools::Itertools;
+use rust_sc2::prelude::*;
+
+use crate::game_view::{center, GameView};
+use crate::utils::{GridSnap, SegmentDistance};
+
+// Where a structure goes when it isn't part of a wall.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlacementPolicy {
+    // Behind the main mineral line, out of the way of runbys
+    BehindMinerals,
+    // Side of the main facing away from the ramp and the mineral line
+    Pocket,
+    // Between the main and the map center
+    Front,
+}
+
+impl PlacementPolicy {
+    pub fn for_structure(unit_type: UnitTypeId) -> Self {
+        match unit_type {
+            // Late tech is expensive to lose to drops
+            UnitTypeId::Spire
+            | UnitTypeId::HydraliskDen
+            | UnitTypeId::InfestationPit
+            | UnitTypeId::UltraliskCavern => PlacementPolicy::BehindMinerals,
+            UnitTypeId::SpawningPool
+            | UnitTypeId::RoachWarren
+            | UnitTypeId::BanelingNest
+            | UnitTypeId::EvolutionChamber => PlacementPolicy::Pocket,
+            _ => PlacementPolicy::Front,
+        }
+    }
+}
+
+const MAIN_RESOURCES_RANGE: f32 = 10f32;
+const BEHIND_MINERALS: f32 = 3f32;
+const POCKET_DISTANCE: f32 = 8f32;
+const SEARCH_RADIUS: i32 = 6;
+// Space kept around drone paths and the walk to the ramp
+const MINING_CLEARANCE: f32 = 1.5f32;
+const RAMP_CLEARANCE: f32 = 6f32;
+const RAMP_PATH_CLEARANCE: f32 = 2f32;
+
+fn footprint(unit_type: UnitTypeId) -> usize {
+    match unit_type {
+        UnitTypeId::Spire
+        | UnitTypeId::GreaterSpire
+        | UnitTypeId::SpineCrawler
+        | UnitTypeId::SporeCrawler => 2,
+        UnitTypeId::Hatchery => 5,
+        _ => 3,
+    }
+}
+
+fn direction(from: Point2, to: Point2) -> Point2 {
+    let length = from.distance(to);
+    if length == 0f32 {
+        Point2::new(0f32, 0f32)
+    } else {
+        Point2::new((to.x - from.x) / length, (to.y - from.y) / length)
+    }
+}
+
+// Spots for the structure following its policy, closest to the ideal spot
+// first. Empty for front placements or when the main has no mineral line.
+pub fn placement_candidates<G: GameView>(game: &G, unit_type: UnitTypeId) -> Vec<Point2> {
+    policy_candidates(game, PlacementPolicy::for_structure(unit_type), unit_type)
+}
+
+pub fn policy_candidates<G: GameView>(
+    game: &G,
+    policy: PlacementPolicy,
+    unit_type: UnitTypeId,
+) -> Vec<Point2> {
+    let main = game.start_location();
+    let minerals = game
+        .mineral_fields()
+        .iter()
+        .filter(|f| f.distance(main) < MAIN_RESOURCES_RANGE)
+        .cloned()
+        .collect::<Vec<_>>();
+    let mineral_line = match (policy, center(&minerals)) {
+        (PlacementPolicy::Front, _) | (_, None) => return vec![],
+        (_, Some(mineral_line)) => mineral_line,
+    };
+    let resources = minerals
+        .iter()
+        .chain(game.vespene_geysers().iter())
+        .filter(|r| r.distance(main) < MAIN_RESOURCES_RANGE)
+        .map(|r| r.position)
+        .collect::<Vec<_>>();
+    let ramp = game.my_ramp_center().unwrap_or_else(|| game.map_center());
+    let to_minerals = direction(main, mineral_line);
+    let anchor = match policy {
+        PlacementPolicy::BehindMinerals => Point2::new(
+            mineral_line.x + to_minerals.x * BEHIND_MINERALS,
+            mineral_line.y + to_minerals.y * BEHIND_MINERALS,
+        ),
+        _ => {
+            let from_ramp = direction(ramp, main);
+            let mut away = direction(
+                Point2::new(0f32, 0f32),
+                Point2::new(from_ramp.x - to_minerals.x, from_ramp.y - to_minerals.y),
+            );
+            // Ramp right behind the mineral line, any side works
+            if away.x == 0f32 && away.y == 0f32 {
+                away = Point2::new(-to_minerals.y, to_minerals.x);
+            }
+            Point2::new(
+                main.x + away.x * POCKET_DISTANCE,
+                main.y + away.y * POCKET_DISTANCE,
+            )
+        }
+    };
+    let size = footprint(unit_type);
+    let half_size = size as f32 / 2f32;
+    (-SEARCH_RADIUS..=SEARCH_RADIUS)
+        .cartesian_product(-SEARCH_RADIUS..=SEARCH_RADIUS)
+        .map(|(x, y)| Point2::new(anchor.x + x as f32, anchor.y + y as f32).snap(size))
+        .filter(|p| {
+            game.is_pathable(*p)
+                && p.distance(ramp) > RAMP_CLEARANCE + half_size
+                && p.segment_distance(main, ramp) > RAMP_PATH_CLEARANCE + half_size
+                && resources
+                    .iter()
+                    .all(|r| p.segment_distance(main, *r) > MINING_CLEARANCE + half_size)
+        })
+        .sorted_by(|a, b| a.distance(anchor).total_cmp(&b.distance(anchor)))
+        .dedup()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_sc2::prelude::*;
+
+    use crate::fake_game::FakeGame;
+    use crate::game_view::UnitSnapshot;
+    use crate::placement::{placement_candidates, PlacementPolicy};
+    use crate::utils::SegmentDistance;
+
+    fn main_game() -> FakeGame {
+        let mut game = FakeGame {
+            ramp: Some(Point2::new(38f32, 38f32)),
+            ..Default::default()
+        };
+        for (tag, y) in (26..=34).step_by(2).enumerate() {
+            game.mineral_fields.push(UnitSnapshot::new(
+                tag as u64,
+                UnitTypeId::MineralField,
+                Point2::new(23f32, y as f32),
+            ));
+        }
+        game
+    }
+
+    #[test]
+    fn late_tech_behind_minerals() {
+        let game = main_game();
+        assert_eq!(
+            PlacementPolicy::for_structure(UnitTypeId::Spire),
+            PlacementPolicy::BehindMinerals
+        );
+        let candidates = placement_candidates(&game, UnitTypeId::Spire);
+        assert_eq!(candidates[0], Point2::new(20f32, 30f32));
+        assert!(candidates.iter().all(|c| game
+            .mineral_fields
+            .iter()
+            .all(|f| c.segment_distance(game.start_location, f.position) > 2f32)));
+    }
+
+    #[test]
+    fn early_tech_in_pocket() {
+        let game = main_game();
+        let candidates = placement_candidates(&game, UnitTypeId::SpawningPool);
+        assert_eq!(candidates[0], Point2::new(33.5f32, 22.5f32));
+        assert!(placement_candidates(&game, UnitTypeId::SpineCrawler).is_empty());
+        assert!(placement_candidates(&FakeGame::default(), UnitTypeId::Spire).is_empty());
+    }
+}
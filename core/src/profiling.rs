@@ -0,0 +1,77 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::Duration;
+
+// Wall time statistics of a component, with a rolling window for recent runs.
+#[derive(Debug, Default, Clone)]
+pub struct StepStats {
+    pub runs: u32,
+    pub deferred: u32,
+    pub total: Duration,
+    pub max: Duration,
+    recent: VecDeque<Duration>,
+}
+
+impl StepStats {
+    const WINDOW: usize = 64;
+
+    pub fn record(&mut self, elapsed: Duration) {
+        self.runs += 1;
+        self.total += elapsed;
+        self.max = self.max.max(elapsed);
+        if self.recent.len() >= Self::WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(elapsed);
+    }
+
+    pub fn average(&self) -> Duration {
+        if self.runs == 0 {
+            Duration::ZERO
+        } else {
+            self.total / self.runs
+        }
+    }
+
+    pub fn recent_average(&self) -> Duration {
+        if self.recent.is_empty() {
+            Duration::ZERO
+        } else {
+            self.recent.iter().sum::<Duration>() / self.recent.len() as u32
+        }
+    }
+}
+
+impl fmt::Display for StepStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "runs {} | avg {:.3}ms | recent {:.3}ms | max {:.3}ms | total {:.1}ms | deferred {}",
+            self.runs,
+            self.average().as_secs_f64() * 1_000f64,
+            self.recent_average().as_secs_f64() * 1_000f64,
+            self.max.as_secs_f64() * 1_000f64,
+            self.total.as_secs_f64() * 1_000f64,
+            self.deferred
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::profiling::StepStats;
+
+    #[test]
+    fn rolling_window() {
+        let mut stats = StepStats::default();
+        stats.record(Duration::from_millis(100));
+        for _ in 0..StepStats::WINDOW {
+            stats.record(Duration::from_millis(1));
+        }
+        assert_eq!(stats.max, Duration::from_millis(100));
+        assert_eq!(stats.recent_average(), Duration::from_millis(1));
+        assert!(stats.average() > Duration::from_millis(1));
+    }
+}
@@ -0,0 +1,249 @@
+use std::fmt;
+
+use rust_sc2::bot::Bot;
+use rust_sc2::prelude::*;
+
+use crate::game_view::ResourceCost;
+
+// Minerals, gas, larva and supply, either available or held back.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Funds {
+    pub minerals: u32,
+    pub vespene: u32,
+    pub larva: u32,
+    pub supply: u32,
+}
+
+impl Funds {
+    pub fn covers(&self, cost: &ResourceCost, larva: u32, check_supply: bool) -> bool {
+        self.minerals >= cost.minerals
+            && self.vespene >= cost.vespene
+            && self.larva >= larva
+            && (!check_supply || self.supply as f32 >= cost.supply)
+    }
+
+    fn saturating_sub(&self, other: &Funds) -> Self {
+        Self {
+            minerals: self.minerals.saturating_sub(other.minerals),
+            vespene: self.vespene.saturating_sub(other.vespene),
+            larva: self.larva.saturating_sub(other.larva),
+            supply: self.supply.saturating_sub(other.supply),
+        }
+    }
+}
+
+// What a queue entry holds back while it waits to be affordable.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reservation {
+    pub unit_type: Option<UnitTypeId>,
+    pub upgrade: Option<UpgradeId>,
+    pub priority: usize,
+    pub funds: Funds,
+    // Game time the entry is expected to start
+    pub expected_start: f32,
+    // Game time of the first step the entry was reserved on
+    pub since: f32,
+}
+
+impl Reservation {
+    pub fn for_unit(
+        unit_type: UnitTypeId,
+        priority: usize,
+        cost: ResourceCost,
+        larva: u32,
+        use_supply: bool,
+    ) -> Self {
+        Self {
+            unit_type: Some(unit_type),
+            upgrade: None,
+            priority,
+            funds: Funds {
+                minerals: cost.minerals,
+                vespene: cost.vespene,
+                larva,
+                supply: if use_supply {
+                    cost.supply.ceil() as u32
+                } else {
+                    0
+                },
+            },
+            expected_start: 0f32,
+            since: 0f32,
+        }
+    }
+
+    pub fn for_upgrade(upgrade: UpgradeId, priority: usize, cost: ResourceCost) -> Self {
+        Self {
+            unit_type: None,
+            upgrade: Some(upgrade),
+            priority,
+            funds: Funds {
+                minerals: cost.minerals,
+                vespene: cost.vespene,
+                ..Default::default()
+            },
+            expected_start: 0f32,
+            since: 0f32,
+        }
+    }
+
+    fn same_entry(&self, other: &Reservation) -> bool {
+        self.unit_type == other.unit_type
+            && self.upgrade == other.upgrade
+            && self.priority == other.priority
+    }
+}
+
+impl fmt::Display for Reservation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let target = match (self.unit_type, self.upgrade) {
+            (Some(unit_type), _) => format!("{:?}", unit_type),
+            (_, Some(upgrade)) => format!("{:?}", upgrade),
+            _ => "?".to_string(),
+        };
+        write!(
+            f,
+            "{:>6} {:<24} {:>4}m {:>4}g {}l {:>2}s since {:.0}s",
+            self.priority,
+            target,
+            self.funds.minerals,
+            self.funds.vespene,
+            self.funds.larva,
+            self.funds.supply,
+            self.since
+        )
+    }
+}
+
+// Ledger of resources held for queue entries, rebuilt on every production step
+// in priority order. Lower priority entries can only spend what is left free.
+#[derive(Debug, Default)]
+pub struct Reservations {
+    entries: Vec<Reservation>,
+    previous: Vec<Reservation>,
+    time: f32,
+}
+
+impl Reservations {
+    pub fn start_step(&mut self, time: f32) {
+        self.time = time;
+        self.previous = std::mem::take(&mut self.entries);
+    }
+
+    pub fn reserve(&mut self, mut reservation: Reservation) {
+        reservation.since = self
+            .previous
+            .iter()
+            .chain(self.entries.iter())
+            .find(|r| r.same_entry(&reservation))
+            .map_or(self.time, |r| r.since);
+        if reservation.expected_start < self.time {
+            reservation.expected_start = self.time;
+        }
+        self.entries.push(reservation);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Reservation> {
+        self.entries.iter()
+    }
+
+    pub fn reserved(&self) -> Funds {
+        self.entries
+            .iter()
+            .fold(Funds::default(), |total, r| Funds {
+                minerals: total.minerals + r.funds.minerals,
+                vespene: total.vespene + r.funds.vespene,
+                larva: total.larva + r.funds.larva,
+                supply: total.supply + r.funds.supply,
+            })
+    }
+
+    pub fn free(&self, available: Funds) -> Funds {
+        available.saturating_sub(&self.reserved())
+    }
+
+    // Entries waiting on resources for longer than `limit` seconds
+    pub fn starved(&self, limit: f32) -> impl Iterator<Item = &Reservation> {
+        let time = self.time;
+        self.entries.iter().filter(move |r| time - r.since > limit)
+    }
+
+    pub fn draw(&self, bot: &mut Bot, starved_limit: f32) {
+        for (index, reservation) in self.entries.iter().enumerate() {
+            let color = if self.time - reservation.since > starved_limit {
+                (255, 64, 64)
+            } else {
+                (255, 255, 255)
+            };
+            bot.debug.draw_text_screen(
+                &reservation.to_string(),
+                Some((0.01, 0.2 + 0.02 * index as f32)),
+                Some(color),
+                None,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_sc2::prelude::*;
+
+    use crate::game_view::ResourceCost;
+    use crate::reservation::{Funds, Reservation, Reservations};
+
+    fn roach() -> Reservation {
+        Reservation::for_unit(
+            UnitTypeId::Roach,
+            100,
+            ResourceCost::new(75, 25, 2f32),
+            1,
+            true,
+        )
+    }
+
+    #[test]
+    fn free_funds_exclude_reservations() {
+        let mut reservations = Reservations::default();
+        reservations.start_step(10f32);
+        reservations.reserve(roach());
+        reservations.reserve(Reservation::for_upgrade(
+            UpgradeId::Zerglingmovementspeed,
+            50,
+            ResourceCost::new(100, 100, 0f32),
+        ));
+        let free = reservations.free(Funds {
+            minerals: 200,
+            vespene: 100,
+            larva: 2,
+            supply: 4,
+        });
+        assert_eq!(
+            free,
+            Funds {
+                minerals: 25,
+                vespene: 0,
+                larva: 1,
+                supply: 2,
+            }
+        );
+        assert!(!free.covers(&ResourceCost::new(50, 0, 1f32), 1, true));
+        assert!(free.covers(&ResourceCost::new(25, 0, 1f32), 1, true));
+    }
+
+    #[test]
+    fn waiting_entries_become_starved() {
+        let mut reservations = Reservations::default();
+        reservations.start_step(10f32);
+        reservations.reserve(roach());
+        reservations.start_step(50f32);
+        reservations.reserve(roach());
+        assert_eq!(reservations.iter().count(), 1);
+        assert_eq!(reservations.starved(30f32).next().unwrap().since, 10f32);
+        reservations.start_step(60f32);
+        assert_eq!(reservations.iter().count(), 0);
+        reservations.start_step(70f32);
+        reservations.reserve(roach());
+        assert!(reservations.starved(30f32).next().is_none());
+    }
+}
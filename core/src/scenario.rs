@@ -0,0 +1,358 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use rust_sc2::prelude::*;
+use serde::Deserialize;
+
+use crate::bus::Message;
+use crate::command_queue::Command;
+use crate::fake_game::FakeGame;
+use crate::game_view::{ExpansionSnapshot, GameView, UnitSnapshot};
+use crate::managers::production_manager::ProductionManager;
+use crate::managers::rally_manager::RallyManager;
+use crate::managers::resource_manager::ResourceManager;
+use crate::managers::worker_manager::WorkerManager;
+use crate::{BotState, SpendingFocus};
+
+// Components that only talk to the game through the GameView, so scenarios can
+// step them on a FakeGame.
+pub trait ScenarioComponent {
+    fn simulate(&mut self, game: &mut FakeGame, bot_state: &mut BotState);
+}
+
+pub fn scenario_component(name: &str) -> Option<Box<dyn ScenarioComponent>> {
+    match name {
+        "WorkerManager" => Some(Box::new(WorkerManager::default())),
+        "ResourceManager" => Some(Box::new(ResourceManager::default())),
+        "ProductionManager" => Some(Box::new(ProductionManager::default())),
+        "RallyManager" => Some(Box::new(RallyManager::default())),
+        _ => None,
+    }
+}
+
+#[derive(Debug)]
+pub enum ScenarioError {
+    Io(std::io::Error),
+    Parse(String),
+    UnknownComponent(String),
+    InvalidCommand(String),
+    Failed { name: String, failures: Vec<String> },
+}
+
+impl fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScenarioError::Io(e) => write!(f, "could not read scenario file: {}", e),
+            ScenarioError::Parse(e) => write!(f, "could not parse scenario file: {}", e),
+            ScenarioError::UnknownComponent(name) => {
+                write!(f, "component `{}` can't run in scenarios", name)
+            }
+            ScenarioError::InvalidCommand(e) => write!(f, "invalid queued command: {}", e),
+            ScenarioError::Failed { name, failures } => {
+                write!(
+                    f,
+                    "scenario `{}` failed:\n  {}",
+                    name,
+                    failures.join("\n  ")
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ScenarioError {}
+
+// Game state at the start of the scenario, missing values come from FakeGame.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameSetup {
+    pub time: f32,
+    pub minerals: u32,
+    pub vespene: u32,
+    pub supply_left: u32,
+    pub supply_cap: u32,
+    pub start_location: Point2,
+    pub enemy_start: Point2,
+    pub ramp: Option<Point2>,
+    pub map_center: Point2,
+    pub units: Vec<UnitSnapshot>,
+    pub enemies: Vec<UnitSnapshot>,
+    pub mineral_fields: Vec<UnitSnapshot>,
+    pub vespene_geysers: Vec<UnitSnapshot>,
+    pub expansions: Vec<ExpansionSnapshot>,
+    pub upgrades: Vec<UpgradeId>,
+}
+
+impl Default for GameSetup {
+    fn default() -> Self {
+        let game = FakeGame::default();
+        Self {
+            time: game.time,
+            minerals: game.minerals,
+            vespene: game.vespene,
+            supply_left: game.supply_left,
+            supply_cap: game.supply_cap,
+            start_location: game.start_location,
+            enemy_start: game.enemy_start,
+            ramp: game.ramp,
+            map_center: game.map_center,
+            units: vec![],
+            enemies: vec![],
+            mineral_fields: vec![],
+            vespene_geysers: vec![],
+            expansions: vec![],
+            upgrades: vec![],
+        }
+    }
+}
+
+impl GameSetup {
+    pub fn game(&self) -> FakeGame {
+        FakeGame {
+            time: self.time,
+            minerals: self.minerals,
+            vespene: self.vespene,
+            supply_left: self.supply_left,
+            supply_cap: self.supply_cap,
+            start_location: self.start_location,
+            enemy_start: self.enemy_start,
+            ramp: self.ramp,
+            map_center: self.map_center,
+            units: self.units.clone(),
+            enemies: self.enemies.clone(),
+            mineral_fields: self.mineral_fields.clone(),
+            vespene_geysers: self.vespene_geysers.clone(),
+            expansions: self.expansions.clone(),
+            upgrades: self.upgrades.iter().copied().collect(),
+            ..Default::default()
+        }
+    }
+}
+
+// A unit or upgrade command already in the build queue.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QueuedCommand {
+    pub unit_type: Option<UnitTypeId>,
+    pub upgrade: Option<UpgradeId>,
+    #[serde(default)]
+    pub amount: usize,
+    #[serde(default)]
+    pub save_resources: bool,
+    #[serde(default)]
+    pub blocked: bool,
+    pub priority: usize,
+}
+
+impl QueuedCommand {
+    pub fn command(&self) -> Result<Command, ScenarioError> {
+        match (self.unit_type, self.upgrade) {
+            (Some(unit_type), None) => Ok(Command::new_unit(
+                unit_type,
+                self.amount,
+                self.save_resources,
+            )),
+            (None, Some(upgrade)) => Ok(Command::new_upgrade(upgrade, self.save_resources)),
+            _ => Err(ScenarioError::InvalidCommand(
+                "needs either `unit_type` or `upgrade`".to_string(),
+            )),
+        }
+    }
+}
+
+// How many issued actions of a kind (and unit type) are expected, at least one
+// when no bounds are given.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExpectedAction {
+    pub action: String,
+    pub unit_type: Option<UnitTypeId>,
+    pub min: Option<usize>,
+    pub max: Option<usize>,
+}
+
+impl ExpectedAction {
+    fn check(&self, game: &FakeGame) -> Option<String> {
+        let count = game
+            .actions
+            .iter()
+            .filter(|a| {
+                a.name() == self.action && self.unit_type.map_or(true, |t| a.unit_type() == Some(t))
+            })
+            .count();
+        let min = self.min.unwrap_or(if self.max.is_none() { 1 } else { 0 });
+        if count < min || self.max.map_or(false, |max| count > max) {
+            Some(format!(
+                "expected {} to {:?} {} actions of {:?}, got {}",
+                min, self.max, self.action, self.unit_type, count
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+// A build queue entry that should (or shouldn't) be there after the run.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExpectedQueue {
+    pub unit_type: Option<UnitTypeId>,
+    pub upgrade: Option<UpgradeId>,
+    pub amount: Option<usize>,
+    pub priority: Option<usize>,
+    #[serde(default = "default_true")]
+    pub present: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl ExpectedQueue {
+    fn check(&self, bot_state: &BotState) -> Option<String> {
+        let found = bot_state.build_queue.into_iter().any(|element| {
+            self.priority.map_or(true, |p| p == element.priority)
+                && match element.command {
+                    Command::UnitCommand {
+                        unit_type,
+                        wanted_amount,
+                        save_resources: _,
+                    } => {
+                        self.upgrade.is_none()
+                            && self.unit_type.map_or(true, |t| t == unit_type)
+                            && self.amount.map_or(true, |a| a == wanted_amount)
+                    }
+                    Command::UpgradeCommand {
+                        upgrade,
+                        save_resources: _,
+                    } => self.unit_type.is_none() && self.upgrade.map_or(true, |u| u == upgrade),
+                    Command::MacroHatchCommand {
+                        wanted_amount,
+                        save_resources: _,
+                    } => {
+                        self.upgrade.is_none()
+                            && self.unit_type.map_or(true, |t| t == UnitTypeId::Hatchery)
+                            && self.amount.map_or(true, |a| a == wanted_amount)
+                    }
+                }
+        });
+        if found != self.present {
+            Some(format!(
+                "expected queue {}to have {:?}{:?} amount {:?} priority {:?}",
+                if self.present { "" } else { "not " },
+                self.unit_type,
+                self.upgrade,
+                self.amount,
+                self.priority
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Expectations {
+    pub actions: Vec<ExpectedAction>,
+    pub queue: Vec<ExpectedQueue>,
+    pub messages: Vec<Message>,
+    pub spending_focus: Option<SpendingFocus>,
+}
+
+// A game situation, the components to step on it and what they should do.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    pub name: String,
+    pub components: Vec<String>,
+    #[serde(default)]
+    pub spending_focus: SpendingFocus,
+    #[serde(default)]
+    pub game: GameSetup,
+    #[serde(default)]
+    pub queue: Vec<QueuedCommand>,
+    #[serde(default)]
+    pub expect: Expectations,
+}
+
+pub struct ScenarioRun {
+    pub game: FakeGame,
+    pub bot_state: BotState,
+}
+
+impl Scenario {
+    pub fn load(path: &Path) -> Result<Self, ScenarioError> {
+        let content = fs::read_to_string(path).map_err(ScenarioError::Io)?;
+        serde_json::from_str(&content).map_err(|e| ScenarioError::Parse(e.to_string()))
+    }
+
+    // Steps the components once, in order, on the scenario game.
+    pub fn run(&self) -> Result<ScenarioRun, ScenarioError> {
+        let mut components = vec![];
+        for name in self.components.iter() {
+            components.push(
+                scenario_component(name)
+                    .ok_or_else(|| ScenarioError::UnknownComponent(name.clone()))?,
+            );
+        }
+        let mut game = self.game.game();
+        let mut bot_state = BotState {
+            spending_focus: self.spending_focus.clone(),
+            ..Default::default()
+        };
+        bot_state.messages.start_step(game.game_loop());
+        for queued in self.queue.iter() {
+            bot_state
+                .build_queue
+                .push(queued.command()?, queued.blocked, queued.priority);
+        }
+        for component in components.iter_mut() {
+            component.simulate(&mut game, &mut bot_state);
+        }
+        Ok(ScenarioRun { game, bot_state })
+    }
+
+    pub fn failures(&self, run: &ScenarioRun) -> Vec<String> {
+        let mut failures = self
+            .expect
+            .actions
+            .iter()
+            .filter_map(|e| e.check(&run.game))
+            .chain(
+                self.expect
+                    .queue
+                    .iter()
+                    .filter_map(|e| e.check(&run.bot_state)),
+            )
+            .collect::<Vec<_>>();
+        for message in self.expect.messages.iter() {
+            if !run.bot_state.messages.messages().any(|m| m == message) {
+                failures.push(format!("expected {:?} to be published", message));
+            }
+        }
+        if let Some(focus) = &self.expect.spending_focus {
+            if *focus != run.bot_state.spending_focus {
+                failures.push(format!(
+                    "expected {:?} spending focus, got {:?}",
+                    focus, run.bot_state.spending_focus
+                ));
+            }
+        }
+        failures
+    }
+
+    pub fn verify(&self) -> Result<ScenarioRun, ScenarioError> {
+        let run = self.run()?;
+        let failures = self.failures(&run);
+        if failures.is_empty() {
+            Ok(run)
+        } else {
+            Err(ScenarioError::Failed {
+                name: self.name.clone(),
+                failures,
+            })
+        }
+    }
+}
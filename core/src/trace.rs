@@ -0,0 +1,191 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub const LOOPS_PER_SECOND: f32 = 22.4;
+
+// One decision of a component on a game loop, written as a JSON line.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceEntry {
+    pub game_loop: u32,
+    pub component: String,
+    pub units: Vec<u64>,
+    pub data: Value,
+}
+
+impl TraceEntry {
+    pub fn time(&self) -> f32 {
+        self.game_loop as f32 / LOOPS_PER_SECOND
+    }
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{} {:>7.1}s] {:<18} {}",
+            self.game_loop,
+            self.time(),
+            self.component,
+            self.data
+        )
+    }
+}
+
+// Decision trace sink, disabled unless opened with a file. Buffered lines are
+// written once per game second and when the game ends.
+#[derive(Default)]
+pub struct Trace {
+    writer: RefCell<Option<BufWriter<File>>>,
+    last_flush: u32,
+}
+
+impl Trace {
+    pub fn open(path: &str) -> io::Result<Self> {
+        Ok(Self {
+            writer: RefCell::new(Some(BufWriter::new(File::create(path)?))),
+            last_flush: 0,
+        })
+    }
+
+    // Components should check this before building the trace data.
+    pub fn is_enabled(&self) -> bool {
+        self.writer.borrow().is_some()
+    }
+
+    pub fn record(&mut self, game_loop: u32, component: &str, units: Vec<u64>, data: Value) {
+        if let Some(writer) = self.writer.get_mut() {
+            let entry = TraceEntry {
+                game_loop,
+                component: component.to_string(),
+                units,
+                data,
+            };
+            let result = serde_json::to_string(&entry)
+                .map_err(io::Error::from)
+                .and_then(|line| writeln!(writer, "{}", line));
+            if let Err(e) = result {
+                error!("Could not write decision trace, disabling it: {}", e);
+                *self.writer.get_mut() = None;
+                return;
+            }
+        }
+        if game_loop >= self.last_flush + LOOPS_PER_SECOND as u32 {
+            self.last_flush = game_loop;
+            self.flush();
+        }
+    }
+
+    // Takes &self so it can be called when the game ends
+    pub fn flush(&self) {
+        let mut writer = self.writer.borrow_mut();
+        if let Some(Err(e)) = writer.as_mut().map(|w| w.flush()) {
+            error!("Could not write decision trace, disabling it: {}", e);
+            *writer = None;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TraceFilter {
+    pub unit: Option<u64>,
+    pub component: Option<String>,
+    // Time window in game seconds
+    pub from: Option<f32>,
+    pub to: Option<f32>,
+}
+
+impl TraceFilter {
+    pub fn matches(&self, entry: &TraceEntry) -> bool {
+        self.unit.map_or(true, |unit| entry.units.contains(&unit))
+            && self
+                .component
+                .as_ref()
+                .map_or(true, |component| &entry.component == component)
+            && self.from.map_or(true, |from| entry.time() >= from)
+            && self.to.map_or(true, |to| entry.time() <= to)
+    }
+}
+
+pub fn read_trace(path: &Path, filter: &TraceFilter) -> io::Result<Vec<TraceEntry>> {
+    let mut result = vec![];
+    for (index, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<TraceEntry>(&line) {
+            Ok(entry) if filter.matches(&entry) => result.push(entry),
+            Ok(_) => {}
+            Err(e) => warn!("Skipping trace line {}: {}", index + 1, e),
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::trace::{read_trace, Trace, TraceEntry, TraceFilter};
+
+    fn entry(game_loop: u32, component: &str, units: Vec<u64>) -> TraceEntry {
+        TraceEntry {
+            game_loop,
+            component: component.to_string(),
+            units,
+            data: json!({}),
+        }
+    }
+
+    #[test]
+    fn filter_by_unit_and_window() {
+        let filter = TraceFilter {
+            unit: Some(42),
+            from: Some(60f32),
+            to: Some(120f32),
+            ..Default::default()
+        };
+        assert!(filter.matches(&entry(1_792, "ArmyManager", vec![1, 42])));
+        assert!(!filter.matches(&entry(1_792, "ArmyManager", vec![1])));
+        assert!(!filter.matches(&entry(1_000, "ArmyManager", vec![42])));
+        assert!(!filter.matches(&entry(3_000, "WorkerManager", vec![42])));
+    }
+
+    #[test]
+    fn entry_round_trip() {
+        let entry = TraceEntry {
+            game_loop: 224,
+            component: "WorkerManager".to_string(),
+            units: vec![7],
+            data: json!({"7": "Gather"}),
+        };
+        let line = serde_json::to_string(&entry).unwrap();
+        assert_eq!(serde_json::from_str::<TraceEntry>(&line).unwrap(), entry);
+    }
+
+    #[test]
+    fn written_every_game_second() {
+        let path = std::env::temp_dir().join(format!(
+            "caninana_written_every_game_second_{}.jsonl",
+            std::process::id()
+        ));
+        let mut trace = Trace::open(path.to_str().unwrap()).unwrap();
+        let filter = TraceFilter::default();
+        trace.record(10, "ArmyManager", vec![], json!({}));
+        assert!(read_trace(&path, &filter).unwrap().is_empty());
+        trace.record(40, "ArmyManager", vec![], json!({}));
+        assert_eq!(read_trace(&path, &filter).unwrap().len(), 2);
+        trace.record(45, "ArmyManager", vec![], json!({}));
+        trace.flush();
+        assert_eq!(read_trace(&path, &filter).unwrap().len(), 3);
+        drop(trace);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
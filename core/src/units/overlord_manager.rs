@@ -7,8 +7,10 @@ use rust_sc2::Event::UnitDestroyed;
 use std::cmp::Ordering;
 use std::collections::HashMap;
 
+use crate::bus::{Message, Subscription};
 use crate::command_queue::Command;
-use crate::params::*;
+use crate::game_view::BotView;
+use crate::managers::rally_manager::safe_spot;
 use crate::utils::*;
 use crate::{AIComponent, BotState};
 
@@ -19,6 +21,7 @@ pub struct OverlordManager {
     placement_occupation: HashMap<Point2, u64>,
     assignments: HashMap<u64, OverlordAssignment>,
     cast_time: HashMap<u64, f32>,
+    subscription: Subscription,
 }
 
 enum OverlordAssignment {
@@ -41,7 +44,7 @@ impl OverlordManager {
     fn queue_overseers(&self, bot: &mut Bot, bot_state: &mut BotState) {
         let workers = bot.units.my.workers.len();
         let invisible_units = bot_state.enemy_cache.units.filter(|u| u.need_detection());
-        if workers >= UNLOCK_OVERSEER_WORKERS || !invisible_units.is_empty() {
+        if workers >= bot_state.params.unlock_overseer_workers || !invisible_units.is_empty() {
             let workers = bot.supply_workers as usize;
             bot_state.build_queue.push(
                 Command::new_unit(
@@ -197,7 +200,7 @@ impl OverlordManager {
                             }
                             OverlordAssignment::Unit(current_assignment) => {
                                 if let Some(unit) = enemy_units.get(*current_assignment) {
-                                    if OVERSEER_SWAP_DISTANCE
+                                    if bot_state.params.overseer_swap_distance
                                         + possible_target.distance(bot.start_location)
                                         < unit.distance(bot.start_location)
                                     {
@@ -249,13 +252,14 @@ impl OverlordManager {
     }
 
     fn micro(&mut self, bot: &mut Bot, bot_state: &mut BotState) {
-        self.micro_overlord(bot, bot_state);
+        let safe_point = safe_spot(&BotView::new(bot), &bot_state.threats);
+        self.micro_overlord(bot, bot_state, safe_point);
         self.micro_overseer(bot, bot_state);
         self.micro_changeling(bot, bot_state);
     }
 
     // TODO: Hide them if enemy is going heavy on anti air.
-    fn micro_overlord(&self, bot: &Bot, bot_state: &BotState) {
+    fn micro_overlord(&self, bot: &Bot, bot_state: &mut BotState, safe_point: Point2) {
         let random_scouting = bot_state
             .enemy_cache
             .units
@@ -277,13 +281,6 @@ impl OverlordManager {
             {
                 unit.move_towards(bot, bot_state, -20f32);
             } else {
-                let safe_point = bot
-                    .units
-                    .my
-                    .townhalls
-                    .closest(bot.start_location)
-                    .map(|u| u.position())
-                    .unwrap_or(bot.start_location);
                 let position = if Some(unit.tag()) == self.scout_lord {
                     if let Some(closest_enemy) = bot_state
                         .enemy_cache
@@ -310,9 +307,10 @@ impl OverlordManager {
                     }
                 } else if random_scouting && unit.hits_percentage().unwrap_or_default() > 0.9f32 {
                     if unit.is_idle() {
-                        let mut rng = thread_rng();
-                        let random_x = (rng.next_u64() % bot.game_info.map_size.x as u64) as f32;
-                        let random_y = (rng.next_u64() % bot.game_info.map_size.y as u64) as f32;
+                        let random_x =
+                            (bot_state.rng.next_u64() % bot.game_info.map_size.x as u64) as f32;
+                        let random_y =
+                            (bot_state.rng.next_u64() % bot.game_info.map_size.y as u64) as f32;
                         Point2::new(random_x, random_y)
                     } else {
                         unit.target_pos().unwrap()
@@ -348,7 +346,7 @@ impl OverlordManager {
                     .get(&overseer.tag())
                     .cloned()
                     .unwrap_or_default()
-                    + OVERSEER_CHANGELING_DELAY
+                    + bot_state.params.overseer_changeling_delay
                     < bot.time
             {
                 self.cast_time.insert(overseer.tag(), bot.time);
@@ -392,6 +390,20 @@ impl OverlordManager {
         }
     }
 
+    // Morphed overlords get an assignment for their new type
+    fn read_messages(&mut self, bot_state: &BotState) {
+        for message in bot_state.messages.receive(&mut self.subscription) {
+            if let Message::UnitMorphed {
+                tag,
+                from: UnitTypeId::Overlord,
+                ..
+            } = message
+            {
+                self.clear_assignment_unit(tag);
+            }
+        }
+    }
+
     fn clear_assignment_point(&mut self, point: &Point2) {
         let removed_tag = self.placement_occupation.remove(point);
         if let Some(tag) = removed_tag {
@@ -399,8 +411,7 @@ impl OverlordManager {
         }
     }
 
-    fn micro_changeling(&self, bot: &mut Bot, _bot_state: &mut BotState) {
-        let mut random = thread_rng();
+    fn micro_changeling(&self, bot: &mut Bot, bot_state: &mut BotState) {
         for changeling in bot
             .units
             .my
@@ -419,7 +430,7 @@ impl OverlordManager {
                 .expansions
                 .iter()
                 .filter(|u| !u.alliance.is_mine())
-                .choose(&mut random)
+                .choose(&mut bot_state.rng)
             {
                 expansion.loc
             } else {
@@ -432,6 +443,7 @@ impl OverlordManager {
 
 impl AIComponent for OverlordManager {
     fn process(&mut self, bot: &mut Bot, bot_state: &mut BotState) {
+        self.read_messages(bot_state);
         self.build_placement_map(bot);
         self.assignment(bot, bot_state);
         self.micro(bot, bot_state);
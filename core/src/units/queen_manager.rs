@@ -1,22 +1,64 @@
+use std::collections::HashMap;
+
 use log::debug;
 use rust_sc2::action::ActionResult;
 use rust_sc2::bot::Bot;
 use rust_sc2::prelude::*;
 
-use crate::params::*;
+use crate::params::Params;
 use crate::utils::*;
 use crate::{AIComponent, BotState};
 
 #[derive(Default)]
 pub struct QueenManager {
     spread_map: Vec<Point2>,
+    roles: HashMap<u64, QueenRole>,
+    // Created units waiting for a role, non queens are dropped
+    fresh: Vec<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum QueenRole {
+    Inject(u64),
+    Creep,
 }
 
 impl QueenManager {
-    fn handle_transfusion(&mut self, bot: &mut Bot) {
+    fn assign_roles(&mut self, bot: &Bot) {
+        for tag in std::mem::take(&mut self.fresh) {
+            if let Some(queen) = bot.units.my.units.get(tag) {
+                if queen.type_id() != UnitTypeId::Queen {
+                    continue;
+                }
+                let role = bot
+                    .units
+                    .my
+                    .townhalls
+                    .ready()
+                    .filter(|h| {
+                        !self
+                            .roles
+                            .values()
+                            .any(|r| *r == QueenRole::Inject(h.tag()))
+                    })
+                    .closest(queen)
+                    .map_or(QueenRole::Creep, |h| QueenRole::Inject(h.tag()));
+                debug!("Queen {} role {:?}", tag, role);
+                self.roles.insert(tag, role);
+            }
+        }
+    }
+
+    fn assigned_queen<'a>(&self, queens: &'a Units, base: u64) -> Option<&'a Unit> {
+        queens
+            .iter()
+            .find(|q| self.roles.get(&q.tag()) == Some(&QueenRole::Inject(base)))
+    }
+
+    fn handle_transfusion(&mut self, bot: &mut Bot, bot_state: &BotState) {
         let queens = bot.units.my.units.of_type(UnitTypeId::Queen).filter(|u| {
             !u.is_using(AbilityId::EffectInjectLarva)
-                && u.energy().unwrap_or_default() > TRANSFUSION_MIN_ENERGY
+                && u.energy().unwrap_or_default() > bot_state.params.transfusion_min_energy
                 && u.has_ability(AbilityId::TransfusionTransfusion)
         });
 
@@ -35,9 +77,10 @@ impl QueenManager {
                     u.tag() != queen.tag()
                         && !being_healed.contains(&u.tag())
                         && !u.is_burrowed()
-                        && u.position().distance(queen.position()) < TRANSFUSION_MAX_RANGE
+                        && u.position().distance(queen.position())
+                            < bot_state.params.transfusion_max_range
                         && u.health_max().unwrap_or_default() - u.health().unwrap_or_default()
-                            > TRANSFUSION_MISSING_HEALTH
+                            > bot_state.params.transfusion_missing_health
                 })
                 .min(|u| u.hits_percentage())
             {
@@ -53,9 +96,9 @@ impl QueenManager {
         }
     }
 
-    fn handle_spread(&mut self, bot: &mut Bot) {
+    fn handle_spread(&mut self, bot: &mut Bot, bot_state: &BotState) {
         if self.spread_map.is_empty() {
-            self.spread_map = bot.create_creep_spread_map();
+            self.spread_map = bot.create_creep_spread_map(&bot_state.params);
         }
         let tumors = bot.units.my.all.of_type(UnitTypeId::CreepTumorBurrowed);
 
@@ -74,7 +117,9 @@ impl QueenManager {
                     })
                     .closest(h.position())
                 {
-                    if let Some(position) = bot.find_creep_placement(h, *closest_spot) {
+                    if let Some(position) =
+                        bot.find_creep_placement(h, *closest_spot, &bot_state.params)
+                    {
                         h.command(
                             AbilityId::BuildCreepTumorTumor,
                             Target::Pos(position),
@@ -84,20 +129,21 @@ impl QueenManager {
                 }
             });
 
-        let min_energy = CREEP_SPREAD_ENERGY
-            .min(CREEP_SPREAD_ENERGY_MIN + tumors.len() as u32 * CREEP_SPREAD_ENERGY_PER_TUMOR);
-        if let Some(queen) = bot
-            .units
-            .my
-            .units
-            .filter(|u| {
-                !u.is_using(AbilityId::EffectInjectLarva)
-                    && !u.is_using(AbilityId::TransfusionTransfusion)
-                    && !u.is_using(AbilityId::BuildCreepTumorQueen)
-                    && u.has_ability(AbilityId::BuildCreepTumorQueen)
-                    && u.energy().unwrap_or_default() >= min_energy
-            })
-            .first()
+        let min_energy = bot_state.params.creep_spread_energy.min(
+            bot_state.params.creep_spread_energy_min
+                + tumors.len() as u32 * bot_state.params.creep_spread_energy_per_tumor,
+        );
+        let spreading_queens = bot.units.my.units.filter(|u| {
+            !u.is_using(AbilityId::EffectInjectLarva)
+                && !u.is_using(AbilityId::TransfusionTransfusion)
+                && !u.is_using(AbilityId::BuildCreepTumorQueen)
+                && u.has_ability(AbilityId::BuildCreepTumorQueen)
+                && u.energy().unwrap_or_default() >= min_energy
+        });
+        if let Some(queen) = spreading_queens
+            .iter()
+            .find(|q| self.roles.get(&q.tag()) == Some(&QueenRole::Creep))
+            .or_else(|| spreading_queens.first())
         {
             if let Some(closest_spot) = self
                 .spread_map
@@ -123,10 +169,10 @@ impl QueenManager {
         }
     }
 
-    fn handle_injection(&mut self, bot: &mut Bot) {
-        if bot.units.my.larvas.len() > INJECTION_MAX_LARVA
+    fn handle_injection(&mut self, bot: &mut Bot, bot_state: &BotState) {
+        if bot.units.my.larvas.len() > bot_state.params.injection_max_larva
             || bot.units.my.larvas.len()
-                > bot.units.my.townhalls.len() * INJECTION_MAX_LARVA_PER_BASE
+                > bot.units.my.townhalls.len() * bot_state.params.injection_max_larva_per_base
         {
             return;
         }
@@ -152,7 +198,10 @@ impl QueenManager {
                 .filter(|h| !h.has_buff(BuffId::QueenSpawnLarvaTimer))
             {
                 debug!("Need to inject in base {}", base.tag());
-                if let Some(closest_queen) = queens.closest(base) {
+                if let Some(closest_queen) = self
+                    .assigned_queen(&queens, base.tag())
+                    .or_else(|| queens.closest(base))
+                {
                     if closest_queen.is_using(AbilityId::EffectInjectLarva) {
                         if let Some(current_job) = closest_queen.target_tag() {
                             if current_job != base.tag() {
@@ -161,7 +210,7 @@ impl QueenManager {
                                 {
                                     if injecting_in_base.distance(closest_queen)
                                         > base.distance(closest_queen)
-                                            + QUEEN_INJECT_SWITCH_BASE_RANGE
+                                            + bot_state.params.queen_inject_switch_base_range
                                     {
                                         closest_queen.order_ability_at(
                                             AbilityId::TransfusionTransfusion,
@@ -205,17 +254,17 @@ impl QueenManager {
 }
 
 trait CreepMap {
-    fn create_creep_spread_map(&self) -> Vec<Point2>;
+    fn create_creep_spread_map(&self, params: &Params) -> Vec<Point2>;
 }
 
 impl CreepMap for Bot {
-    fn create_creep_spread_map(&self) -> Vec<Point2> {
+    fn create_creep_spread_map(&self, params: &Params) -> Vec<Point2> {
         let mut result = vec![];
         for x in (self.game_info.playable_area.x0..self.game_info.playable_area.x1)
-            .step_by(CREEP_SPREAD_MAP_DISTANCE)
+            .step_by(params.creep_spread_map_distance)
         {
             for y in (self.game_info.playable_area.y0..self.game_info.playable_area.y1)
-                .step_by(CREEP_SPREAD_MAP_DISTANCE)
+                .step_by(params.creep_spread_map_distance)
             {
                 let point = Point2::new(x as f32, y as f32);
                 if self.is_placeable(point)
@@ -225,7 +274,7 @@ impl CreepMap for Bot {
                         .map(|e| e.loc)
                         .closest_distance(point)
                         .unwrap_or_default()
-                        > CREEP_SPREAD_MAP_DISTANCE as f32
+                        > params.creep_spread_map_distance as f32
                 {
                     result.push(point);
                 }
@@ -236,15 +285,15 @@ impl CreepMap for Bot {
 }
 
 trait CreepPlacement {
-    fn find_creep_placement(&self, unit: &Unit, pos: Point2) -> Option<Point2>;
+    fn find_creep_placement(&self, unit: &Unit, pos: Point2, params: &Params) -> Option<Point2>;
 }
 
 impl CreepPlacement for Bot {
-    fn find_creep_placement(&self, unit: &Unit, spot: Point2) -> Option<Point2> {
+    fn find_creep_placement(&self, unit: &Unit, spot: Point2, params: &Params) -> Option<Point2> {
         if let Some(data) = self.game_data.units.get(&UnitTypeId::CreepTumor) {
             if let Some(ability) = data.ability {
                 let placement_step = 1;
-                let range = CREEP_SPREAD_DISTANCE as f32;
+                let range = params.creep_spread_distance as f32;
                 let near = unit.position().towards(spot, range);
                 for distance in (placement_step..(range as i32)).step_by(placement_step as usize) {
                     let positions = (-distance..=distance)
@@ -259,8 +308,8 @@ impl CreepPlacement for Bot {
                         })
                         .filter(|p| {
                             if let Some(exp) = self.expansions.iter().map(|e| e.loc).closest(p) {
-                                (exp.x - p.x).abs() > CREEP_DISTANCE_TO_HALL
-                                    || (exp.y - p.y).abs() > CREEP_DISTANCE_TO_HALL
+                                (exp.x - p.x).abs() > params.creep_distance_to_hall
+                                    || (exp.y - p.y).abs() > params.creep_distance_to_hall
                             } else {
                                 false
                             }
@@ -297,9 +346,31 @@ impl CreepPlacement for Bot {
 }
 
 impl AIComponent for QueenManager {
-    fn process(&mut self, bot: &mut Bot, _: &mut BotState) {
-        self.handle_injection(bot);
-        self.handle_spread(bot);
-        self.handle_transfusion(bot);
+    fn process(&mut self, bot: &mut Bot, bot_state: &mut BotState) {
+        self.assign_roles(bot);
+        self.handle_injection(bot, bot_state);
+        self.handle_spread(bot, bot_state);
+        self.handle_transfusion(bot, bot_state);
+    }
+
+    fn on_event(&mut self, event: &Event, _: &mut BotState) {
+        match event {
+            Event::UnitCreated(tag) => self.fresh.push(*tag),
+            Event::UnitDestroyed(tag, _) => {
+                self.roles.remove(tag);
+                // Queens of a lost base look for a new one
+                let orphans = self
+                    .roles
+                    .iter()
+                    .filter(|(_, role)| **role == QueenRole::Inject(*tag))
+                    .map(|(queen, _)| *queen)
+                    .collect::<Vec<u64>>();
+                for queen in orphans {
+                    self.roles.remove(&queen);
+                    self.fresh.push(queen);
+                }
+            }
+            _ => {}
+        }
     }
 }
@@ -1,3 +1,4 @@
+use crate::game_view::UnitSnapshot;
 use crate::BotState;
 use rust_sc2::bot::Bot;
 use rust_sc2::prelude::*;
@@ -88,6 +89,12 @@ impl NeedsCorruptors for Unit {
     }
 }
 
+impl NeedsCorruptors for UnitSnapshot {
+    fn need_corruptors(&self) -> bool {
+        NEED_CORRUPTORS.contains(&self.type_id)
+    }
+}
+
 pub trait NeedsRoaches {
     fn need_roaches(&self) -> bool;
 }
@@ -98,6 +105,12 @@ impl NeedsRoaches for Unit {
     }
 }
 
+impl NeedsRoaches for UnitSnapshot {
+    fn need_roaches(&self) -> bool {
+        NEED_ROACHES.contains(&self.type_id)
+    }
+}
+
 pub trait NeedsRavagers {
     fn need_ravagers(&self) -> bool;
 }
@@ -176,37 +189,54 @@ impl Strength for Unit {
 
 impl BaseStrength for Unit {
     fn base_strength(&self, _bot: &Bot) -> f32 {
-        let multiplier = if !self.is_almost_ready() || self.is_hallucination() || !self.can_attack()
-        {
-            0.0f32
-        } else if self.is_cloaked() {
-            1.2f32
-        } else {
-            1f32
-        };
-        multiplier * (self.type_id().base_strength(_bot))
+        strength_multiplier(
+            self.is_almost_ready(),
+            self.is_hallucination(),
+            self.can_attack(),
+            self.is_cloaked(),
+        ) * (self.type_id().base_strength(_bot))
     }
 }
 
 impl BaseStrength for UnitTypeId {
     fn base_strength(&self, _bot: &Bot) -> f32 {
-        let multiplier = if self.is_worker() {
-            0.1f32
-        } else if SPECIAL_DANGEROUS.contains(self) {
-            0.5f32
-        } else if self.is_structure() {
-            1.5f32
-        } else {
-            1f32
-        };
         if let Some(cost) = _bot.game_data.units.get(self) {
-            multiplier * (cost.vespene_cost as f32 * 1.6875f32 + cost.mineral_cost as f32)
+            cost_strength(*self, cost.mineral_cost, cost.vespene_cost)
         } else {
             0f32
         }
     }
 }
 
+// Shared with the unit snapshots, so both views agree on strength.
+pub fn strength_multiplier(
+    is_almost_ready: bool,
+    is_hallucination: bool,
+    can_attack: bool,
+    is_cloaked: bool,
+) -> f32 {
+    if !is_almost_ready || is_hallucination || !can_attack {
+        0.0f32
+    } else if is_cloaked {
+        1.2f32
+    } else {
+        1f32
+    }
+}
+
+pub fn cost_strength(unit_type: UnitTypeId, mineral_cost: u32, vespene_cost: u32) -> f32 {
+    let multiplier = if unit_type.is_worker() {
+        0.1f32
+    } else if SPECIAL_DANGEROUS.contains(&unit_type) {
+        0.5f32
+    } else if unit_type.is_structure() {
+        1.5f32
+    } else {
+        1f32
+    };
+    multiplier * (vespene_cost as f32 * 1.6875f32 + mineral_cost as f32)
+}
+
 pub trait CounteredBy {
     fn countered_by(&self) -> Vec<UnitTypeId>;
 }
@@ -438,6 +468,42 @@ impl Between for Point2 {
     }
 }
 
+pub trait SegmentDistance {
+    fn segment_distance(self, from: Point2, to: Point2) -> f32;
+}
+
+impl SegmentDistance for Point2 {
+    fn segment_distance(self, from: Point2, to: Point2) -> f32 {
+        let length = from.distance_squared(to);
+        if length == 0f32 {
+            return self.distance(from);
+        }
+        let t = (((self.x - from.x) * (to.x - from.x) + (self.y - from.y) * (to.y - from.y))
+            / length)
+            .clamp(0f32, 1f32);
+        self.distance(Point2::new(
+            from.x + t * (to.x - from.x),
+            from.y + t * (to.y - from.y),
+        ))
+    }
+}
+
+// Centers a footprint of the given size on the placement grid, odd sizes on
+// tiles and even ones on tile corners.
+pub trait GridSnap {
+    fn snap(self, size: usize) -> Point2;
+}
+
+impl GridSnap for Point2 {
+    fn snap(self, size: usize) -> Point2 {
+        if size % 2 == 1 {
+            Point2::new(self.x.floor() + 0.5, self.y.floor() + 0.5)
+        } else {
+            Point2::new(self.x.round(), self.y.round())
+        }
+    }
+}
+
 pub trait PathingDistance {
     fn pathing_distance(&self, p1: Point2, p2: Point2) -> Option<f32>;
 }
@@ -652,13 +718,59 @@ impl ProducedOn for UpgradeId {
             | UpgradeId::ZergFlyerWeaponsLevel3 => {
                 vec![UnitTypeId::Spire, UnitTypeId::GreaterSpire]
             }
-            _ => {
-                panic!("Idk where to produce {:?}", self);
-            }
+            // Not researched by us
+            _ => vec![],
         }
     }
 }
 
+// What the production knows how to make, anything else would never finish
+pub trait Producible {
+    fn is_producible(&self) -> bool;
+}
+
+impl Producible for UnitTypeId {
+    fn is_producible(&self) -> bool {
+        matches!(
+            self,
+            UnitTypeId::Drone
+                | UnitTypeId::Overlord
+                | UnitTypeId::Overseer
+                | UnitTypeId::Queen
+                | UnitTypeId::Zergling
+                | UnitTypeId::Baneling
+                | UnitTypeId::Roach
+                | UnitTypeId::Ravager
+                | UnitTypeId::Hydralisk
+                | UnitTypeId::Mutalisk
+                | UnitTypeId::Corruptor
+                | UnitTypeId::BroodLord
+                | UnitTypeId::Ultralisk
+                | UnitTypeId::Hatchery
+                | UnitTypeId::Lair
+                | UnitTypeId::Hive
+                | UnitTypeId::Extractor
+                | UnitTypeId::SpawningPool
+                | UnitTypeId::EvolutionChamber
+                | UnitTypeId::RoachWarren
+                | UnitTypeId::BanelingNest
+                | UnitTypeId::HydraliskDen
+                | UnitTypeId::Spire
+                | UnitTypeId::GreaterSpire
+                | UnitTypeId::InfestationPit
+                | UnitTypeId::UltraliskCavern
+                | UnitTypeId::SpineCrawler
+                | UnitTypeId::SporeCrawler
+        )
+    }
+}
+
+impl Producible for UpgradeId {
+    fn is_producible(&self) -> bool {
+        !self.produced_on().is_empty()
+    }
+}
+
 impl BuildingRequirement for UpgradeId {
     fn building_requirements(&self) -> Vec<UnitTypeId> {
         match *self {
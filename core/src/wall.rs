@@ -0,0 +1,228 @@
+use rust_sc2::prelude::*;
+
+use crate::game_view::{BaseOwner, GameView};
+use crate::utils::GridSnap;
+
+// Partial wall across the natural choke, tech structures side by side with a
+// one tile gap for our units and spines covering the gap from behind.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WallPlan {
+    pub natural: Point2,
+    pub structures: Vec<(UnitTypeId, Point2)>,
+    pub gap: Point2,
+    pub spines: Vec<Point2>,
+}
+
+impl WallPlan {
+    pub fn positions(&self, unit_type: UnitTypeId) -> Vec<Point2> {
+        if unit_type == UnitTypeId::SpineCrawler {
+            self.spines.clone()
+        } else {
+            self.structures
+                .iter()
+                .filter(|(t, _)| *t == unit_type)
+                .map(|(_, p)| *p)
+                .collect()
+        }
+    }
+}
+
+// Early pressure we wall against
+pub fn wall_needed(enemy_race: Race) -> bool {
+    matches!(enemy_race, Race::Protoss | Race::Zerg)
+}
+
+const WALL_ORDER: [UnitTypeId; 3] = [
+    UnitTypeId::EvolutionChamber,
+    UnitTypeId::SpawningPool,
+    UnitTypeId::RoachWarren,
+];
+// Structures placed before the gap
+const GAP_AFTER: usize = 2;
+const MAX_LINE_SPINES: usize = 2;
+const CHOKE_SEARCH: std::ops::RangeInclusive<u32> = 5..=20;
+const MAX_HALF_WIDTH: u32 = 12;
+const MAIN_RANGE: f32 = 12f32;
+
+fn along(origin: Point2, direction: Point2, distance: f32) -> Point2 {
+    Point2::new(
+        origin.x + direction.x * distance,
+        origin.y + direction.y * distance,
+    )
+}
+
+// Open tiles from the center (included) until something blocks the way
+fn half_width<G: GameView>(game: &G, center: Point2, side: Point2) -> Option<u32> {
+    (1..=MAX_HALF_WIDTH).find(|i| !game.is_pathable(along(center, side, *i as f32)))
+}
+
+// Closest expansion to the main by ground that isn't the enemy's
+pub fn natural_location<G: GameView>(game: &G) -> Option<Point2> {
+    let main = game.start_location();
+    game.expansions()
+        .iter()
+        .filter(|e| e.owner != BaseOwner::Enemy && e.loc.distance(main) > MAIN_RANGE)
+        .filter_map(|e| game.ground_distance(main, e.loc).map(|d| (d, e.loc)))
+        .min_by(|(a, _), (b, _)| a.total_cmp(b))
+        .map(|(_, loc)| loc)
+}
+
+// Walks out of the natural towards the enemy looking for the narrowest section,
+// None when the natural is open to every side.
+pub fn plan_natural_wall<G: GameView>(game: &G) -> Option<WallPlan> {
+    let natural = natural_location(game)?;
+    let target = game.enemy_start();
+    let length = natural.distance(target);
+    if length == 0f32 {
+        return None;
+    }
+    let direction = Point2::new(
+        (target.x - natural.x) / length,
+        (target.y - natural.y) / length,
+    );
+    // Structures line up along the grid axis closest to the choke, footprints
+    // placed along a diagonal would overlap
+    let side = if direction.y.abs() >= direction.x.abs() {
+        Point2::new(direction.y.signum(), 0f32)
+    } else {
+        Point2::new(0f32, -direction.x.signum())
+    };
+    let back_side = Point2::new(-side.x, -side.y);
+    let forward = Point2::new(-side.y, side.x);
+    let mut choke: Option<(Point2, u32, u32)> = None;
+    for distance in CHOKE_SEARCH {
+        let center = along(natural, direction, distance as f32).snap(1);
+        if !game.is_pathable(center) {
+            break;
+        }
+        if let (Some(right), Some(left)) = (
+            half_width(game, center, side),
+            half_width(game, center, back_side),
+        ) {
+            if choke.map_or(true, |(_, r, l)| right + left < r + l) {
+                choke = Some((center, right, left));
+            }
+        }
+    }
+    let (center, right, left) = choke?;
+    let width = (right + left - 1) as usize;
+    let edge = along(center, back_side, left as f32 - 0.5f32);
+    let mut structures = vec![];
+    let mut spines = vec![];
+    let mut offset = 0usize;
+    let mut gap = None;
+    for (index, unit_type) in WALL_ORDER.iter().enumerate() {
+        if index == GAP_AFTER {
+            gap = Some(along(edge, side, offset as f32 + 0.5f32).snap(1));
+            offset += 1;
+        }
+        if offset + 3 > width {
+            break;
+        }
+        structures.push((
+            *unit_type,
+            along(edge, side, offset as f32 + 1.5f32).snap(3),
+        ));
+        offset += 3;
+    }
+    let gap = gap?;
+    while offset + 2 <= width && spines.len() < MAX_LINE_SPINES {
+        spines.push(along(edge, side, offset as f32 + 1f32).snap(2));
+        offset += 2;
+    }
+    spines.insert(0, along(gap, forward, -3f32).snap(2));
+    Some(WallPlan {
+        natural,
+        structures,
+        gap,
+        spines,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_sc2::prelude::*;
+
+    use crate::fake_game::FakeGame;
+    use crate::game_view::{BaseOwner, ExpansionSnapshot};
+    use crate::wall::{plan_natural_wall, WallPlan};
+
+    fn walled_game() -> FakeGame {
+        let mut game = FakeGame {
+            enemy_start: Point2::new(30f32, 170f32),
+            ..Default::default()
+        };
+        game.expansions.push(ExpansionSnapshot {
+            loc: Point2::new(30f32, 60f32),
+            geysers: 2,
+            owner: BaseOwner::Neutral,
+            base: None,
+        });
+        // Ten tiles wide choke
+        for y in 68..=72 {
+            for x in (0..=24).chain(35..=60) {
+                game.unpathable.insert((x, y));
+            }
+        }
+        game
+    }
+
+    #[test]
+    fn wall_leaves_one_tile_gap() {
+        let plan = plan_natural_wall(&walled_game()).unwrap();
+        assert_eq!(
+            plan.structures,
+            vec![
+                (UnitTypeId::EvolutionChamber, Point2::new(26.5f32, 68.5f32)),
+                (UnitTypeId::SpawningPool, Point2::new(29.5f32, 68.5f32)),
+                (UnitTypeId::RoachWarren, Point2::new(33.5f32, 68.5f32)),
+            ]
+        );
+        assert_eq!(plan.gap, Point2::new(31.5f32, 68.5f32));
+        assert_eq!(plan.spines, vec![Point2::new(32f32, 66f32)]);
+    }
+
+    #[test]
+    fn open_natural_not_walled() {
+        let mut game = walled_game();
+        game.unpathable.clear();
+        assert_eq!(plan_natural_wall(&game), None);
+    }
+
+    fn overlaps(a: (Point2, f32), b: (Point2, f32)) -> bool {
+        let reach = (a.1 + b.1) / 2f32;
+        (a.0.x - b.0.x).abs() < reach && (a.0.y - b.0.y).abs() < reach
+    }
+
+    fn footprints(plan: &WallPlan) -> Vec<(Point2, f32)> {
+        plan.structures
+            .iter()
+            .map(|(_, p)| (*p, 3f32))
+            .chain(plan.spines.iter().map(|p| (*p, 2f32)))
+            .collect()
+    }
+
+    #[test]
+    fn diagonal_choke_footprints_apart() {
+        let mut game = walled_game();
+        game.enemy_start = Point2::new(170f32, 200f32);
+        game.unpathable.clear();
+        // Choke crossing the way to the enemy at 45 degrees
+        for x in 0..=130usize {
+            for y in 0..=130usize {
+                let across = (x as i32 - y as i32 + 30).abs();
+                if (100..=130).contains(&(x + y)) && across > 6 {
+                    game.unpathable.insert((x, y));
+                }
+            }
+        }
+        let plan = plan_natural_wall(&game).unwrap();
+        assert_eq!(plan.structures.len(), 3);
+        let footprints = footprints(&plan);
+        for (i, a) in footprints.iter().enumerate() {
+            for b in footprints.iter().skip(i + 1) {
+                assert!(!overlaps(*a, *b), "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+}
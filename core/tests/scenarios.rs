@@ -0,0 +1,29 @@
+use std::path::Path;
+
+use caninana_core::scenario::Scenario;
+
+fn verify(file: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("scenarios")
+        .join(file);
+    let scenario = Scenario::load(&path).unwrap_or_else(|e| panic!("{}: {}", file, e));
+    if let Err(e) = scenario.verify() {
+        panic!("{}", e);
+    }
+}
+
+#[test]
+fn worker_rush() {
+    verify("worker_rush.json");
+}
+
+#[test]
+fn cannon_rush_at_natural() {
+    verify("cannon_rush.json");
+}
+
+#[test]
+fn supply_blocked() {
+    verify("supply_blocked.json");
+}
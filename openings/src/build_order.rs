@@ -0,0 +1,722 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use log::{debug, warn};
+use rust_sc2::bot::Bot;
+use rust_sc2::prelude::*;
+use serde::de::value::StrDeserializer;
+use serde::de::{DeserializeOwned, IntoDeserializer};
+use serde::Deserialize;
+
+use caninana_core::command_queue::Command;
+use caninana_core::game_view::BotView;
+use caninana_core::managers::strategy_manager::StrategyPlan;
+use caninana_core::utils::Producible;
+use caninana_core::{BotState, Opening, SpendingFocus};
+
+use crate::condition::{Condition, ConditionState};
+use crate::OPENINGS;
+
+#[derive(Debug)]
+pub enum BuildOrderError {
+    Io(std::io::Error),
+    Parse(String),
+    UnsupportedFormat,
+    UnknownUnit { step: usize, name: String },
+    UnknownUpgrade { step: usize, name: String },
+    Unproducible { step: usize, name: String },
+    InvalidStep { step: usize, reason: &'static str },
+    UnknownStep { step: usize, id: String },
+    InvalidFollowUp(String),
+}
+
+impl fmt::Display for BuildOrderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildOrderError::Io(e) => write!(f, "could not read file: {}", e),
+            BuildOrderError::Parse(e) => write!(f, "could not parse file: {}", e),
+            BuildOrderError::UnsupportedFormat => {
+                write!(f, "only .toml and .json files are supported")
+            }
+            BuildOrderError::UnknownUnit { step, name } => {
+                write!(f, "step {}: unknown unit type `{}`", step, name)
+            }
+            BuildOrderError::UnknownUpgrade { step, name } => {
+                write!(f, "step {}: unknown upgrade `{}`", step, name)
+            }
+            BuildOrderError::Unproducible { step, name } => {
+                write!(f, "step {}: the bot can't produce `{}`", step, name)
+            }
+            BuildOrderError::InvalidStep { step, reason } => write!(f, "step {}: {}", step, reason),
+            BuildOrderError::UnknownStep { step, id } => {
+                write!(f, "step {}: no earlier step with id `{}`", step, id)
+            }
+            BuildOrderError::InvalidFollowUp(reason) => write!(f, "follow up: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for BuildOrderError {}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BuildOrderFile {
+    name: String,
+    #[serde(default)]
+    wall_natural: bool,
+    #[serde(default)]
+    steps: Vec<StepFile>,
+    follow_up: Option<FollowUpFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FollowUpFile {
+    name: String,
+    expires_at: f32,
+    #[serde(default)]
+    composition: BTreeMap<String, usize>,
+    timing_attack_supply: Option<u32>,
+    #[serde(default)]
+    tech: Vec<TechFile>,
+    spending_focus: Option<String>,
+    #[serde(default)]
+    invalidated_by: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TechFile {
+    unit: Option<String>,
+    upgrade: Option<String>,
+    amount: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StepFile {
+    id: Option<String>,
+    unit: Option<String>,
+    upgrade: Option<String>,
+    amount: Option<usize>,
+    priority: usize,
+    #[serde(default = "default_true")]
+    blocked: bool,
+    #[serde(default = "default_true")]
+    save_resources: bool,
+    #[serde(default)]
+    when: ConditionFile,
+    #[serde(default)]
+    cancels: Vec<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ConditionFile {
+    supply: Option<u32>,
+    time: Option<f32>,
+    enemy_natural_missing: Option<f32>,
+    enemy_unit_seen: Option<String>,
+    after: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuildStep {
+    pub id: Option<String>,
+    pub conditions: Vec<Condition>,
+    pub command: Option<Command>,
+    pub blocked: bool,
+    pub priority: usize,
+    pub cancels: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StepState {
+    Waiting,
+    Issued,
+    Cancelled,
+}
+
+#[derive(Debug, Clone)]
+pub struct BuildOrder {
+    pub name: String,
+    // Wall off the natural against Protoss and Zerg
+    pub wall_natural: bool,
+    pub steps: Vec<BuildStep>,
+    pub follow_up: Option<StrategyPlan>,
+    states: Vec<StepState>,
+    condition_state: ConditionState,
+}
+
+impl BuildOrder {
+    pub fn load(path: &Path) -> Result<Self, BuildOrderError> {
+        let content = fs::read_to_string(path).map_err(BuildOrderError::Io)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Self::from_toml(&content),
+            Some("json") => Self::from_json(&content),
+            _ => Err(BuildOrderError::UnsupportedFormat),
+        }
+    }
+
+    // Loads every build order in the directory, skipping (and reporting) broken
+    // files and names already taken by a built-in opening or an earlier file.
+    pub fn load_dir(dir: &str) -> Vec<Self> {
+        let mut result: Vec<Self> = vec![];
+        let mut paths = fs::read_dir(dir)
+            .map(|entries| entries.flatten().map(|e| e.path()).collect::<Vec<_>>())
+            .unwrap_or_default();
+        paths.sort();
+        for path in paths {
+            match Self::load(&path) {
+                Ok(build_order) if OPENINGS.contains(&build_order.name.as_str()) => warn!(
+                    "Skipping build order {:?}: {} is a built-in opening",
+                    path, build_order.name
+                ),
+                Ok(build_order) if result.iter().any(|b| b.name == build_order.name) => warn!(
+                    "Skipping build order {:?}: {} is already loaded",
+                    path, build_order.name
+                ),
+                Ok(build_order) => {
+                    debug!("Loaded build order {} from {:?}", build_order.name, path);
+                    result.push(build_order);
+                }
+                Err(e) => warn!("Skipping build order {:?}: {}", path, e),
+            }
+        }
+        result.sort_by(|a, b| a.name.cmp(&b.name));
+        result
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self, BuildOrderError> {
+        toml::from_str::<BuildOrderFile>(content)
+            .map_err(|e| BuildOrderError::Parse(e.to_string()))
+            .and_then(Self::from_file)
+    }
+
+    pub fn from_json(content: &str) -> Result<Self, BuildOrderError> {
+        serde_json::from_str::<BuildOrderFile>(content)
+            .map_err(|e| BuildOrderError::Parse(e.to_string()))
+            .and_then(Self::from_file)
+    }
+
+    fn from_file(file: BuildOrderFile) -> Result<Self, BuildOrderError> {
+        let mut steps: Vec<BuildStep> = vec![];
+        for (index, step) in file.steps.into_iter().enumerate() {
+            let step = Self::parse_step(index + 1, step)?;
+            let referenced =
+                step.cancels
+                    .iter()
+                    .chain(step.conditions.iter().filter_map(|c| match c {
+                        Condition::StepDone(id) => Some(id),
+                        _ => None,
+                    }));
+            for id in referenced {
+                if !steps.iter().any(|s| s.id.as_ref() == Some(id)) {
+                    return Err(BuildOrderError::UnknownStep {
+                        step: index + 1,
+                        id: id.clone(),
+                    });
+                }
+            }
+            steps.push(step);
+        }
+        let follow_up = file.follow_up.map(Self::parse_follow_up).transpose()?;
+        Ok(Self {
+            name: file.name,
+            wall_natural: file.wall_natural,
+            follow_up,
+            states: vec![StepState::Waiting; steps.len()],
+            steps,
+            condition_state: ConditionState::default(),
+        })
+    }
+
+    fn parse_step(step: usize, file: StepFile) -> Result<BuildStep, BuildOrderError> {
+        let command = match (file.unit, file.upgrade, file.amount) {
+            (Some(name), None, Some(amount)) => {
+                if amount == 0 {
+                    return Err(BuildOrderError::InvalidStep {
+                        step,
+                        reason: "amount must be at least 1",
+                    });
+                }
+                let unit_type =
+                    parse_id::<UnitTypeId>(&name).ok_or_else(|| BuildOrderError::UnknownUnit {
+                        step,
+                        name: name.clone(),
+                    })?;
+                if !unit_type.is_producible() {
+                    return Err(BuildOrderError::Unproducible { step, name });
+                }
+                Some(Command::new_unit(unit_type, amount, file.save_resources))
+            }
+            (Some(_), None, None) => {
+                return Err(BuildOrderError::InvalidStep {
+                    step,
+                    reason: "unit steps need an amount",
+                })
+            }
+            (None, Some(name), None) => {
+                let upgrade = parse_id::<UpgradeId>(&name).ok_or_else(|| {
+                    BuildOrderError::UnknownUpgrade {
+                        step,
+                        name: name.clone(),
+                    }
+                })?;
+                if !upgrade.is_producible() {
+                    return Err(BuildOrderError::Unproducible { step, name });
+                }
+                Some(Command::new_upgrade(upgrade, file.save_resources))
+            }
+            (None, Some(_), Some(_)) => {
+                return Err(BuildOrderError::InvalidStep {
+                    step,
+                    reason: "upgrade steps can't have an amount",
+                })
+            }
+            (None, None, None) if !file.cancels.is_empty() => None,
+            _ => {
+                return Err(BuildOrderError::InvalidStep {
+                    step,
+                    reason: "a step needs a unit, an upgrade or steps to cancel",
+                })
+            }
+        };
+        let mut conditions = vec![];
+        if let Some(supply) = file.when.supply {
+            conditions.push(Condition::Supply(supply));
+        }
+        if let Some(time) = file.when.time {
+            conditions.push(Condition::Time(time));
+        }
+        if let Some(time) = file.when.enemy_natural_missing {
+            conditions.push(Condition::EnemyNaturalMissing(time));
+        }
+        if let Some(name) = file.when.enemy_unit_seen {
+            let unit_type =
+                parse_id::<UnitTypeId>(&name).ok_or(BuildOrderError::UnknownUnit { step, name })?;
+            conditions.push(Condition::EnemyUnitSeen(unit_type));
+        }
+        if let Some(id) = file.when.after {
+            conditions.push(Condition::StepDone(id));
+        }
+        Ok(BuildStep {
+            id: file.id,
+            conditions,
+            command,
+            blocked: file.blocked,
+            priority: file.priority,
+            cancels: file.cancels,
+        })
+    }
+
+    fn parse_follow_up(file: FollowUpFile) -> Result<StrategyPlan, BuildOrderError> {
+        let unit_type = |name: &String| {
+            parse_id::<UnitTypeId>(name).ok_or_else(|| {
+                BuildOrderError::InvalidFollowUp(format!("unknown unit type `{}`", name))
+            })
+        };
+        let producible_unit = |name: &String| {
+            unit_type(name).and_then(|unit_type| {
+                if unit_type.is_producible() {
+                    Ok(unit_type)
+                } else {
+                    Err(BuildOrderError::InvalidFollowUp(format!(
+                        "the bot can't produce `{}`",
+                        name
+                    )))
+                }
+            })
+        };
+        let mut plan = StrategyPlan::new(&file.name, file.expires_at);
+        for (name, weight) in file.composition.iter() {
+            plan = plan.with_unit(producible_unit(name)?, *weight);
+        }
+        if let Some(supply) = file.timing_attack_supply {
+            plan = plan.with_timing_attack(supply);
+        }
+        for tech in file.tech.iter() {
+            let command = match (&tech.unit, &tech.upgrade) {
+                (Some(name), None) => {
+                    Command::new_unit(producible_unit(name)?, tech.amount.unwrap_or(1), true)
+                }
+                (None, Some(name)) if tech.amount.is_none() => Command::new_upgrade(
+                    parse_id::<UpgradeId>(name)
+                        .filter(|upgrade| upgrade.is_producible())
+                        .ok_or_else(|| {
+                            BuildOrderError::InvalidFollowUp(format!(
+                                "unknown or unproducible upgrade `{}`",
+                                name
+                            ))
+                        })?,
+                    true,
+                ),
+                _ => {
+                    return Err(BuildOrderError::InvalidFollowUp(
+                        "tech needs a unit (with optional amount) or an upgrade".to_string(),
+                    ))
+                }
+            };
+            plan = plan.with_tech(command);
+        }
+        if let Some(focus) = file.spending_focus {
+            plan = plan.with_spending_focus(match focus.as_str() {
+                "Economy" => SpendingFocus::Economy,
+                "Balance" => SpendingFocus::Balance,
+                "Army" => SpendingFocus::Army,
+                _ => {
+                    return Err(BuildOrderError::InvalidFollowUp(format!(
+                        "unknown spending focus `{}`",
+                        focus
+                    )))
+                }
+            });
+        }
+        for name in file.invalidated_by.iter() {
+            plan = plan.invalidated_by(unit_type(name)?);
+        }
+        Ok(plan)
+    }
+
+    fn finished_steps(&self, bot: &mut Bot) -> HashSet<String> {
+        let game = BotView::new(bot);
+        self.steps
+            .iter()
+            .zip(self.states.iter())
+            .filter(|(step, state)| {
+                **state == StepState::Issued
+                    && step.command.as_ref().map_or(true, |c| c.is_complete(&game))
+            })
+            .filter_map(|(step, _)| step.id.clone())
+            .collect()
+    }
+
+    fn issue_ready_steps(&mut self, bot: &mut Bot, bot_state: &mut BotState) {
+        let finished_steps = self.finished_steps(bot);
+        self.condition_state.update(bot, finished_steps);
+        for index in 0..self.steps.len() {
+            if self.states[index] != StepState::Waiting
+                || !self.steps[index]
+                    .conditions
+                    .iter()
+                    .all(|c| c.is_met(bot, bot_state, &self.condition_state))
+            {
+                continue;
+            }
+            let step = &self.steps[index];
+            for cancelled in step.cancels.iter() {
+                if let Some(position) = self
+                    .steps
+                    .iter()
+                    .position(|s| s.id.as_ref() == Some(cancelled))
+                {
+                    let cancelled_step = &self.steps[position];
+                    if let Some(command) = &cancelled_step.command {
+                        bot_state
+                            .build_queue
+                            .cancel(command, cancelled_step.priority);
+                    }
+                    debug!("Step {:?} cancelled", cancelled);
+                    self.states[position] = StepState::Cancelled;
+                }
+            }
+            if let Some(command) = &step.command {
+                bot_state
+                    .build_queue
+                    .push(command.clone(), step.blocked, step.priority);
+            }
+            self.states[index] = StepState::Issued;
+        }
+    }
+}
+
+// Names are matched against the enum variants, e.g. `SpawningPool` or `Zerglingmovementspeed`.
+fn parse_id<T: DeserializeOwned>(name: &str) -> Option<T> {
+    let deserializer: StrDeserializer<serde::de::value::Error> = name.into_deserializer();
+    T::deserialize(deserializer).ok()
+}
+
+impl Opening for BuildOrder {
+    fn opening(&mut self, bot: &mut Bot, bot_state: &mut BotState) {
+        bot_state.strategy.follow_up = self.follow_up.clone();
+        bot_state.strategy.wall_requested = self.wall_natural;
+        bot_state.strategy.update_natural_wall(bot.enemy_race);
+        self.issue_ready_steps(bot, bot_state);
+    }
+
+    fn on_step(&mut self, bot: &mut Bot, bot_state: &mut BotState) {
+        self.issue_ready_steps(bot, bot_state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_sc2::bot::Bot;
+    use rust_sc2::prelude::*;
+
+    use caninana_core::command_queue::Command;
+    use caninana_core::{BotState, SpendingFocus};
+
+    use crate::build_order::{BuildOrder, BuildOrderError};
+    use crate::condition::Condition;
+
+    #[test]
+    fn example_build_order_loads() {
+        let build_order =
+            BuildOrder::from_toml(include_str!("../builds/hatch_gas_pool.toml")).unwrap();
+        assert_eq!(build_order.name, "HatchGasPool");
+        assert_eq!(
+            build_order.steps[0].command,
+            Some(Command::new_unit(UnitTypeId::Drone, 13, true))
+        );
+        assert_eq!(build_order.steps[0].priority, 1000);
+        assert!(build_order.steps[0].blocked);
+        assert!(build_order.wall_natural);
+    }
+
+    #[test]
+    fn taken_names_skipped() {
+        let dir = std::env::temp_dir().join(format!(
+            "caninana_taken_names_skipped_{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let build = |name: &str| {
+            format!(
+                "name = \"{}\"\n[[steps]]\nunit = \"Drone\"\namount = 13\npriority = 10\n",
+                name
+            )
+        };
+        std::fs::write(dir.join("a.toml"), build("Dup")).unwrap();
+        std::fs::write(dir.join("b.toml"), build("Dup")).unwrap();
+        std::fs::write(dir.join("c.toml"), build("Pool14")).unwrap();
+        let build_orders = BuildOrder::load_dir(dir.to_str().unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            build_orders
+                .iter()
+                .map(|b| b.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["Dup"]
+        );
+        assert_eq!(
+            crate::opening_names(&build_orders),
+            vec!["Hatch17", "Pool14", "Pool16", "Dup"]
+        );
+    }
+
+    #[test]
+    fn json_upgrade_step() {
+        let build_order = BuildOrder::from_json(
+            r#"{"name": "Speed", "steps": [{"upgrade": "Zerglingmovementspeed", "priority": 10, "blocked": false}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            build_order.steps[0].command,
+            Some(Command::new_upgrade(UpgradeId::Zerglingmovementspeed, true))
+        );
+        assert!(!build_order.steps[0].blocked);
+    }
+
+    #[test]
+    fn unknown_unit_reported() {
+        let result = BuildOrder::from_toml(
+            r#"
+            name = "Broken"
+            [[steps]]
+            unit = "Drone"
+            amount = 13
+            priority = 10
+            [[steps]]
+            unit = "Dron"
+            amount = 14
+            priority = 9
+            "#,
+        );
+        assert!(matches!(
+            result,
+            Err(BuildOrderError::UnknownUnit { step: 2, ref name }) if name == "Dron"
+        ));
+    }
+
+    #[test]
+    fn unproducible_names_rejected() {
+        let result = BuildOrder::from_toml(
+            r#"
+            name = "Broken"
+            [[steps]]
+            unit = "Marine"
+            amount = 2
+            priority = 10
+            "#,
+        );
+        assert!(matches!(
+            result,
+            Err(BuildOrderError::Unproducible { step: 1, ref name }) if name == "Marine"
+        ));
+        let result = BuildOrder::from_json(
+            r#"{"name": "Broken", "steps": [{"upgrade": "Stimpack", "priority": 10}]}"#,
+        );
+        assert!(matches!(
+            result,
+            Err(BuildOrderError::Unproducible { step: 1, ref name }) if name == "Stimpack"
+        ));
+    }
+
+    #[test]
+    fn unit_without_amount_rejected() {
+        let result = BuildOrder::from_toml(
+            r#"
+            name = "Broken"
+            [[steps]]
+            unit = "Drone"
+            priority = 10
+            "#,
+        );
+        assert!(matches!(
+            result,
+            Err(BuildOrderError::InvalidStep { step: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn step_without_priority_rejected() {
+        let result = BuildOrder::from_toml(
+            r#"
+            name = "Broken"
+            [[steps]]
+            unit = "Drone"
+            amount = 13
+            "#,
+        );
+        assert!(matches!(result, Err(BuildOrderError::Parse(_))));
+    }
+
+    #[test]
+    fn conditional_steps() {
+        let build_order = BuildOrder::from_toml(
+            r#"
+            name = "Reactive"
+            [[steps]]
+            id = "hatch"
+            unit = "Hatchery"
+            amount = 2
+            priority = 10
+            [[steps]]
+            unit = "Zergling"
+            amount = 6
+            priority = 9
+            cancels = ["hatch"]
+            when = { enemy_natural_missing = 120.0, after = "hatch" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            build_order.steps[1].conditions,
+            vec![
+                Condition::EnemyNaturalMissing(120f32),
+                Condition::StepDone("hatch".to_string())
+            ]
+        );
+        assert_eq!(build_order.steps[1].cancels, vec!["hatch".to_string()]);
+    }
+
+    fn queued(bot_state: &BotState) -> Vec<Command> {
+        bot_state
+            .build_queue
+            .into_iter()
+            .map(|e| e.command)
+            .collect()
+    }
+
+    #[test]
+    fn conditional_steps_issued_and_cancelled() {
+        let mut build_order = BuildOrder::from_toml(
+            r#"
+            name = "Reactive"
+            [[steps]]
+            id = "hatch"
+            unit = "Hatchery"
+            amount = 2
+            priority = 10
+            [[steps]]
+            unit = "Zergling"
+            amount = 6
+            priority = 9
+            when = { time = 120.0 }
+            [[steps]]
+            cancels = ["hatch"]
+            priority = 8
+            when = { supply = 20 }
+            "#,
+        )
+        .unwrap();
+        let hatch = Command::new_unit(UnitTypeId::Hatchery, 2, true);
+        let lings = Command::new_unit(UnitTypeId::Zergling, 6, true);
+        let mut bot = Bot::default();
+        let mut bot_state = BotState::default();
+        build_order.issue_ready_steps(&mut bot, &mut bot_state);
+        assert_eq!(queued(&bot_state), vec![hatch.clone()]);
+
+        bot.time = 120f32;
+        build_order.issue_ready_steps(&mut bot, &mut bot_state);
+        assert_eq!(queued(&bot_state), vec![hatch, lings.clone()]);
+
+        bot.supply_used = 20;
+        build_order.issue_ready_steps(&mut bot, &mut bot_state);
+        build_order.issue_ready_steps(&mut bot, &mut bot_state);
+        assert_eq!(queued(&bot_state), vec![lings]);
+    }
+
+    #[test]
+    fn unknown_step_reference_rejected() {
+        let result = BuildOrder::from_toml(
+            r#"
+            name = "Broken"
+            [[steps]]
+            cancels = ["pool"]
+            priority = 10
+            "#,
+        );
+        assert!(matches!(
+            result,
+            Err(BuildOrderError::UnknownStep { step: 1, ref id }) if id == "pool"
+        ));
+    }
+
+    #[test]
+    fn follow_up_plan() {
+        let build_order = BuildOrder::from_toml(
+            r#"
+            name = "Roaches"
+            [follow_up]
+            name = "RoachTiming"
+            expires_at = 420.0
+            composition = { Roach = 10, Zergling = 1 }
+            timing_attack_supply = 40
+            tech = [{ unit = "RoachWarren" }, { upgrade = "GlialReconstitution" }]
+            spending_focus = "Army"
+            invalidated_by = ["Banshee"]
+            "#,
+        )
+        .unwrap();
+        let plan = build_order.follow_up.unwrap();
+        assert_eq!(plan.unit_weight(UnitTypeId::Roach), Some(10));
+        assert_eq!(plan.timing_attack_supply, Some(40));
+        assert_eq!(
+            plan.tech_path,
+            vec![
+                Command::new_unit(UnitTypeId::RoachWarren, 1, true),
+                Command::new_upgrade(UpgradeId::GlialReconstitution, true)
+            ]
+        );
+        assert_eq!(plan.spending_focus, Some(SpendingFocus::Army));
+        assert_eq!(plan.invalidated_by, vec![(UnitTypeId::Banshee, 1)]);
+    }
+}
@@ -0,0 +1,68 @@
+use std::collections::HashSet;
+
+use rust_sc2::bot::Bot;
+use rust_sc2::prelude::*;
+
+use caninana_core::BotState;
+
+// Seconds a sighting of the enemy natural counts as current
+const NATURAL_SEEN_RECENTLY: f32 = 20f32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    // Supply used reached the value
+    Supply(u32),
+    // Game time (in seconds) reached the value
+    Time(f32),
+    // The enemy natural was seen lately and still not taken at the given game time
+    EnemyNaturalMissing(f32),
+    // Enemy unit type was seen and is still remembered
+    EnemyUnitSeen(UnitTypeId),
+    // Another step was issued and its command completed
+    StepDone(String),
+}
+
+// What the build order learned in earlier steps
+#[derive(Debug, Clone, Default)]
+pub struct ConditionState {
+    pub finished_steps: HashSet<String>,
+    // Last game time the enemy natural was visible
+    pub natural_seen_at: Option<f32>,
+}
+
+impl ConditionState {
+    pub fn update(&mut self, bot: &Bot, finished_steps: HashSet<String>) {
+        self.finished_steps = finished_steps;
+        if Condition::enemy_natural(bot).map_or(false, |p| bot.is_visible(p)) {
+            self.natural_seen_at = Some(bot.time);
+        }
+    }
+}
+
+impl Condition {
+    pub fn is_met(&self, bot: &Bot, bot_state: &BotState, state: &ConditionState) -> bool {
+        match self {
+            Condition::Supply(supply) => bot.supply_used >= *supply,
+            Condition::Time(time) => bot.time >= *time,
+            Condition::EnemyNaturalMissing(time) => {
+                bot.time >= *time
+                    && bot.enemy_expansions().count() < 2
+                    && state
+                        .natural_seen_at
+                        .map_or(false, |seen| bot.time - seen <= NATURAL_SEEN_RECENTLY)
+            }
+            Condition::EnemyUnitSeen(unit_type) => {
+                !bot_state.enemy_cache.units.of_type(*unit_type).is_empty()
+            }
+            Condition::StepDone(id) => state.finished_steps.contains(id),
+        }
+    }
+
+    fn enemy_natural(bot: &Bot) -> Option<Point2> {
+        bot.expansions
+            .iter()
+            .map(|e| e.loc)
+            .filter(|loc| *loc != bot.enemy_start)
+            .closest(bot.enemy_start)
+    }
+}
@@ -1 +1,43 @@
+pub mod build_order;
+pub mod condition;
+pub mod selector;
 pub mod zerg;
+
+use caninana_core::Opening;
+
+use crate::build_order::BuildOrder;
+use crate::zerg::hatch17::Hatch17;
+use crate::zerg::pool14::Pool14;
+use crate::zerg::pool16::Pool16;
+
+pub const OPENINGS: [&str; 3] = ["Hatch17", "Pool14", "Pool16"];
+
+pub fn opening_by_name(name: &str) -> Option<Box<dyn Opening>> {
+    match name {
+        "Hatch17" => Some(Box::new(Hatch17::default())),
+        "Pool14" => Some(Box::new(Pool14::default())),
+        "Pool16" => Some(Box::new(Pool16::default())),
+        _ => None,
+    }
+}
+
+// Every name once, built-in openings win over build orders named like them
+pub fn opening_names(build_orders: &[BuildOrder]) -> Vec<&str> {
+    let mut names = OPENINGS.to_vec();
+    for build_order in build_orders.iter() {
+        if !names.contains(&build_order.name.as_str()) {
+            names.push(build_order.name.as_str());
+        }
+    }
+    names
+}
+
+pub fn find_opening(name: &str, build_orders: &[BuildOrder]) -> Option<Box<dyn Opening>> {
+    if let Some(opening) = opening_by_name(name) {
+        return Some(opening);
+    }
+    build_orders
+        .iter()
+        .find(|b| b.name == name)
+        .map(|b| Box::new(b.clone()) as Box<dyn Opening>)
+}
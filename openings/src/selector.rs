@@ -0,0 +1,151 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use log::{debug, warn};
+use rust_sc2::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameRecord {
+    pub opening: String,
+    pub result: GameResult,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct OpponentHistory {
+    pub games: Vec<GameRecord>,
+}
+
+impl OpponentHistory {
+    fn stats(&self, opening: &str) -> (usize, f32) {
+        self.games
+            .iter()
+            .filter(|g| g.opening == opening)
+            .fold((0, 0f32), |(games, reward), g| {
+                (games + 1, reward + Self::reward(&g.result))
+            })
+    }
+
+    fn reward(result: &GameResult) -> f32 {
+        match result {
+            GameResult::Victory => 1f32,
+            GameResult::Tie => 0.5f32,
+            _ => 0f32,
+        }
+    }
+}
+
+// Picks openings per opponent using UCB1, so every opening gets tried and the
+// ones winning more often get played more.
+pub struct OpeningSelector {
+    path: PathBuf,
+    history: OpponentHistory,
+}
+
+impl OpeningSelector {
+    const EXPLORATION: f32 = std::f32::consts::SQRT_2;
+
+    pub fn load(data_dir: &str, opponent: &str) -> Self {
+        let file_name = opponent
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+        let path = PathBuf::from(data_dir).join(format!("{}.json", file_name));
+        let history = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring broken history {:?}: {}", path, e);
+                OpponentHistory::default()
+            }),
+            Err(_) => OpponentHistory::default(),
+        };
+        Self { path, history }
+    }
+
+    pub fn select(&self, openings: &[&str]) -> String {
+        let total_games = openings
+            .iter()
+            .map(|o| self.history.stats(o).0)
+            .sum::<usize>();
+        let mut best = (openings[0], f32::MIN);
+        for opening in openings {
+            let (games, reward) = self.history.stats(opening);
+            if games == 0 {
+                debug!("Opening {} not tried yet", opening);
+                return opening.to_string();
+            }
+            let score = reward / games as f32
+                + Self::EXPLORATION * ((total_games as f32).ln() / games as f32).sqrt();
+            debug!(
+                "Opening {} [{}|{:.2}] = {:.3}",
+                opening, games, reward, score
+            );
+            if score > best.1 {
+                best = (*opening, score);
+            }
+        }
+        best.0.to_string()
+    }
+
+    pub fn record(&self, opening: &str, result: GameResult) -> io::Result<()> {
+        let mut games = self.history.games.clone();
+        games.push(GameRecord {
+            opening: opening.to_string(),
+            result,
+        });
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let history = OpponentHistory { games };
+        fs::write(&self.path, serde_json::to_string_pretty(&history)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_sc2::prelude::*;
+
+    use crate::selector::{GameRecord, OpeningSelector, OpponentHistory};
+
+    fn selector(games: Vec<(&str, GameResult)>) -> OpeningSelector {
+        OpeningSelector {
+            path: Default::default(),
+            history: OpponentHistory {
+                games: games
+                    .into_iter()
+                    .map(|(opening, result)| GameRecord {
+                        opening: opening.to_string(),
+                        result,
+                    })
+                    .collect(),
+            },
+        }
+    }
+
+    #[test]
+    fn untried_opening_first() {
+        let selector = selector(vec![("A", GameResult::Victory)]);
+        assert_eq!(selector.select(&["A", "B"]), "B");
+    }
+
+    #[test]
+    fn winning_opening_exploited() {
+        let selector = selector(vec![
+            ("A", GameResult::Victory),
+            ("A", GameResult::Victory),
+            ("A", GameResult::Victory),
+            ("A", GameResult::Victory),
+            ("B", GameResult::Defeat),
+            ("B", GameResult::Defeat),
+            ("B", GameResult::Defeat),
+            ("B", GameResult::Defeat),
+        ]);
+        assert_eq!(selector.select(&["A", "B"]), "A");
+    }
+}
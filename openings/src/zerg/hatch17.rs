@@ -2,7 +2,8 @@ use rust_sc2::bot::Bot;
 use rust_sc2::prelude::*;
 
 use caninana_core::command_queue::Command;
-use caninana_core::{BotState, Opening};
+use caninana_core::managers::strategy_manager::StrategyPlan;
+use caninana_core::{BotState, Opening, SpendingFocus};
 
 #[derive(Default)]
 pub struct Hatch17 {}
@@ -42,7 +43,18 @@ impl Hatch17 {
 }
 
 impl Opening for Hatch17 {
-    fn opening(&mut self, _: &Bot, bot_state: &mut BotState) {
+    fn opening(&mut self, bot: &mut Bot, bot_state: &mut BotState) {
         self.push_commands(bot_state);
+        bot_state.strategy.wall_requested = true;
+        bot_state.strategy.update_natural_wall(bot.enemy_race);
+        bot_state.strategy.follow_up = Some(
+            StrategyPlan::new("Macro", 240f32)
+                .with_unit(UnitTypeId::Zergling, 1)
+                .with_spending_focus(SpendingFocus::Economy)
+                // Scouting lings are expected in ZvZ, only a ling flood drops it
+                .invalidated_by_count(UnitTypeId::Zergling, 8)
+                .invalidated_by(UnitTypeId::Zealot)
+                .invalidated_by(UnitTypeId::Reaper),
+        );
     }
 }
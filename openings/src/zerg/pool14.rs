@@ -2,7 +2,8 @@ use rust_sc2::bot::Bot;
 use rust_sc2::prelude::*;
 
 use caninana_core::command_queue::Command;
-use caninana_core::{BotState, Opening};
+use caninana_core::managers::strategy_manager::StrategyPlan;
+use caninana_core::{BotState, Opening, SpendingFocus};
 
 #[derive(Default)]
 pub struct Pool14 {}
@@ -42,7 +43,18 @@ impl Pool14 {
 }
 
 impl Opening for Pool14 {
-    fn opening(&mut self, _: &Bot, bot_state: &mut BotState) {
+    fn opening(&mut self, _: &mut Bot, bot_state: &mut BotState) {
         self.push_commands(bot_state);
+        bot_state.strategy.follow_up = Some(
+            StrategyPlan::new("LingPressure", 300f32)
+                .with_unit(UnitTypeId::Zergling, 1)
+                .with_timing_attack(16)
+                .with_tech(Command::new_upgrade(UpgradeId::Zerglingmovementspeed, true))
+                .with_spending_focus(SpendingFocus::Army)
+                .invalidated_by(UnitTypeId::PhotonCannon)
+                .invalidated_by(UnitTypeId::Bunker)
+                .invalidated_by(UnitTypeId::SpineCrawler)
+                .invalidated_by(UnitTypeId::Baneling),
+        );
     }
 }
@@ -2,6 +2,7 @@ use rust_sc2::bot::Bot;
 use rust_sc2::prelude::*;
 
 use caninana_core::command_queue::Command;
+use caninana_core::managers::strategy_manager::StrategyPlan;
 use caninana_core::{BotState, Opening};
 
 #[derive(Default)]
@@ -48,7 +49,21 @@ impl Pool16 {
 }
 
 impl Opening for Pool16 {
-    fn opening(&mut self, _: &Bot, bot_state: &mut BotState) {
+    fn opening(&mut self, bot: &mut Bot, bot_state: &mut BotState) {
         self.push_commands(bot_state);
+        bot_state.strategy.wall_requested = true;
+        bot_state.strategy.update_natural_wall(bot.enemy_race);
+        bot_state.strategy.follow_up = Some(
+            StrategyPlan::new("RoachTiming", 420f32)
+                .with_unit(UnitTypeId::Roach, 10)
+                .with_unit(UnitTypeId::Zergling, 1)
+                .with_timing_attack(40)
+                .with_tech(Command::new_unit(UnitTypeId::RoachWarren, 1, true))
+                .with_tech(Command::new_upgrade(UpgradeId::GlialReconstitution, true))
+                .invalidated_by(UnitTypeId::Banshee)
+                .invalidated_by(UnitTypeId::Oracle)
+                .invalidated_by(UnitTypeId::VoidRay)
+                .invalidated_by(UnitTypeId::Mutalisk),
+        );
     }
 }